@@ -0,0 +1,238 @@
+﻿use std::path::{Path, PathBuf};
+use crate::classes::c_palette::Palette;
+use crate::classes::c_pipeline_settings::PipelineSettings;
+use crate::classes::c_rgb16::Rgb16;
+use crate::decode_utils::load_frames;
+use crate::export_utils::write_by_extension;
+use crate::classes::c_export_settings::ExportSettings;
+use crate::pipeline_utils::{prepare_frames, process_file, quantize_with_locked, sample_frames, MAX_SAMPLES};
+
+/// Extensions picked up when a batch input is a plain folder.
+pub const INPUT_EXTENSIONS: [&str; 7] = ["png", "apng", "gif", "jpg", "jpeg", "bmp", "tga"];
+
+pub const DEFAULT_NAME_PATTERN: &str = "{name}.png";
+
+/// `*` matches any run of characters, `?` exactly one. Case-insensitive.
+pub fn wildcard_match(pattern: &str, name: &str) -> bool {
+    let p: Vec<char> = pattern.to_lowercase().chars().collect();
+    let n: Vec<char> = name.to_lowercase().chars().collect();
+
+    let (mut pi, mut ni) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while ni < n.len() {
+        if pi < p.len() && (p[pi] == '?' || p[pi] == n[ni]) {
+            pi += 1;
+            ni += 1;
+        } else if pi < p.len() && p[pi] == '*' {
+            star = Some((pi, ni));
+            pi += 1;
+        } else if let Some((sp, sn)) = star {
+            pi = sp + 1;
+            ni = sn + 1;
+            star = Some((sp, sn + 1));
+        } else {
+            return false;
+        }
+    }
+    p[pi..].iter().all(|&c| c == '*')
+}
+
+fn has_input_extension(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .map(|e| INPUT_EXTENSIONS.contains(&e.to_ascii_lowercase().as_str()))
+        .unwrap_or(false)
+}
+
+/// Folder the inputs of `source` come from: the folder itself, or the one holding the
+/// file or wildcard ("sprites/*.png" gives "sprites"), even when nothing matches yet.
+pub fn source_dir(source: &str) -> PathBuf {
+    let path = Path::new(source);
+    if path.is_dir() {
+        return path.to_path_buf();
+    }
+    path.parent().filter(|p| !p.as_os_str().is_empty()).unwrap_or(Path::new(".")).to_path_buf()
+}
+
+/// Files for a batch: every image in a folder, or the files matching a wildcard
+/// in the last path component ("sprites/*.png"). Sorted by name.
+pub fn collect_inputs(source: &str) -> Result<Vec<PathBuf>, String> {
+    let files = list_inputs(source)?;
+    if files.is_empty() {
+        return Err(format!("{source}: no input images"));
+    }
+    Ok(files)
+}
+
+/// Same as [`collect_inputs`] but an empty folder is not an error.
+pub fn list_inputs(source: &str) -> Result<Vec<PathBuf>, String> {
+    let path = Path::new(source);
+
+    let (dir, pattern) = if path.is_dir() {
+        (path.to_path_buf(), None)
+    } else {
+        let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+        if !name.contains(['*', '?']) {
+            return if path.is_file() {
+                Ok(vec![path.to_path_buf()])
+            } else {
+                Err(format!("{source}: no such file or folder"))
+            };
+        }
+        (source_dir(source), Some(name.to_string()))
+    };
+
+    let entries = std::fs::read_dir(&dir).map_err(|e| format!("{}: {e}", dir.display()))?;
+    let mut files: Vec<PathBuf> = entries
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| p.is_file())
+        .filter(|p| match &pattern {
+            Some(pat) => p.file_name().and_then(|n| n.to_str()).map(|n| wildcard_match(pat, n)).unwrap_or(false),
+            None => has_input_extension(p),
+        })
+        .collect();
+    files.sort();
+    Ok(files)
+}
+
+/// Output path for the `index`-th input. The pattern knows `{name}` (input file stem),
+/// `{index}` (1-based, 4 digits) and `{ext}` (input extension); the result's extension
+/// picks the export format.
+pub fn output_path(output_dir: &Path, pattern: &str, input: &Path, index: usize) -> PathBuf {
+    let stem = input.file_stem().and_then(|s| s.to_str()).unwrap_or("image");
+    let ext = input.extension().and_then(|s| s.to_str()).unwrap_or("");
+    let name = pattern
+        .replace("{name}", stem)
+        .replace("{index}", &format!("{:04}", index + 1))
+        .replace("{ext}", ext);
+    output_dir.join(name)
+}
+
+/// Color samples of one input for a palette shared by the whole batch.
+pub fn sample_file(path: &Path, settings: &PipelineSettings) -> Result<Vec<Rgb16>, String> {
+    let decoded = load_frames(path, settings.load_sequences)?;
+    let (frames, _, _) = prepare_frames(decoded, settings);
+    Ok(sample_frames(&frames))
+}
+
+/// Quantizes the samples of all inputs into one palette.
+pub fn shared_palette(per_file: Vec<Vec<Rgb16>>, settings: &PipelineSettings) -> Palette {
+    let files = per_file.len().max(1);
+    let per_file_max = (MAX_SAMPLES / files).max(1);
+
+    let mut samples = Vec::with_capacity(MAX_SAMPLES);
+    for s in per_file {
+        let step = s.len().div_ceil(per_file_max).max(1);
+        samples.extend(s.into_iter().step_by(step));
+    }
+
+    quantize_with_locked(samples, settings, None)
+}
+
+/// Fails when `output` is the `input` file, also when spelled differently (`./a.png`, links).
+pub fn check_output_is_not_input(input: &Path, output: &Path) -> Result<(), String> {
+    // an output that doesn't exist yet can't be the input
+    let same = match (input.canonicalize(), output.canonicalize()) {
+        (Ok(input), Ok(output)) => input == output,
+        _ => input == output,
+    };
+    if same {
+        return Err(format!("{}: output would overwrite the input", output.display()));
+    }
+    Ok(())
+}
+
+/// Runs the pipeline on `input` and writes the result to `output`.
+pub fn convert_file(input: &Path, output: &Path, settings: &PipelineSettings, export: &ExportSettings, fixed: Option<Palette>) -> Result<(), String> {
+    check_output_is_not_input(input, output)?;
+    if let Some(dir) = output.parent()
+        && !dir.as_os_str().is_empty() {
+        std::fs::create_dir_all(dir).map_err(|e| format!("{}: {e}", dir.display()))?;
+    }
+
+    let processed = process_file(input, settings, fixed)?;
+    let (frames, delays) = processed.to_indexed();
+    write_by_extension(output, &frames, &delays, 0, export).map_err(|e| format!("{}: {e}", output.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wildcard_matches() {
+        let cases = [
+            ("*.png", "sprite.png", true),
+            ("*.png", "sprite.PNG", true),
+            ("*.PNG", "sprite.png", true),
+            ("*.png", "sprite.png.bak", false),
+            ("*.png", ".png", true),
+            ("sprite_??.png", "sprite_01.png", true),
+            ("sprite_??.png", "sprite_1.png", false),
+            ("sprite_??.png", "sprite_001.png", false),
+            ("*_*_*.gif", "a_b_c.gif", true),
+            ("*_*_*.gif", "a_bc.gif", false),
+            ("a*b*c", "abc", true),
+            ("a*b*c", "axxbxxbxxc", true),
+            ("a*b*c", "axxbxxcxxd", false),
+            ("*", "", true),
+            ("**", "anything", true),
+            ("", "", true),
+            ("", "a", false),
+            ("?", "", false),
+            ("?", "é", true),
+            ("Ä*", "äpfel", true),
+        ];
+        for (pattern, name, expected) in cases {
+            assert_eq!(wildcard_match(pattern, name), expected, "{pattern} vs {name}");
+        }
+    }
+
+    #[test]
+    fn output_path_fills_placeholders() {
+        let out = output_path(Path::new("out"), "{name}_{index}.{ext}.gif", Path::new("in/walk.png"), 6);
+        assert_eq!(out, Path::new("out").join("walk_0007.png.gif"));
+        let out = output_path(Path::new("out"), DEFAULT_NAME_PATTERN, Path::new("noext"), 0);
+        assert_eq!(out, Path::new("out").join("noext.png"));
+    }
+
+    #[test]
+    fn list_inputs_filters_folders_and_wildcards() {
+        let dir = std::env::temp_dir().join(format!("retroimager-{}-inputs", std::process::id()));
+        std::fs::create_dir_all(dir.join("sub.png")).unwrap();
+        for name in ["b.png", "a.GIF", "notes.txt", "c.jpg"] {
+            std::fs::write(dir.join(name), b"").unwrap();
+        }
+        let names = |source: &Path| -> Vec<String> {
+            list_inputs(source.to_str().unwrap()).unwrap()
+                .iter()
+                .map(|p| p.file_name().unwrap().to_string_lossy().into_owned())
+                .collect()
+        };
+
+        assert_eq!(names(&dir), ["a.GIF", "b.png", "c.jpg"]);
+        assert_eq!(names(&dir.join("*.png")), ["b.png"]);
+        assert_eq!(names(&dir.join("?.*")), ["a.GIF", "b.png", "c.jpg"]);
+        assert!(names(&dir.join("*.bmp")).is_empty());
+        assert!(collect_inputs(dir.join("*.bmp").to_str().unwrap()).is_err());
+        assert!(list_inputs(dir.join("missing.png").to_str().unwrap()).is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn output_may_not_be_the_input() {
+        let dir = std::env::temp_dir().join(format!("retroimager-{}-same-file", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("a.png");
+        std::fs::write(&input, b"").unwrap();
+
+        assert!(check_output_is_not_input(&input, &input).is_err());
+        let roundabout = dir.join("..").join(dir.file_name().unwrap()).join("a.png");
+        assert!(check_output_is_not_input(&input, &roundabout).is_err());
+        assert!(check_output_is_not_input(&input, &dir.join("b.png")).is_ok());
+        assert!(check_output_is_not_input(Path::new("missing.png"), Path::new("missing.png")).is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
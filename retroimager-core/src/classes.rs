@@ -0,0 +1,36 @@
+﻿pub mod c_rgb16;
+pub mod c_palette;
+pub mod c_image_buffer16;
+pub mod c_color_box;
+pub mod c_image_frame;
+pub mod c_indexed_image;
+pub mod c_processed_image;
+pub mod t_quantizer;
+pub mod c_median_cut;
+pub mod c_kmeans;
+pub mod c_quantizer_kind;
+pub mod t_ditherer;
+pub mod c_error_diffusion;
+pub mod c_ordered_bayer;
+pub mod c_nearest_color;
+pub mod c_dither_algorithm;
+pub mod c_color_metric;
+pub mod c_resize_filter;
+pub mod c_adjustments;
+pub mod c_palette_source;
+pub mod c_pipeline_settings;
+pub mod c_export_format;
+pub mod c_export_settings;
+pub mod c_preset;
+pub mod c_batch_job;
+pub mod c_batch_worker;
+pub mod c_folder_watcher;
+pub mod c_watch_worker;
+pub mod c_viewer_state;
+pub mod c_project;
+pub mod c_job_progress;
+pub mod c_pipeline_worker;
+pub mod c_palette_worker;
+pub mod c_palette_format;
+pub mod c_palette_sort;
+pub mod c_hardware_palette;
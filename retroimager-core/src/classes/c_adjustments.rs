@@ -0,0 +1,331 @@
+﻿use serde::{Deserialize, Serialize};
+use crate::classes::c_image_buffer16::ImageBuffer16;
+use crate::image_utils::{gaussian_blur_rgb16, median_filter_rgba16};
+
+/// Entries of the tone curve lookup table, one per 16-bit value.
+const CURVE_LUT_SIZE: usize = 65536;
+
+/// Largest unsharp mask blur radius.
+pub const MAX_SHARPEN_RADIUS: f32 = 5.0;
+/// Largest median denoise window radius.
+pub const MAX_DENOISE_RADIUS: u8 = 3;
+
+/// Tone and color corrections applied to the scaled source before quantizing, in this
+/// order: median denoise, unsharp mask, levels, brightness/contrast, gamma, curve,
+/// hue shift, saturation, posterize.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Adjustments {
+    /// -1..=1, added to every channel.
+    pub brightness: f32,
+    /// -1..=1, 0 keeps the image as is.
+    pub contrast: f32,
+    /// Output = input^(1/gamma), 1 keeps the image as is.
+    pub gamma: f32,
+    /// 0 = grayscale, 1 = unchanged, 2 = twice as saturated.
+    pub saturation: f32,
+    /// Hue rotation in degrees, -180..=180.
+    pub hue_shift: f32,
+    /// Input level (0..1) that becomes black.
+    pub levels_in_black: f32,
+    /// Input level (0..1) that becomes white.
+    pub levels_in_white: f32,
+    /// Output level (0..1) black is mapped to.
+    pub levels_out_black: f32,
+    /// Output level (0..1) white is mapped to.
+    pub levels_out_white: f32,
+    /// Tone curve points (input, output), both 0..1, joined by a monotone cubic.
+    /// Empty = no curve.
+    pub curve: Vec<[f32; 2]>,
+    /// Unsharp mask strength, 0 = off.
+    pub sharpen_amount: f32,
+    /// Blur radius (Gaussian sigma) of the unsharp mask in pixels.
+    pub sharpen_radius: f32,
+    /// Median denoise window radius in pixels, 0 = off.
+    pub denoise_radius: u8,
+    /// Levels per channel at the end, 0 or 1 = off.
+    pub posterize: u8,
+}
+
+impl Default for Adjustments {
+    fn default() -> Self {
+        Self {
+            brightness: 0.0,
+            contrast: 0.0,
+            gamma: 1.0,
+            saturation: 1.0,
+            hue_shift: 0.0,
+            levels_in_black: 0.0,
+            levels_in_white: 1.0,
+            levels_out_black: 0.0,
+            levels_out_white: 1.0,
+            curve: vec![],
+            sharpen_amount: 0.0,
+            sharpen_radius: 1.0,
+            denoise_radius: 0,
+            posterize: 0,
+        }
+    }
+}
+
+impl Adjustments {
+    pub fn is_identity(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Checks the ranges the editor allows; a hand-edited file could ask for a huge blur
+    /// kernel or values that aren't numbers.
+    pub fn validate(&self) -> Result<(), String> {
+        let check = |name: &str, v: f32, min: f32, max: f32| {
+            if (min..=max).contains(&v) {
+                Ok(())
+            } else {
+                Err(format!("adjustments.{name} must be {min}..={max}, got {v}"))
+            }
+        };
+        check("brightness", self.brightness, -1.0, 1.0)?;
+        check("contrast", self.contrast, -1.0, 1.0)?;
+        check("gamma", self.gamma, 0.2, 5.0)?;
+        check("saturation", self.saturation, 0.0, 2.0)?;
+        check("hue_shift", self.hue_shift, -180.0, 180.0)?;
+        check("levels_in_black", self.levels_in_black, 0.0, 1.0)?;
+        check("levels_in_white", self.levels_in_white, 0.0, 1.0)?;
+        check("levels_out_black", self.levels_out_black, 0.0, 1.0)?;
+        check("levels_out_white", self.levels_out_white, 0.0, 1.0)?;
+        check("sharpen_amount", self.sharpen_amount, 0.0, 3.0)?;
+        check("sharpen_radius", self.sharpen_radius, 0.3, MAX_SHARPEN_RADIUS)?;
+        if self.denoise_radius > MAX_DENOISE_RADIUS {
+            return Err(format!("adjustments.denoise_radius must be 0..={MAX_DENOISE_RADIUS}, got {}", self.denoise_radius));
+        }
+        for &[x, y] in &self.curve {
+            check("curve", x, 0.0, 1.0)?;
+            check("curve", y, 0.0, 1.0)?;
+        }
+        Ok(())
+    }
+
+    /// Applies all adjustments in place, alpha is kept.
+    pub fn apply(&self, img: &mut ImageBuffer16) {
+        if self.is_identity() {
+            return;
+        }
+
+        median_filter_rgba16(&mut img.data, img.w, img.h, self.denoise_radius as usize);
+        let blurred = if self.sharpen_amount > 0.0 {
+            Some(gaussian_blur_rgb16(&img.data, img.w, img.h, self.sharpen_radius.max(0.1)))
+        } else {
+            None
+        };
+
+        let contrast = (1.0 + self.contrast.clamp(-1.0, 1.0)).powi(2);
+        let inv_gamma = 1.0 / self.gamma.max(0.01);
+        let in_range = (self.levels_in_white - self.levels_in_black).max(1e-4);
+        let out_range = self.levels_out_white - self.levels_out_black;
+        let curve = self.curve_lut();
+        let hue = (self.hue_shift != 0.0).then(|| hue_rotation(self.hue_shift));
+        let posterize = self.posterize >= 2;
+        let steps = self.posterize as f32 - 1.0;
+
+        for (i, px) in img.data.chunks_exact_mut(4).enumerate() {
+            let mut rgb = [px[0] as f32 / 65535.0, px[1] as f32 / 65535.0, px[2] as f32 / 65535.0];
+
+            for (k, c) in rgb.iter_mut().enumerate() {
+                if let Some(blurred) = &blurred {
+                    *c += (*c - blurred[i * 3 + k]) * self.sharpen_amount;
+                }
+                *c = ((*c - self.levels_in_black) / in_range).clamp(0.0, 1.0) * out_range + self.levels_out_black;
+                *c = (*c + self.brightness - 0.5) * contrast + 0.5;
+                *c = c.clamp(0.0, 1.0).powf(inv_gamma);
+                if let Some(lut) = &curve {
+                    *c = lut[(*c * (CURVE_LUT_SIZE - 1) as f32).round() as usize];
+                }
+            }
+
+            if let Some(m) = &hue {
+                rgb = [
+                    m[0][0] * rgb[0] + m[0][1] * rgb[1] + m[0][2] * rgb[2],
+                    m[1][0] * rgb[0] + m[1][1] * rgb[1] + m[1][2] * rgb[2],
+                    m[2][0] * rgb[0] + m[2][1] * rgb[1] + m[2][2] * rgb[2],
+                ];
+            }
+
+            let luma = 0.299 * rgb[0] + 0.587 * rgb[1] + 0.114 * rgb[2];
+            for (k, c) in rgb.iter().enumerate() {
+                let mut v = (luma + (c - luma) * self.saturation).clamp(0.0, 1.0);
+                if posterize {
+                    v = (v * steps).round() / steps;
+                }
+                px[k] = (v * 65535.0).round() as u16;
+            }
+        }
+    }
+
+    /// The curve sampled at every 16-bit input value, None without curve points.
+    fn curve_lut(&self) -> Option<Vec<f32>> {
+        if self.curve.is_empty() {
+            return None;
+        }
+        Some(Self::sample_curve(&self.curve, CURVE_LUT_SIZE))
+    }
+
+    /// Outputs of the curve through `points` at `n` evenly spaced inputs from 0 to 1
+    /// (`n` >= 2). Without points the curve is the identity.
+    pub fn sample_curve(points: &[[f32; 2]], n: usize) -> Vec<f32> {
+        if points.is_empty() {
+            return (0..n).map(|i| i as f32 / (n - 1) as f32).collect();
+        }
+        let mut points = points.to_vec();
+        points.sort_by(|a, b| a[0].total_cmp(&b[0]));
+        let slopes = monotone_slopes(&points);
+
+        let mut k = 0;
+        (0..n)
+            .map(|i| {
+                let x = i as f32 / (n - 1) as f32;
+                let last = points.len() - 1;
+                if x <= points[0][0] {
+                    return points[0][1].clamp(0.0, 1.0);
+                }
+                if x >= points[last][0] {
+                    return points[last][1].clamp(0.0, 1.0);
+                }
+                while points[k + 1][0] < x {
+                    k += 1;
+                }
+                let ([x0, y0], [x1, y1]) = (points[k], points[k + 1]);
+                let h = x1 - x0;
+                if h <= 0.0 {
+                    return y1.clamp(0.0, 1.0);
+                }
+                let t = (x - x0) / h;
+                let (t2, t3) = (t * t, t * t * t);
+                let y = (2.0 * t3 - 3.0 * t2 + 1.0) * y0
+                    + (t3 - 2.0 * t2 + t) * h * slopes[k]
+                    + (-2.0 * t3 + 3.0 * t2) * y1
+                    + (t3 - t2) * h * slopes[k + 1];
+                y.clamp(0.0, 1.0)
+            })
+            .collect()
+    }
+}
+
+/// Tangents at `points` (sorted by x) for a monotone cubic Hermite curve (Fritsch–Carlson).
+fn monotone_slopes(points: &[[f32; 2]]) -> Vec<f32> {
+    let n = points.len();
+    if n < 2 {
+        return vec![0.0; n];
+    }
+    let secants: Vec<f32> = points
+        .windows(2)
+        .map(|p| {
+            let dx = p[1][0] - p[0][0];
+            if dx > 0.0 { (p[1][1] - p[0][1]) / dx } else { 0.0 }
+        })
+        .collect();
+
+    let mut m = vec![0.0; n];
+    m[0] = secants[0];
+    m[n - 1] = secants[n - 2];
+    for k in 1..n - 1 {
+        m[k] = if secants[k - 1] * secants[k] <= 0.0 { 0.0 } else { (secants[k - 1] + secants[k]) / 2.0 };
+    }
+    for (k, &d) in secants.iter().enumerate() {
+        if d == 0.0 {
+            m[k] = 0.0;
+            m[k + 1] = 0.0;
+            continue;
+        }
+        let (a, b) = (m[k] / d, m[k + 1] / d);
+        let s = a * a + b * b;
+        if s > 9.0 {
+            let t = 3.0 / s.sqrt();
+            m[k] = t * a * d;
+            m[k + 1] = t * b * d;
+        }
+    }
+    m
+}
+
+/// Matrix rotating colors around the gray axis by `degrees`, keeping luma about the same.
+fn hue_rotation(degrees: f32) -> [[f32; 3]; 3] {
+    let (sin, cos) = degrees.to_radians().sin_cos();
+    [
+        [0.213 + cos * 0.787 - sin * 0.213, 0.715 - cos * 0.715 - sin * 0.715, 0.072 - cos * 0.072 + sin * 0.928],
+        [0.213 - cos * 0.213 + sin * 0.143, 0.715 + cos * 0.285 + sin * 0.140, 0.072 - cos * 0.072 - sin * 0.283],
+        [0.213 - cos * 0.213 - sin * 0.787, 0.715 - cos * 0.715 + sin * 0.715, 0.072 + cos * 0.928 + sin * 0.072],
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ramp(w: usize, h: usize) -> ImageBuffer16 {
+        let rgba8: Vec<u8> = (0..w * h).flat_map(|i| [(i % 256) as u8, (i * 7 % 256) as u8, (i * 13 % 256) as u8, 255]).collect();
+        ImageBuffer16::from_rgba8(w, h, &rgba8)
+    }
+
+    fn applied(a: &Adjustments, img: &ImageBuffer16) -> ImageBuffer16 {
+        let mut out = img.clone();
+        a.apply(&mut out);
+        out
+    }
+
+    #[test]
+    fn defaults_and_identity_curve_keep_the_image() {
+        let img = ramp(16, 16);
+        assert_eq!(applied(&Adjustments::default(), &img).data, img.data);
+        let curve = Adjustments { curve: vec![[0.0, 0.0], [1.0, 1.0]], ..Default::default() };
+        assert_eq!(applied(&curve, &img).data, img.data);
+    }
+
+    #[test]
+    fn posterize_below_two_is_off() {
+        let img = ramp(16, 16);
+        assert_eq!(applied(&Adjustments { posterize: 1, ..Default::default() }, &img).data, img.data);
+
+        let out = applied(&Adjustments { posterize: 4, ..Default::default() }, &img);
+        let levels: std::collections::HashSet<u16> = out.data.chunks_exact(4).map(|p| p[0]).collect();
+        assert_eq!(levels.len(), 4);
+    }
+
+    #[test]
+    fn full_hue_turn_keeps_colors() {
+        let img = ramp(16, 16);
+        let out = applied(&Adjustments { hue_shift: 360.0, ..Default::default() }, &img);
+        assert!(out.data.iter().zip(&img.data).all(|(a, b)| a.abs_diff(*b) <= 2));
+    }
+
+    #[test]
+    fn levels_stretch_input_range() {
+        let img = ImageBuffer16::from_rgba8(2, 1, &[64, 64, 64, 255, 192, 192, 192, 255]);
+        let a = Adjustments { levels_in_black: 64.0 / 255.0, levels_in_white: 192.0 / 255.0, ..Default::default() };
+        let out = applied(&a, &img);
+        assert_eq!(&out.data[0..3], &[0, 0, 0]);
+        assert_eq!(&out.data[4..7], &[65535, 65535, 65535]);
+    }
+
+    #[test]
+    fn median_removes_a_single_speckle() {
+        let mut rgba8 = vec![100u8; 5 * 5 * 4];
+        rgba8[(2 * 5 + 2) * 4] = 255;
+        let img = ImageBuffer16::from_rgba8(5, 5, &rgba8);
+        let out = applied(&Adjustments { denoise_radius: 1, ..Default::default() }, &img);
+        assert!(out.data.chunks_exact(4).all(|p| p[0] == 100 * 257));
+    }
+
+    #[test]
+    fn sharpening_a_flat_image_changes_nothing() {
+        let img = ImageBuffer16::from_rgba8(4, 4, &[80u8; 4 * 4 * 4]);
+        let out = applied(&Adjustments { sharpen_amount: 2.0, sharpen_radius: 1.5, ..Default::default() }, &img);
+        assert!(out.data.iter().zip(&img.data).all(|(a, b)| a.abs_diff(*b) <= 1));
+    }
+
+    #[test]
+    fn curve_is_monotone_between_monotone_points() {
+        let samples = Adjustments::sample_curve(&[[0.0, 0.0], [0.3, 0.1], [0.6, 0.9], [1.0, 1.0]], 256);
+        assert!(samples.windows(2).all(|w| w[1] >= w[0]));
+        assert_eq!(samples[0], 0.0);
+        assert_eq!(samples[255], 1.0);
+    }
+}
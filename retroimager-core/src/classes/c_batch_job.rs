@@ -0,0 +1,143 @@
+﻿use std::path::{Path, PathBuf};
+use crate::batch_utils::{convert_file, output_path, sample_file, shared_palette};
+use crate::classes::c_export_settings::ExportSettings;
+use crate::classes::c_palette::Palette;
+use crate::classes::c_palette_source::PaletteSource;
+use crate::classes::c_pipeline_settings::PipelineSettings;
+use crate::classes::c_rgb16::Rgb16;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum BatchStatus {
+    Pending,
+    /// Sampled for the shared palette, not converted yet.
+    Sampled,
+    Done,
+    Failed(String),
+}
+
+#[derive(Clone, Debug)]
+pub struct BatchItem {
+    pub input: PathBuf,
+    pub output: PathBuf,
+    pub status: BatchStatus,
+}
+
+/// A folder conversion that advances one file per [`BatchJob::step`], so the GUI can
+/// draw progress between files and the command line can print it.
+pub struct BatchJob {
+    pub items: Vec<BatchItem>,
+    settings: PipelineSettings,
+    export: ExportSettings,
+    palette: Option<Palette>,
+    /// Build one palette from all inputs before converting any of them.
+    share_palette: bool,
+    samples: Vec<Vec<Rgb16>>,
+    cursor: usize,
+}
+
+impl BatchJob {
+    /// `fixed` (or a fixed `settings.palette`) overrides both the per-file and the shared palette.
+    /// Numbered sequences are not merged: every file in the batch is its own image.
+    pub fn new(
+        inputs: Vec<PathBuf>,
+        output_dir: &Path,
+        name_pattern: &str,
+        settings: &PipelineSettings,
+        export: &ExportSettings,
+        fixed: Option<Palette>,
+        share_palette: bool,
+    ) -> Self {
+        let items = inputs
+            .into_iter()
+            .enumerate()
+            .map(|(i, input)| BatchItem {
+                output: output_path(output_dir, name_pattern, &input, i),
+                input,
+                status: BatchStatus::Pending,
+            })
+            .collect();
+
+        Self {
+            items,
+            settings: PipelineSettings { load_sequences: false, ..settings.clone() },
+            export: export.clone(),
+            share_palette: share_palette && fixed.is_none() && settings.palette == PaletteSource::Extract,
+            palette: fixed,
+            samples: vec![],
+            cursor: 0,
+        }
+    }
+
+    fn sampling(&self) -> bool {
+        self.share_palette && self.palette.is_none()
+    }
+
+    pub fn is_done(&self) -> bool {
+        !self.sampling() && self.cursor >= self.items.len()
+    }
+
+    /// Files finished (either way) and the total. The sampling pass counts as half the work.
+    pub fn progress(&self) -> (usize, usize) {
+        let total = self.items.len();
+        if !self.share_palette {
+            return (self.cursor, total);
+        }
+        if self.sampling() {
+            (self.cursor / 2, total)
+        } else {
+            ((total + self.cursor) / 2, total)
+        }
+    }
+
+    pub fn failed(&self) -> impl Iterator<Item = &BatchItem> {
+        self.items.iter().filter(|i| matches!(i.status, BatchStatus::Failed(_)))
+    }
+
+    /// Processes the next file. Returns the index of the item that changed, `None` when done.
+    pub fn step(&mut self) -> Option<usize> {
+        if self.sampling() {
+            if self.cursor >= self.items.len() {
+                let samples = std::mem::take(&mut self.samples);
+                self.palette = Some(shared_palette(samples, &self.settings));
+                self.cursor = 0;
+                return self.step();
+            }
+
+            let id = self.cursor;
+            self.cursor += 1;
+            let item = &mut self.items[id];
+            item.status = match sample_file(&item.input, &self.settings) {
+                Ok(s) => {
+                    self.samples.push(s);
+                    BatchStatus::Sampled
+                }
+                Err(e) => BatchStatus::Failed(e),
+            };
+            return Some(id);
+        }
+
+        // skip inputs that already failed while sampling
+        while self.cursor < self.items.len() && matches!(self.items[self.cursor].status, BatchStatus::Failed(_)) {
+            self.cursor += 1;
+        }
+        if self.cursor >= self.items.len() {
+            return None;
+        }
+
+        let id = self.cursor;
+        self.cursor += 1;
+        let item = &mut self.items[id];
+        item.status = match convert_file(&item.input, &item.output, &self.settings, &self.export, self.palette.clone()) {
+            Ok(()) => BatchStatus::Done,
+            Err(e) => BatchStatus::Failed(e),
+        };
+        Some(id)
+    }
+
+    /// Runs the whole job, calling `on_item` after every processed file.
+    pub fn run(&mut self, mut on_item: impl FnMut(&BatchItem)) {
+        while let Some(id) = self.step() {
+            on_item(&self.items[id]);
+        }
+    }
+}
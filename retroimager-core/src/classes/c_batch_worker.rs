@@ -0,0 +1,146 @@
+﻿use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, TryRecvError};
+use crate::classes::c_batch_job::{BatchItem, BatchJob, BatchStatus};
+
+/// One processed file and the job's progress after it.
+struct BatchUpdate {
+    item: Option<(usize, BatchItem)>,
+    progress: (usize, usize),
+}
+
+/// Runs a [`BatchJob`] on a background thread. [`BatchWorker::poll`] takes over the items
+/// finished since the last call, so a GUI can draw the list without doing the work.
+pub struct BatchWorker {
+    pub items: Vec<BatchItem>,
+    progress: (usize, usize),
+    done: bool,
+    updates: Receiver<BatchUpdate>,
+    cancel: Arc<AtomicBool>,
+}
+
+impl BatchWorker {
+    /// Starts `job`. `notify` is called from the worker thread after every file.
+    pub fn new(mut job: BatchJob, notify: impl Fn() + Send + 'static) -> Self {
+        let (tx, updates) = channel::<BatchUpdate>();
+        let items = job.items.clone();
+        let progress = job.progress();
+        let cancel = Arc::new(AtomicBool::new(false));
+        let cancelled = cancel.clone();
+
+        // not joined: a file can't be stopped halfway, so a cancelled thread finishes the
+        // one it is on in the background instead of blocking the caller
+        std::thread::spawn(move || {
+            while !cancelled.load(Ordering::Relaxed)
+                && let Some(id) = job.step() {
+                if tx.send(BatchUpdate { item: Some((id, job.items[id].clone())), progress: job.progress() }).is_err() {
+                    return;
+                }
+                notify();
+            }
+            let _ = tx.send(BatchUpdate { item: None, progress: job.progress() });
+            notify();
+        });
+
+        Self { items, progress, done: false, updates, cancel }
+    }
+
+    /// Applies the updates sent since the last call. True when anything changed.
+    pub fn poll(&mut self) -> bool {
+        let mut changed = false;
+        loop {
+            match self.updates.try_recv() {
+                Ok(update) => {
+                    if let Some((id, item)) = update.item {
+                        self.items[id] = item;
+                    }
+                    self.progress = update.progress;
+                    changed = true;
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    changed |= !self.done;
+                    self.done = true;
+                    break;
+                }
+            }
+        }
+        changed
+    }
+
+    /// True once the job finished or was cancelled and every update was polled.
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+
+    /// Files finished (either way) and the total, as of the last [`BatchWorker::poll`].
+    pub fn progress(&self) -> (usize, usize) {
+        self.progress
+    }
+
+    pub fn failed(&self) -> impl Iterator<Item = &BatchItem> {
+        self.items.iter().filter(|i| matches!(i.status, BatchStatus::Failed(_)))
+    }
+
+    /// Stops after the file being converted.
+    pub fn cancel(&self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+}
+
+impl Drop for BatchWorker {
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use std::time::{Duration, Instant};
+    use crate::classes::c_export_settings::ExportSettings;
+    use crate::classes::c_pipeline_settings::PipelineSettings;
+
+    fn run(worker: &mut BatchWorker) {
+        let start = Instant::now();
+        while !worker.is_done() {
+            worker.poll();
+            assert!(start.elapsed() < Duration::from_secs(30), "batch did not finish");
+            std::thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    #[test]
+    fn reports_every_file_and_finishes() {
+        let dir = std::env::temp_dir().join(format!("retroimager-{}-batch-worker", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let good = dir.join("good.png");
+        image::RgbaImage::from_pixel(3, 2, image::Rgba([10, 200, 30, 255])).save(&good).unwrap();
+        let bad = dir.join("bad.png");
+        std::fs::write(&bad, b"not a png").unwrap();
+
+        for share in [false, true] {
+            let inputs: Vec<PathBuf> = vec![bad.clone(), good.clone()];
+            let job = BatchJob::new(inputs, &dir.join("out"), "{name}.png", &PipelineSettings::default(), &ExportSettings::default(), None, share);
+            let mut worker = BatchWorker::new(job, || {});
+            run(&mut worker);
+
+            assert_eq!(worker.progress(), (2, 2));
+            assert!(matches!(worker.items[0].status, BatchStatus::Failed(_)));
+            assert_eq!(worker.items[1].status, BatchStatus::Done);
+            assert_eq!(worker.failed().count(), 1);
+            assert!(dir.join("out/good.png").is_file());
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn cancelled_worker_stops() {
+        let job = BatchJob::new(vec![], std::path::Path::new("out"), "{name}.png", &PipelineSettings::default(), &ExportSettings::default(), None, false);
+        let mut worker = BatchWorker::new(job, || {});
+        worker.cancel();
+        run(&mut worker);
+        assert!(worker.items.is_empty());
+    }
+}
@@ -0,0 +1,72 @@
+﻿use serde::{Deserialize, Serialize};
+use crate::classes::c_rgb16::Rgb16;
+
+/// How "nearest palette color" is measured when pixels are mapped to the palette.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ColorMetric {
+    /// Squared RGB distance.
+    #[default]
+    Rgb,
+    /// "Redmean" weighted RGB, cheap approximation of perceived difference.
+    Redmean,
+    /// Channels weighted by their luma contribution (0.299, 0.587, 0.114).
+    Luma,
+}
+
+impl ColorMetric {
+    pub const ALL: [ColorMetric; 3] = [ColorMetric::Rgb, ColorMetric::Redmean, ColorMetric::Luma];
+
+    pub fn distance(&self, a: Rgb16, b: Rgb16) -> u64 {
+        let dr = (a.r as i64 - b.r as i64).pow(2);
+        let dg = (a.g as i64 - b.g as i64).pow(2);
+        let db = (a.b as i64 - b.b as i64).pow(2);
+        match self {
+            ColorMetric::Rgb => (dr + dg + db) as u64,
+            ColorMetric::Redmean => {
+                let rmean = (a.r as i64 + b.r as i64) / 2;
+                ((2 * 65535 + rmean) * dr + 4 * 65535 * dg + (3 * 65535 - rmean) * db) as u64
+            }
+            ColorMetric::Luma => (299 * dr + 587 * dg + 114 * db) as u64,
+        }
+    }
+
+    /// Index of the closest entry of `palette`, 0 when it's empty.
+    pub fn nearest_index(&self, c: Rgb16, palette: &[Rgb16]) -> usize {
+        let mut best = 0;
+        let mut best_d = u64::MAX;
+        for (i, &p) in palette.iter().enumerate() {
+            let d = self.distance(c, p);
+            if d < best_d {
+                best_d = d;
+                best = i;
+            }
+        }
+        best
+    }
+
+    pub fn nearest(&self, c: Rgb16, palette: &[Rgb16]) -> Rgb16 {
+        palette.get(self.nearest_index(c, palette)).copied().unwrap_or_default()
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ColorMetric::Rgb => "RGB",
+            ColorMetric::Redmean => "Weighted RGB (redmean)",
+            ColorMetric::Luma => "Luma weighted",
+        }
+    }
+
+    /// Name used on the command line.
+    pub fn cli_name(&self) -> &'static str {
+        match self {
+            ColorMetric::Rgb => "rgb",
+            ColorMetric::Redmean => "redmean",
+            ColorMetric::Luma => "luma",
+        }
+    }
+
+    pub fn from_cli_name(name: &str) -> Option<ColorMetric> {
+        Self::ALL.into_iter().find(|m| m.cli_name().eq_ignore_ascii_case(name))
+    }
+}
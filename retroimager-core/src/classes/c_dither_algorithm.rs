@@ -0,0 +1,60 @@
+﻿use serde::{Deserialize, Serialize};
+use crate::classes::c_color_metric::ColorMetric;
+use crate::classes::c_error_diffusion::ErrorDiffusion;
+use crate::classes::c_nearest_color::NearestColor;
+use crate::classes::c_ordered_bayer::OrderedBayer8;
+use crate::classes::t_ditherer::Ditherer;
+
+/// Serializable choice of the built-in ditherers.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum DitherAlgorithm {
+    #[default]
+    FloydSteinberg,
+    Atkinson,
+    Bayer8,
+    /// Nearest palette color, no dithering.
+    None,
+}
+
+impl DitherAlgorithm {
+    pub const ALL: [DitherAlgorithm; 4] = [
+        DitherAlgorithm::FloydSteinberg,
+        DitherAlgorithm::Atkinson,
+        DitherAlgorithm::Bayer8,
+        DitherAlgorithm::None,
+    ];
+
+    /// `strength` (0..=1) is ignored by `None`.
+    pub fn ditherer(&self, metric: ColorMetric, strength: f32) -> Box<dyn Ditherer> {
+        match self {
+            DitherAlgorithm::FloydSteinberg => Box::new(ErrorDiffusion { metric, strength, ..ErrorDiffusion::floyd_steinberg() }),
+            DitherAlgorithm::Atkinson => Box::new(ErrorDiffusion { metric, strength, ..ErrorDiffusion::atkinson() }),
+            DitherAlgorithm::Bayer8 => Box::new(OrderedBayer8 { metric, strength }),
+            DitherAlgorithm::None => Box::new(NearestColor { metric }),
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            DitherAlgorithm::FloydSteinberg => "Floyd–Steinberg",
+            DitherAlgorithm::Atkinson => "Atkinson",
+            DitherAlgorithm::Bayer8 => "Ordered (Bayer 8x8)",
+            DitherAlgorithm::None => "None",
+        }
+    }
+
+    /// Name used on the command line.
+    pub fn cli_name(&self) -> &'static str {
+        match self {
+            DitherAlgorithm::FloydSteinberg => "fs",
+            DitherAlgorithm::Atkinson => "atkinson",
+            DitherAlgorithm::Bayer8 => "bayer8",
+            DitherAlgorithm::None => "none",
+        }
+    }
+
+    pub fn from_cli_name(name: &str) -> Option<DitherAlgorithm> {
+        Self::ALL.into_iter().find(|d| d.cli_name().eq_ignore_ascii_case(name))
+    }
+}
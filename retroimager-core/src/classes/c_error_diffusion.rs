@@ -0,0 +1,35 @@
+﻿use crate::classes::c_color_metric::ColorMetric;
+use crate::classes::c_image_buffer16::ImageBuffer16;
+use crate::classes::c_palette::Palette;
+use crate::classes::t_ditherer::Ditherer;
+use crate::image_utils::{dither_diffusion_palette_rgba16_to_rgba8, DiffusionKernel, ATKINSON, FLOYD_STEINBERG};
+
+/// Error diffusion with any kernel; supports temporal coherence.
+#[derive(Clone, Copy)]
+pub struct ErrorDiffusion {
+    pub kernel: &'static DiffusionKernel,
+    pub metric: ColorMetric,
+    /// 0..=1, share of the error that is diffused.
+    pub strength: f32,
+}
+
+impl ErrorDiffusion {
+    pub fn new(kernel: &'static DiffusionKernel) -> Self {
+        Self { kernel, metric: ColorMetric::Rgb, strength: 1.0 }
+    }
+
+    pub fn floyd_steinberg() -> Self {
+        Self::new(&FLOYD_STEINBERG)
+    }
+
+    pub fn atkinson() -> Self {
+        Self::new(&ATKINSON)
+    }
+}
+
+impl Ditherer for ErrorDiffusion {
+    fn dither(&self, src: &ImageBuffer16, palette: &Palette, prev: Option<(&ImageBuffer16, &[u8])>, dst8: &mut Vec<u8>, indices: &mut Vec<u8>) {
+        let prev = prev.map(|(s, o)| (s.data.as_slice(), o));
+        dither_diffusion_palette_rgba16_to_rgba8(self.kernel, self.strength, self.metric, &src.data, prev, dst8, indices, src.w, src.h, palette);
+    }
+}
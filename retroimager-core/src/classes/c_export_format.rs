@@ -0,0 +1,31 @@
+﻿use serde::{Deserialize, Serialize};
+
+/// Preferred output format. Explicit output paths still pick the format by extension.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportFormat {
+    #[default]
+    Png,
+    Gif,
+    Ilbm,
+}
+
+impl ExportFormat {
+    pub const ALL: [ExportFormat; 3] = [ExportFormat::Png, ExportFormat::Gif, ExportFormat::Ilbm];
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Png => "png",
+            ExportFormat::Gif => "gif",
+            ExportFormat::Ilbm => "iff",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ExportFormat::Png => "PNG (indexed)",
+            ExportFormat::Gif => "GIF",
+            ExportFormat::Ilbm => "IFF ILBM",
+        }
+    }
+}
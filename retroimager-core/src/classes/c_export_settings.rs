@@ -0,0 +1,24 @@
+﻿use serde::{Deserialize, Serialize};
+use crate::classes::c_export_format::ExportFormat;
+
+/// Format specific export options.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExportSettings {
+    /// Format for outputs that are not given an explicit extension.
+    pub format: ExportFormat,
+    /// ByteRun1 compression of the IFF ILBM BODY.
+    pub ilbm_compression: bool,
+    /// GIF NETSCAPE loop count for animations, 0 = forever.
+    pub gif_loop_count: u16,
+}
+
+impl Default for ExportSettings {
+    fn default() -> Self {
+        Self {
+            format: ExportFormat::Png,
+            ilbm_compression: true,
+            gif_loop_count: 0,
+        }
+    }
+}
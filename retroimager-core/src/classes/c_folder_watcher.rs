@@ -0,0 +1,210 @@
+﻿use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+use crate::batch_utils::{convert_file, list_inputs, output_path, source_dir};
+use crate::classes::c_batch_job::{BatchItem, BatchStatus};
+use crate::classes::c_export_settings::ExportSettings;
+use crate::classes::c_pipeline_settings::PipelineSettings;
+
+pub const DEFAULT_DEBOUNCE_MS: u64 = 500;
+pub const DEFAULT_POLL_INTERVAL_MS: u64 = 250;
+
+/// Modification time and size, a file counts as changed when either differs.
+type Stamp = (SystemTime, u64);
+
+/// Polls a folder (or wildcard) and converts files once they stop changing for `debounce`.
+/// On start, inputs whose output is missing or older get converted too.
+pub struct FolderWatcher {
+    source: String,
+    output_dir: PathBuf,
+    name_pattern: String,
+    settings: PipelineSettings,
+    export: ExportSettings,
+    debounce: Duration,
+
+    /// Stamp of the last converted (or skipped) version of each input.
+    known: HashMap<PathBuf, Stamp>,
+    /// Inputs waiting to settle: their latest stamp and when it was first seen.
+    pending: HashMap<PathBuf, (Stamp, Instant)>,
+    /// `{index}` of each input ever seen, kept when files come and go so outputs don't move.
+    indices: HashMap<PathBuf, usize>,
+    started: bool,
+}
+
+fn stamp(path: &Path) -> Option<Stamp> {
+    let meta = std::fs::metadata(path).ok()?;
+    Some((meta.modified().ok()?, meta.len()))
+}
+
+impl FolderWatcher {
+    pub fn new(
+        source: &str,
+        output_dir: &Path,
+        name_pattern: &str,
+        settings: &PipelineSettings,
+        export: &ExportSettings,
+        debounce: Duration,
+    ) -> Result<Self, String> {
+        list_inputs(source)?;
+        if same_dir(&source_dir(source), output_dir) {
+            return Err("output folder must differ from the watched folder".to_string());
+        }
+
+        Ok(Self {
+            source: source.to_string(),
+            output_dir: output_dir.to_path_buf(),
+            name_pattern: name_pattern.to_string(),
+            settings: PipelineSettings { load_sequences: false, ..settings.clone() },
+            export: export.clone(),
+            debounce,
+            known: HashMap::new(),
+            pending: HashMap::new(),
+            indices: HashMap::new(),
+            started: false,
+        })
+    }
+
+    /// Inputs of the first scan are numbered in name order, later ones count on from there.
+    fn index_of(&mut self, input: &Path) -> usize {
+        let next = self.indices.len();
+        *self.indices.entry(input.to_path_buf()).or_insert(next)
+    }
+
+    fn is_up_to_date(&self, input: &Path, output: &Path, input_stamp: Stamp) -> bool {
+        input != output && stamp(output).is_some_and(|(out_time, _)| out_time >= input_stamp.0)
+    }
+
+    /// Scans the folder once and converts every input that settled since the last call.
+    /// Returns one item per converted (or failed) file.
+    pub fn poll(&mut self) -> Result<Vec<BatchItem>, String> {
+        let inputs = list_inputs(&self.source)?;
+        let now = Instant::now();
+
+        for input in &inputs {
+            let index = self.index_of(input);
+            let Some(current) = stamp(input) else { continue; };
+
+            if !self.started {
+                let output = output_path(&self.output_dir, &self.name_pattern, input, index);
+                if self.is_up_to_date(input, &output, current) {
+                    self.known.insert(input.clone(), current);
+                    continue;
+                }
+            }
+
+            if self.known.get(input) == Some(&current) {
+                continue;
+            }
+            match self.pending.get(input) {
+                Some((s, _)) if *s == current => {}
+                _ => {
+                    self.pending.insert(input.clone(), (current, now));
+                }
+            }
+        }
+        self.started = true;
+
+        // forget deleted files so a re-created one is converted again
+        self.known.retain(|p, _| inputs.contains(p));
+        self.pending.retain(|p, _| inputs.contains(p));
+
+        let mut settled: Vec<PathBuf> = self
+            .pending
+            .iter()
+            .filter(|(_, (_, seen))| now.duration_since(*seen) >= self.debounce)
+            .map(|(p, _)| p.clone())
+            .collect();
+        settled.sort();
+
+        let mut results = Vec::with_capacity(settled.len());
+        for input in settled {
+            let Some((s, _)) = self.pending.remove(&input) else { continue; };
+            let index = self.index_of(&input);
+            let output = output_path(&self.output_dir, &self.name_pattern, &input, index);
+
+            let status = match convert_file(&input, &output, &self.settings, &self.export, None) {
+                Ok(()) => BatchStatus::Done,
+                Err(e) => BatchStatus::Failed(e),
+            };
+            self.known.insert(input.clone(), s);
+            results.push(BatchItem { input, output, status });
+        }
+
+        Ok(results)
+    }
+}
+
+fn same_dir(a: &Path, b: &Path) -> bool {
+    let a = a.canonicalize().unwrap_or_else(|_| a.to_path_buf());
+    let b = b.canonicalize().unwrap_or_else(|_| b.to_path_buf());
+    a == b
+}
+
+/// "HH:MM:SS" (UTC) for log lines.
+pub fn clock_time(t: SystemTime) -> String {
+    let secs = t.duration_since(SystemTime::UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0) % 86_400;
+    format!("{:02}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("retroimager-{}-{name}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn watcher(source: &Path, output_dir: &Path, pattern: &str) -> Result<FolderWatcher, String> {
+        FolderWatcher::new(source.to_str().unwrap(), output_dir, pattern, &PipelineSettings::default(), &ExportSettings::default(), Duration::ZERO)
+    }
+
+    #[test]
+    fn refuses_to_write_into_the_watched_folder() {
+        let dir = temp_dir("watch-guard");
+        assert!(watcher(&dir, &dir, "{name}.png").is_err());
+        // no matches yet, the folder still comes from the pattern
+        assert!(watcher(&dir.join("*.png"), &dir, "{name}.png").is_err());
+        assert!(watcher(&dir.join("*.png"), &dir.join("out"), "{name}.png").is_ok());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    fn write_image(path: &Path, w: u32) {
+        image::RgbaImage::from_pixel(w, 1, image::Rgba([200, 40, 40, 255])).save(path).unwrap();
+    }
+
+    fn outputs(items: &[BatchItem]) -> Vec<(String, String)> {
+        let name = |p: &Path| p.file_name().unwrap().to_string_lossy().into_owned();
+        items.iter().map(|item| {
+            assert_eq!(item.status, BatchStatus::Done);
+            (name(&item.input), name(&item.output))
+        }).collect()
+    }
+
+    #[test]
+    fn index_stays_with_its_input() {
+        let dir = temp_dir("watch-index");
+        let (input, output) = (dir.join("in"), dir.join("out"));
+        std::fs::create_dir_all(&input).unwrap();
+        write_image(&input.join("b.png"), 1);
+        write_image(&input.join("d.png"), 1);
+
+        let mut w = watcher(&input, &output, "{index}.png").unwrap();
+        let s = |a: &str, b: &str| (a.to_string(), b.to_string());
+        assert_eq!(outputs(&w.poll().unwrap()), [s("b.png", "0001.png"), s("d.png", "0002.png")]);
+
+        // a new file sorting first doesn't renumber the others
+        write_image(&input.join("a.png"), 1);
+        write_image(&input.join("d.png"), 2);
+        assert_eq!(outputs(&w.poll().unwrap()), [s("a.png", "0003.png"), s("d.png", "0002.png")]);
+
+        // nor does a deleted one
+        std::fs::remove_file(input.join("b.png")).unwrap();
+        write_image(&input.join("d.png"), 3);
+        assert_eq!(outputs(&w.poll().unwrap()), [s("d.png", "0002.png")]);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
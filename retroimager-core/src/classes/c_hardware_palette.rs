@@ -0,0 +1,101 @@
+﻿use serde::{Deserialize, Serialize};
+use crate::classes::c_rgb16::Rgb16;
+use crate::color_utils::delta_e;
+
+const CGA: [u32; 16] = [
+    0x000000, 0x0000AA, 0x00AA00, 0x00AAAA, 0xAA0000, 0xAA00AA, 0xAA5500, 0xAAAAAA,
+    0x555555, 0x5555FF, 0x55FF55, 0x55FFFF, 0xFF5555, 0xFF55FF, 0xFFFF55, 0xFFFFFF,
+];
+
+/// "Pepto" measurement of the VIC-II colors.
+const C64: [u32; 16] = [
+    0x000000, 0xFFFFFF, 0x68372B, 0x70A4B2, 0x6F3D86, 0x588D43, 0x352879, 0xB8C76F,
+    0x6F4F25, 0x433900, 0x9A6759, 0x444444, 0x6C6C6C, 0x9AD284, 0x6C5EB5, 0x959595,
+];
+
+/// Normal and bright colors; bright black is the same as black.
+const ZX_SPECTRUM: [u32; 15] = [
+    0x000000, 0x0000D7, 0xD70000, 0xD700D7, 0x00D700, 0x00D7D7, 0xD7D700, 0xD7D7D7,
+    0x0000FF, 0xFF0000, 0xFF00FF, 0x00FF00, 0x00FFFF, 0xFFFF00, 0xFFFFFF,
+];
+
+/// Colors a retro machine can show: either every color of a fixed bit depth per channel
+/// or a fixed list. Used to look up the closest color the target hardware can display.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum HardwarePalette {
+    #[default]
+    None,
+    /// 2 bits per channel (64 colors), also the Master System's.
+    Ega,
+    /// 3 bits per channel (512 colors).
+    AtariSt,
+    /// 4 bits per channel (4096 colors), also the Atari STE's.
+    AmigaOcs,
+    /// 6 bits per channel (262144 colors).
+    Vga,
+    Cga,
+    C64,
+    ZxSpectrum,
+}
+
+impl HardwarePalette {
+    pub const ALL: [HardwarePalette; 8] = [
+        HardwarePalette::None,
+        HardwarePalette::Ega,
+        HardwarePalette::AtariSt,
+        HardwarePalette::AmigaOcs,
+        HardwarePalette::Vga,
+        HardwarePalette::Cga,
+        HardwarePalette::C64,
+        HardwarePalette::ZxSpectrum,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            HardwarePalette::None => "None",
+            HardwarePalette::Ega => "EGA (64)",
+            HardwarePalette::AtariSt => "Atari ST (512)",
+            HardwarePalette::AmigaOcs => "Amiga OCS/ECS (4096)",
+            HardwarePalette::Vga => "VGA (18-bit)",
+            HardwarePalette::Cga => "CGA (16)",
+            HardwarePalette::C64 => "C64 (16)",
+            HardwarePalette::ZxSpectrum => "ZX Spectrum (15)",
+        }
+    }
+
+    /// Bits per channel for the palettes that contain every color of that depth.
+    pub fn bits(&self) -> Option<u32> {
+        match self {
+            HardwarePalette::Ega => Some(2),
+            HardwarePalette::AtariSt => Some(3),
+            HardwarePalette::AmigaOcs => Some(4),
+            HardwarePalette::Vga => Some(6),
+            _ => None,
+        }
+    }
+
+    /// The fixed color list, empty for bit depth palettes and `None`.
+    pub fn colors(&self) -> Vec<Rgb16> {
+        let list: &[u32] = match self {
+            HardwarePalette::Cga => &CGA,
+            HardwarePalette::C64 => &C64,
+            HardwarePalette::ZxSpectrum => &ZX_SPECTRUM,
+            _ => &[],
+        };
+        list.iter()
+            .map(|&v| Rgb16 { r: ((v >> 16) & 0xFF) as u16 * 257, g: ((v >> 8) & 0xFF) as u16 * 257, b: (v & 0xFF) as u16 * 257 })
+            .collect()
+    }
+
+    /// Closest displayable color: per channel rounding for bit depth palettes, the smallest
+    /// ΔE for fixed lists. None for `HardwarePalette::None`.
+    pub fn nearest(&self, c: Rgb16) -> Option<Rgb16> {
+        if let Some(bits) = self.bits() {
+            let max = (1u32 << bits) - 1;
+            let round = |v: u16| ((v as u32 * max + 32767) / 65535 * 65535 / max) as u16;
+            return Some(Rgb16 { r: round(c.r), g: round(c.g), b: round(c.b) });
+        }
+        self.colors().into_iter().min_by(|a, b| delta_e(*a, c).total_cmp(&delta_e(*b, c)))
+    }
+}
@@ -0,0 +1,36 @@
+﻿use crate::image_utils::{resize_interleaved_nearest, rgba8_to_rgba16};
+
+/// Interleaved RGBA image with 16 bits per channel: `[r, g, b, a, r, g, b, a, ...]`.
+/// This is the working format of quantizers and ditherers.
+#[derive(Clone, Debug, Default)]
+pub struct ImageBuffer16 {
+    pub w: usize,
+    pub h: usize,
+    pub data: Vec<u16>,
+}
+
+impl ImageBuffer16 {
+    /// Transparent black image.
+    pub fn new(w: usize, h: usize) -> Self {
+        Self { w, h, data: vec![0; w * h * 4] }
+    }
+
+    /// Widens 8-bit RGBA (`v * 257`). Panics if `rgba8.len() != w * h * 4`.
+    pub fn from_rgba8(w: usize, h: usize, rgba8: &[u8]) -> Self {
+        assert_eq!(rgba8.len(), w * h * 4);
+        let mut data = vec![];
+        rgba8_to_rgba16(rgba8, &mut data);
+        Self { w, h, data }
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> [u16; 4] {
+        let p = (y * self.w + x) * 4;
+        [self.data[p], self.data[p + 1], self.data[p + 2], self.data[p + 3]]
+    }
+
+    /// Nearest neighbour resize by `scale` (at least 1x1).
+    pub fn resized_nearest(&self, scale: f32) -> Self {
+        let (data, w, h) = resize_interleaved_nearest::<u16, 4>(&self.data, self.w, self.h, scale);
+        Self { w, h, data }
+    }
+}
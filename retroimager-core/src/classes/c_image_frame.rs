@@ -0,0 +1,21 @@
+﻿use crate::classes::c_image_buffer16::ImageBuffer16;
+
+/// One frame of a loaded image (a still image is a single frame).
+#[derive(Default)]
+pub struct ImageFrame {
+    /// Decoded RGBA8 at the original size.
+    pub pure8: Vec<u8>,
+    /// Scaled RGBA8, what the quantizer and ditherer see.
+    pub bytes8: Vec<u8>,
+    /// `bytes8` widened to 16 bits.
+    pub source16: ImageBuffer16,
+    /// Ditherer output with the unedited palette.
+    pub dithered_pure: Vec<u8>,
+    /// Palette entry of every pixel of `dithered_pure`, as picked by the ditherer.
+    /// Recoloring, usage counts and indexed export go through it, so entries with the
+    /// same 8-bit color stay apart.
+    pub indices: Vec<u8>,
+    /// `dithered_pure` with palette overrides applied.
+    pub dithered: Vec<u8>,
+    pub delay_ms: u32,
+}
@@ -0,0 +1,11 @@
+﻿use crate::classes::c_palette::Palette;
+
+/// Palette-indexed copy of the dithered output, used by the exporters.
+/// `transparent` is the index reserved for pixels with alpha < 128 (if any).
+pub struct IndexedImage {
+    pub w: usize,
+    pub h: usize,
+    pub palette: Palette,
+    pub indices: Vec<u8>,
+    pub transparent: Option<u8>,
+}
@@ -0,0 +1,43 @@
+﻿use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Progress and cancel flag of one running job, shared between the thread doing
+/// the work and the one showing it. Clones refer to the same job.
+#[derive(Clone, Debug, Default)]
+pub struct JobProgress {
+    cancelled: Arc<AtomicBool>,
+    // f32 bits, 0..=1
+    fraction: Arc<AtomicU32>,
+    stage: Arc<Mutex<String>>,
+}
+
+impl JobProgress {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// Reports `fraction` (clamped to 0..=1) done, currently in `stage`.
+    pub fn set(&self, stage: &str, fraction: f32) {
+        self.fraction.store(fraction.clamp(0.0, 1.0).to_bits(), Ordering::Relaxed);
+        if let Ok(mut s) = self.stage.lock()
+            && *s != stage {
+            *s = stage.to_string();
+        }
+    }
+
+    pub fn fraction(&self) -> f32 {
+        f32::from_bits(self.fraction.load(Ordering::Relaxed))
+    }
+
+    pub fn stage(&self) -> String {
+        self.stage.lock().map(|s| s.clone()).unwrap_or_default()
+    }
+}
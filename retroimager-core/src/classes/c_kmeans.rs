@@ -0,0 +1,23 @@
+﻿use crate::classes::c_palette::Palette;
+use crate::classes::c_rgb16::Rgb16;
+use crate::classes::t_quantizer::Quantizer;
+use crate::image_utils::{kmeans_refine_palette, median_cut_palette};
+
+/// Median cut seeds refined with Lloyd (k-means) iterations.
+#[derive(Clone, Copy, Debug)]
+pub struct KMeans {
+    pub iterations: usize,
+}
+
+impl Default for KMeans {
+    fn default() -> Self {
+        Self { iterations: 8 }
+    }
+}
+
+impl Quantizer for KMeans {
+    fn quantize(&self, samples: Vec<Rgb16>, k: usize) -> Palette {
+        let seeds = median_cut_palette(samples.clone(), k.max(2));
+        Palette::new(kmeans_refine_palette(&samples, seeds, self.iterations))
+    }
+}
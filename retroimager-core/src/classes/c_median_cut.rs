@@ -0,0 +1,14 @@
+﻿use crate::classes::c_palette::Palette;
+use crate::classes::c_rgb16::Rgb16;
+use crate::classes::t_quantizer::Quantizer;
+use crate::image_utils::median_cut_palette;
+
+/// Splits the color box with the widest channel range until there are `k` boxes.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MedianCut;
+
+impl Quantizer for MedianCut {
+    fn quantize(&self, samples: Vec<Rgb16>, k: usize) -> Palette {
+        Palette::new(median_cut_palette(samples, k.max(2)))
+    }
+}
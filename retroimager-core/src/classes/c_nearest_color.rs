@@ -0,0 +1,17 @@
+﻿use crate::classes::c_color_metric::ColorMetric;
+use crate::classes::c_image_buffer16::ImageBuffer16;
+use crate::classes::c_palette::Palette;
+use crate::classes::t_ditherer::Ditherer;
+use crate::image_utils::map_nearest_palette_rgba16_to_rgba8;
+
+/// No dithering: every pixel gets the nearest palette color.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NearestColor {
+    pub metric: ColorMetric,
+}
+
+impl Ditherer for NearestColor {
+    fn dither(&self, src: &ImageBuffer16, palette: &Palette, _prev: Option<(&ImageBuffer16, &[u8])>, dst8: &mut Vec<u8>, indices: &mut Vec<u8>) {
+        map_nearest_palette_rgba16_to_rgba8(self.metric, &src.data, dst8, indices, src.w, src.h, palette);
+    }
+}
@@ -0,0 +1,25 @@
+﻿use crate::classes::c_color_metric::ColorMetric;
+use crate::classes::c_image_buffer16::ImageBuffer16;
+use crate::classes::c_palette::Palette;
+use crate::classes::t_ditherer::Ditherer;
+use crate::image_utils::dither_ordered_palette_rgba16_to_rgba8;
+
+/// Ordered dithering with the 8x8 Bayer matrix. Stable between frames by construction.
+#[derive(Clone, Copy, Debug)]
+pub struct OrderedBayer8 {
+    pub metric: ColorMetric,
+    /// 0..=1, scales the threshold spread.
+    pub strength: f32,
+}
+
+impl Default for OrderedBayer8 {
+    fn default() -> Self {
+        Self { metric: ColorMetric::Rgb, strength: 1.0 }
+    }
+}
+
+impl Ditherer for OrderedBayer8 {
+    fn dither(&self, src: &ImageBuffer16, palette: &Palette, _prev: Option<(&ImageBuffer16, &[u8])>, dst8: &mut Vec<u8>, indices: &mut Vec<u8>) {
+        dither_ordered_palette_rgba16_to_rgba8(self.strength, self.metric, &src.data, dst8, indices, src.w, src.h, palette);
+    }
+}
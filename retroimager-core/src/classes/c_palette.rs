@@ -0,0 +1,62 @@
+﻿use std::ops::{Deref, DerefMut};
+use serde::{Deserialize, Serialize};
+use crate::classes::c_rgb16::Rgb16;
+use crate::image_utils::nearest_palette_index;
+
+/// Ordered list of palette colors. Entry order is the index order of indexed exports.
+/// Derefs to `[Rgb16]`, so it can be indexed and iterated like a slice.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Palette {
+    colors: Vec<Rgb16>,
+}
+
+impl Palette {
+    pub fn new(colors: Vec<Rgb16>) -> Self {
+        Self { colors }
+    }
+
+    pub fn colors(&self) -> &Vec<Rgb16> {
+        &self.colors
+    }
+
+    pub fn colors_mut(&mut self) -> &mut Vec<Rgb16> {
+        &mut self.colors
+    }
+
+    pub fn into_colors(self) -> Vec<Rgb16> {
+        self.colors
+    }
+
+    /// Index of the closest entry (squared RGB distance). 0 for an empty palette.
+    pub fn nearest_index(&self, c: Rgb16) -> usize {
+        if self.colors.is_empty() {
+            return 0;
+        }
+        nearest_palette_index(c, &self.colors)
+    }
+
+    pub fn nearest(&self, c: Rgb16) -> Rgb16 {
+        self.colors.get(self.nearest_index(c)).copied().unwrap_or_default()
+    }
+}
+
+impl From<Vec<Rgb16>> for Palette {
+    fn from(colors: Vec<Rgb16>) -> Self {
+        Self { colors }
+    }
+}
+
+impl Deref for Palette {
+    type Target = [Rgb16];
+
+    fn deref(&self) -> &[Rgb16] {
+        &self.colors
+    }
+}
+
+impl DerefMut for Palette {
+    fn deref_mut(&mut self) -> &mut [Rgb16] {
+        &mut self.colors
+    }
+}
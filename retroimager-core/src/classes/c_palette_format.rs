@@ -0,0 +1,80 @@
+﻿use std::path::Path;
+
+/// Palette file formats that can be imported and exported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PaletteFormat {
+    /// One `RRGGBB` per line (Lospec `.hex`).
+    Hex,
+    /// GIMP / Aseprite / Inkscape `.gpl`.
+    Gpl,
+    /// JASC-PAL text `.pal` (Paint Shop Pro, Aseprite). RIFF `.pal` is read too.
+    JascPal,
+    /// Adobe Color Table: 256 RGB triplets plus an optional color count.
+    Act,
+    /// Adobe Swatch Exchange.
+    Ase,
+    /// Paint.NET `.txt`: `AARRGGBB` per line, ';' comments.
+    PaintNet,
+    /// One pixel per color, left to right (Lospec "PNG 1x").
+    PngStrip,
+}
+
+impl PaletteFormat {
+    pub const ALL: [PaletteFormat; 7] = [
+        PaletteFormat::Gpl,
+        PaletteFormat::JascPal,
+        PaletteFormat::Act,
+        PaletteFormat::Ase,
+        PaletteFormat::PaintNet,
+        PaletteFormat::Hex,
+        PaletteFormat::PngStrip,
+    ];
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            PaletteFormat::Hex => "hex",
+            PaletteFormat::Gpl => "gpl",
+            PaletteFormat::JascPal => "pal",
+            PaletteFormat::Act => "act",
+            PaletteFormat::Ase => "ase",
+            PaletteFormat::PaintNet => "txt",
+            PaletteFormat::PngStrip => "png",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            PaletteFormat::Hex => "Hex list",
+            PaletteFormat::Gpl => "GIMP palette",
+            PaletteFormat::JascPal => "JASC-PAL",
+            PaletteFormat::Act => "Adobe Color Table",
+            PaletteFormat::Ase => "Adobe Swatch Exchange",
+            PaletteFormat::PaintNet => "Paint.NET palette",
+            PaletteFormat::PngStrip => "PNG strip",
+        }
+    }
+
+    /// Format by file extension, `None` for unknown ones.
+    pub fn from_path(path: &Path) -> Option<PaletteFormat> {
+        let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+        PaletteFormat::ALL.into_iter().find(|f| f.extension() == ext)
+    }
+
+    /// Format by magic bytes, falling back to the extension and then to a hex list.
+    /// ACT files have no signature and are only recognized by their extension.
+    pub fn detect(path: &Path, bytes: &[u8]) -> PaletteFormat {
+        if bytes.starts_with(b"GIMP Palette") {
+            return PaletteFormat::Gpl;
+        }
+        if bytes.starts_with(b"JASC-PAL") || (bytes.starts_with(b"RIFF") && bytes.get(8..12) == Some(b"PAL ")) {
+            return PaletteFormat::JascPal;
+        }
+        if bytes.starts_with(b"ASEF") {
+            return PaletteFormat::Ase;
+        }
+        if bytes.starts_with(b"\x89PNG") {
+            return PaletteFormat::PngStrip;
+        }
+        PaletteFormat::from_path(path).unwrap_or(PaletteFormat::Hex)
+    }
+}
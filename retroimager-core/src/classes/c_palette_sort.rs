@@ -0,0 +1,22 @@
+﻿/// Orders a palette can be sorted in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PaletteSort {
+    /// Grays first (dark to light), then colors around the hue wheel.
+    Hue,
+    /// Dark to light.
+    Luminance,
+    /// Most used first.
+    Usage,
+}
+
+impl PaletteSort {
+    pub const ALL: [PaletteSort; 3] = [PaletteSort::Hue, PaletteSort::Luminance, PaletteSort::Usage];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            PaletteSort::Hue => "Hue",
+            PaletteSort::Luminance => "Luminance",
+            PaletteSort::Usage => "Usage",
+        }
+    }
+}
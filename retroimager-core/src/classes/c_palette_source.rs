@@ -0,0 +1,36 @@
+﻿use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+use crate::classes::c_palette::Palette;
+use crate::palette_utils::read_palette_file;
+
+/// Where the palette comes from.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum PaletteSource {
+    /// Quantize it from the image.
+    #[default]
+    Extract,
+    /// Colors stored right in the settings.
+    Fixed { colors: Palette },
+    /// Palette file; relative paths are resolved against the preset that contains them.
+    File { path: PathBuf },
+}
+
+impl PaletteSource {
+    /// The fixed palette, `None` when it has to be extracted from the image.
+    pub fn load(&self) -> Result<Option<Palette>, String> {
+        match self {
+            PaletteSource::Extract => Ok(None),
+            PaletteSource::Fixed { colors } => Ok(Some(colors.clone())),
+            PaletteSource::File { path } => read_palette_file(path).map(Some),
+        }
+    }
+
+    /// Makes a relative file path absolute against `base_dir`.
+    pub fn resolve_relative(&mut self, base_dir: &Path) {
+        if let PaletteSource::File { path } = self
+            && path.is_relative() {
+            *path = base_dir.join(&*path);
+        }
+    }
+}
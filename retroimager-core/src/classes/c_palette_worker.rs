@@ -0,0 +1,97 @@
+﻿use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver, TryRecvError};
+use crate::classes::c_job_progress::JobProgress;
+use crate::classes::c_palette::Palette;
+use crate::classes::c_pipeline_settings::PipelineSettings;
+use crate::pipeline_utils::palette_from_image_with;
+
+/// Takes a palette from a reference image on a background thread; see
+/// [`palette_from_image`](crate::pipeline_utils::palette_from_image).
+pub struct PaletteWorker {
+    path: PathBuf,
+    progress: JobProgress,
+    result: Receiver<Result<Palette, String>>,
+}
+
+impl PaletteWorker {
+    /// Starts reading `path`. `notify` is called from the worker thread when it is done.
+    pub fn new(path: &Path, settings: PipelineSettings, notify: impl Fn() + Send + 'static) -> Self {
+        let (tx, result) = channel();
+        let progress = JobProgress::new();
+        let job_progress = progress.clone();
+        let job_path = path.to_path_buf();
+
+        // not joined: a cancelled thread stops at its next check in the background
+        std::thread::spawn(move || {
+            if let Some(palette) = palette_from_image_with(&job_path, &settings, &job_progress) {
+                let _ = tx.send(palette);
+            }
+            notify();
+        });
+
+        Self { path: path.to_path_buf(), progress, result }
+    }
+
+    /// The palette once it is done. A cancelled job never gives one.
+    pub fn poll(&mut self) -> Option<Result<Palette, String>> {
+        match self.result.try_recv() {
+            Ok(result) => Some(result),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) if self.progress.is_cancelled() => None,
+            Err(TryRecvError::Disconnected) => Some(Err("processing failed".to_string())),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn progress(&self) -> &JobProgress {
+        &self.progress
+    }
+
+    pub fn cancel(&self) {
+        self.progress.cancel();
+    }
+}
+
+impl Drop for PaletteWorker {
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    fn wait(worker: &mut PaletteWorker) -> Result<Palette, String> {
+        let start = Instant::now();
+        loop {
+            if let Some(result) = worker.poll() {
+                return result;
+            }
+            assert!(start.elapsed() < Duration::from_secs(30), "palette did not finish");
+            std::thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    #[test]
+    fn reads_the_palette_and_reports_errors() {
+        let dir = std::env::temp_dir().join(format!("retroimager-{}-palette-worker", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("reference.png");
+        let mut img = image::RgbaImage::from_pixel(4, 4, image::Rgba([10, 200, 30, 255]));
+        img.put_pixel(0, 0, image::Rgba([250, 0, 0, 255]));
+        img.save(&path).unwrap();
+
+        let mut worker = PaletteWorker::new(&path, PipelineSettings::default(), || {});
+        assert_eq!(wait(&mut worker).unwrap().len(), 2);
+
+        let mut missing = PaletteWorker::new(&dir.join("missing.png"), PipelineSettings::default(), || {});
+        assert!(wait(&mut missing).is_err());
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
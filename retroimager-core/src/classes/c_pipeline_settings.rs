@@ -0,0 +1,91 @@
+﻿use serde::{Deserialize, Serialize};
+use crate::classes::c_adjustments::Adjustments;
+use crate::classes::c_color_metric::ColorMetric;
+use crate::classes::c_dither_algorithm::DitherAlgorithm;
+use crate::classes::c_palette_source::PaletteSource;
+use crate::classes::c_quantizer_kind::QuantizerKind;
+use crate::classes::c_resize_filter::ResizeFilter;
+use crate::classes::c_rgb16::Rgb16;
+use crate::palette_utils::MAX_PALETTE_COLORS;
+
+/// Largest target width or height.
+pub const MAX_TARGET_SIZE: u32 = 8192;
+
+/// Everything that affects how a source image turns into dithered output.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PipelineSettings {
+    /// Palette size when the palette is extracted from the image.
+    pub colors: u16,
+    /// Scale applied before quantizing, unless a target size is set.
+    pub scale: f32,
+    /// Target width in pixels, 0 = from `scale` (or from `height`, keeping the aspect ratio).
+    pub width: u32,
+    /// Target height in pixels, 0 = from `scale` (or from `width`, keeping the aspect ratio).
+    pub height: u32,
+    pub resize_filter: ResizeFilter,
+    pub adjustments: Adjustments,
+    pub palette: PaletteSource,
+    /// Colors that are always part of an extracted palette.
+    pub locked_colors: Vec<Rgb16>,
+    pub metric: ColorMetric,
+    pub quantizer: QuantizerKind,
+    pub dither: DitherAlgorithm,
+    /// 0..=1, how much of the error (or threshold spread) the ditherer applies.
+    pub dither_strength: f32,
+    /// One palette for all frames of an animation instead of one per frame.
+    pub shared_palette: bool,
+    /// Keep the previous frame's output on pixels that didn't change.
+    pub temporal_coherence: bool,
+    /// Load "name_0001.png" together with the rest of its numbered sequence.
+    pub load_sequences: bool,
+}
+
+impl Default for PipelineSettings {
+    fn default() -> Self {
+        Self {
+            colors: 8,
+            scale: 1.0,
+            width: 0,
+            height: 0,
+            resize_filter: ResizeFilter::Nearest,
+            adjustments: Adjustments::default(),
+            palette: PaletteSource::Extract,
+            locked_colors: vec![],
+            metric: ColorMetric::Rgb,
+            quantizer: QuantizerKind::MedianCut,
+            dither: DitherAlgorithm::FloydSteinberg,
+            dither_strength: 1.0,
+            shared_palette: true,
+            temporal_coherence: true,
+            load_sequences: true,
+        }
+    }
+}
+
+impl PipelineSettings {
+    /// Checks the values a hand-edited file could get wrong; the pipeline assumes them.
+    pub fn validate(&self) -> Result<(), String> {
+        if !(2..=MAX_PALETTE_COLORS).contains(&(self.colors as usize)) {
+            return Err(format!("colors must be 2..={MAX_PALETTE_COLORS}, got {}", self.colors));
+        }
+        if !(self.scale > 0.0 && self.scale.is_finite()) {
+            return Err(format!("scale must be > 0, got {}", self.scale));
+        }
+        if self.width > MAX_TARGET_SIZE || self.height > MAX_TARGET_SIZE {
+            return Err(format!("width and height must be 0..={MAX_TARGET_SIZE}, got {}x{}", self.width, self.height));
+        }
+        if !(0.0..=1.0).contains(&self.dither_strength) {
+            return Err(format!("dither_strength must be 0..=1, got {}", self.dither_strength));
+        }
+        if let PaletteSource::Fixed { colors } = &self.palette
+            && !(1..=MAX_PALETTE_COLORS).contains(&colors.len()) {
+            return Err(format!("fixed palette must have 1..={MAX_PALETTE_COLORS} colors, got {}", colors.len()));
+        }
+        if self.locked_colors.len() > MAX_PALETTE_COLORS {
+            return Err(format!("locked_colors may hold {MAX_PALETTE_COLORS} colors, got {}", self.locked_colors.len()));
+        }
+        self.adjustments.validate()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,268 @@
+﻿use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread::JoinHandle;
+use image::RgbaImage;
+use crate::classes::c_job_progress::JobProgress;
+use crate::classes::c_palette::Palette;
+use crate::classes::c_pipeline_settings::PipelineSettings;
+use crate::classes::c_processed_image::ProcessedImage;
+use crate::classes::c_project::ProjectSource;
+use crate::decode_utils::load_frames;
+use crate::pipeline_utils::{build_palettes_with, dither_frames_with, prepare_frames_with, process_frames, target_size};
+
+/// Where a job gets its frames from.
+pub enum JobSource {
+    File { path: PathBuf, sequences: bool },
+    Project { source: ProjectSource, dir: PathBuf, sequences: bool },
+    /// Already decoded frames, e.g. to re-run the pipeline with other settings.
+    Frames(Arc<Vec<(RgbaImage, u32)>>),
+}
+
+/// A quick low resolution pass of one frame, sent before the full result.
+#[derive(Clone, Copy, Debug)]
+pub struct PreviewPass {
+    pub frame: usize,
+    /// Only done when the output is bigger; the preview is scaled down to about this many pixels.
+    pub max_pixels: usize,
+}
+
+pub struct PipelineJob {
+    pub version: u64,
+    pub source: JobSource,
+    pub settings: PipelineSettings,
+    /// Palettes to dither with; None builds them (or loads `settings.palette`).
+    pub palettes: Option<Vec<Palette>>,
+    /// Palettes shown before the job; locked colors keep their slots in them when building.
+    pub previous: Vec<Palette>,
+    pub preview: Option<PreviewPass>,
+    pub progress: JobProgress,
+}
+
+pub struct JobOutput {
+    /// The decoded source frames.
+    pub source: Arc<Vec<(RgbaImage, u32)>>,
+    pub image: ProcessedImage,
+    /// True when the job built the palettes instead of getting them.
+    pub new_palettes: bool,
+    /// A [`PreviewPass`] result: a single scaled down frame, the full result follows.
+    pub is_preview: bool,
+}
+
+type JobResult = (u64, Result<JobOutput, String>);
+
+/// Runs decode → scale → quantize → dither on a background thread, one job at a time.
+/// A new job supersedes the running one: the old one is cancelled and its result dropped.
+pub struct PipelineWorker {
+    jobs: Option<Sender<PipelineJob>>,
+    results: Receiver<JobResult>,
+    version: u64,
+    running: Option<JobProgress>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl PipelineWorker {
+    /// Starts the worker thread. `notify` is called from it after every finished job.
+    pub fn new(notify: impl Fn() + Send + 'static) -> Self {
+        let (job_tx, job_rx) = channel::<PipelineJob>();
+        let (result_tx, result_rx) = channel::<JobResult>();
+
+        let thread = std::thread::spawn(move || {
+            while let Ok(mut job) = job_rx.recv() {
+                // only the newest queued job matters
+                while let Ok(newer) = job_rx.try_recv() {
+                    job = newer;
+                }
+                let version = job.version;
+                let send = |result: Result<JobOutput, String>| {
+                    let sent = result_tx.send((version, result)).is_ok();
+                    notify();
+                    sent
+                };
+                // a panicking job is reported like a failed one, so the caller isn't left waiting
+                let result = catch_unwind(AssertUnwindSafe(|| run_job(job, &send)))
+                    .unwrap_or_else(|panic| Some(Err(format!("processing failed: {}", panic_message(panic.as_ref())))));
+                let Some(result) = result else { continue; };
+                if !send(result) {
+                    break;
+                }
+            }
+        });
+
+        Self { jobs: Some(job_tx), results: result_rx, version: 0, running: None, thread: Some(thread) }
+    }
+
+    /// Queues a job, cancelling the one that runs. Returns the new job's version.
+    pub fn submit(&mut self, source: JobSource, settings: PipelineSettings, palettes: Option<Vec<Palette>>, previous: Vec<Palette>, preview: Option<PreviewPass>) -> u64 {
+        self.cancel();
+        self.version += 1;
+
+        let progress = JobProgress::new();
+        let job = PipelineJob { version: self.version, source, settings, palettes, previous, preview, progress: progress.clone() };
+        if let Some(jobs) = &self.jobs
+            && jobs.send(job).is_ok() {
+            self.running = Some(progress);
+        }
+        self.version
+    }
+
+    /// Stops the running job; nothing will come out of it.
+    pub fn cancel(&mut self) {
+        if let Some(progress) = self.running.take() {
+            progress.cancel();
+        }
+    }
+
+    pub fn is_busy(&self) -> bool {
+        self.running.is_some()
+    }
+
+    /// Progress of the running job.
+    pub fn progress(&self) -> Option<&JobProgress> {
+        self.running.as_ref()
+    }
+
+    /// Version of the newest job.
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    /// Result of the newest job once it is done, or its preview before that.
+    /// Results of superseded jobs are dropped.
+    pub fn poll(&mut self) -> Option<Result<JobOutput, String>> {
+        while let Ok((version, result)) = self.results.try_recv() {
+            if version == self.version && self.running.is_some() {
+                if !matches!(&result, Ok(output) if output.is_preview) {
+                    self.running = None;
+                }
+                return Some(result);
+            }
+        }
+        None
+    }
+}
+
+impl Drop for PipelineWorker {
+    fn drop(&mut self) {
+        self.cancel();
+        // closing the channel ends the thread's loop
+        self.jobs = None;
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// None when the job was cancelled. A preview result goes out through `send` on the way.
+fn run_job(job: PipelineJob, send: &dyn Fn(Result<JobOutput, String>) -> bool) -> Option<Result<JobOutput, String>> {
+    let progress = &job.progress;
+    if progress.is_cancelled() {
+        return None;
+    }
+
+    progress.set("Loading", 0.0);
+    let decoded = match job.source {
+        JobSource::File { path, sequences } => load_frames(&path, sequences).map(Arc::new).map_err(|e| format!("{}: {e}", path.display())),
+        JobSource::Project { source, dir, sequences } => source.decode(&dir, sequences).map(Arc::new),
+        JobSource::Frames(frames) => Ok(frames),
+    };
+    let source = match decoded {
+        Ok(d) if d.is_empty() => return Some(Err("image has no frames".to_string())),
+        Ok(d) => d,
+        Err(e) => return Some(Err(e)),
+    };
+
+    if let Some(pass) = job.preview
+        && let Some(preview) = preview_pass(&source, &job.settings, job.palettes.as_deref(), pass) {
+        if progress.is_cancelled() {
+            return None;
+        }
+        send(Ok(JobOutput { source: source.clone(), image: preview, new_palettes: false, is_preview: true }));
+    }
+
+    // every frame keeps its own full size copy and the output hands `source` back, so the
+    // decoded frames are copied once here
+    let (mut frames, w, h) = prepare_frames_with(source.as_ref().clone(), &job.settings, progress)?;
+
+    let (palettes, new_palettes) = match job.palettes {
+        Some(p) if !p.is_empty() => (p, false),
+        _ => match job.settings.palette.load() {
+            Ok(Some(fixed)) => (vec![fixed], true),
+            Ok(None) => (build_palettes_with(&frames, &job.settings, &job.previous, progress)?, true),
+            Err(e) => return Some(Err(e)),
+        },
+    };
+
+    if !dither_frames_with(&mut frames, &palettes, &job.settings, progress) {
+        return None;
+    }
+    progress.set("Done", 1.0);
+
+    Some(Ok(JobOutput { source, image: ProcessedImage { frames, palettes, w, h }, new_palettes, is_preview: false }))
+}
+
+fn panic_message(panic: &(dyn std::any::Any + Send)) -> &str {
+    match (panic.downcast_ref::<&str>(), panic.downcast_ref::<String>()) {
+        (Some(s), _) => s,
+        (_, Some(s)) => s,
+        _ => "unknown error",
+    }
+}
+
+/// One frame at about `pass.max_pixels`; None when the full output isn't bigger than that.
+fn preview_pass(source: &[(RgbaImage, u32)], settings: &PipelineSettings, palettes: Option<&[Palette]>, pass: PreviewPass) -> Option<ProcessedImage> {
+    let frame = pass.frame.min(source.len().saturating_sub(1));
+    let (img, delay) = source.get(frame)?;
+
+    let (w, h) = target_size(img.width() as usize, img.height() as usize, settings);
+    if w * h <= pass.max_pixels {
+        return None;
+    }
+    let f = (pass.max_pixels as f32 / (w * h) as f32).sqrt();
+    let small = PipelineSettings {
+        width: ((w as f32 * f) as u32).max(1),
+        height: ((h as f32 * f) as u32).max(1),
+        temporal_coherence: false,
+        ..settings.clone()
+    };
+
+    let fixed = match palettes {
+        Some(p) if !p.is_empty() => Some(p[frame.min(p.len() - 1)].clone()),
+        _ => settings.palette.load().ok().flatten(),
+    };
+    Some(process_frames(vec![(img.clone(), *delay)], &small, fixed))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    fn wait(worker: &mut PipelineWorker) -> Result<JobOutput, String> {
+        let start = Instant::now();
+        loop {
+            if let Some(result) = worker.poll() {
+                return result;
+            }
+            assert!(start.elapsed() < Duration::from_secs(30), "job did not finish");
+            std::thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    #[test]
+    fn a_panicking_job_is_reported_as_failed() {
+        let mut worker = PipelineWorker::new(|| {});
+        // the pipeline doesn't expect a frame without pixels
+        let empty = Arc::new(vec![(RgbaImage::new(0, 3), 0)]);
+        worker.submit(JobSource::Frames(empty), PipelineSettings::default(), None, vec![], None);
+        assert!(wait(&mut worker).is_err());
+        assert!(!worker.is_busy());
+
+        // and the worker keeps going
+        let frames = Arc::new(vec![(RgbaImage::from_pixel(4, 4, image::Rgba([200, 10, 10, 255])), 0)]);
+        worker.submit(JobSource::Frames(frames), PipelineSettings::default(), None, vec![], None);
+        let output = wait(&mut worker).unwrap();
+        assert_eq!((output.image.w, output.image.h), (4, 4));
+    }
+}
@@ -0,0 +1,146 @@
+﻿use std::path::Path;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use crate::classes::c_export_settings::ExportSettings;
+use crate::classes::c_pipeline_settings::PipelineSettings;
+
+/// Schema version written into new presets.
+pub const PRESET_VERSION: u32 = 1;
+
+pub const PRESET_EXTENSION: &str = "json";
+
+/// Named, shareable description of the whole conversion: everything from resizing to the
+/// export format. Stored as JSON with a `version` so older files can be migrated on load.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Preset {
+    pub version: u32,
+    pub name: String,
+    pub pipeline: PipelineSettings,
+    pub export: ExportSettings,
+}
+
+impl Default for Preset {
+    fn default() -> Self {
+        Self {
+            version: PRESET_VERSION,
+            name: String::new(),
+            pipeline: PipelineSettings::default(),
+            export: ExportSettings::default(),
+        }
+    }
+}
+
+/// Version 0 is the flat layout of the GUI's config.json, which people shared as presets.
+fn migrate_v0(old: &Map<String, Value>) -> Value {
+    let get = |key: &str| old.get(key).cloned();
+
+    let mut pipeline = Map::new();
+    let mut export = Map::new();
+    for (from, to) in [
+        ("colors_palette_size", "colors"),
+        ("image_percent", "scale"),
+        ("quantizer", "quantizer"),
+        ("dither_algorithm", "dither"),
+        ("shared_palette", "shared_palette"),
+        ("temporal_coherence", "temporal_coherence"),
+        ("load_sequences", "load_sequences"),
+    ] {
+        if let Some(v) = get(from) {
+            pipeline.insert(to.to_string(), v);
+        }
+    }
+    for key in ["ilbm_compression", "gif_loop_count"] {
+        if let Some(v) = get(key) {
+            export.insert(key.to_string(), v);
+        }
+    }
+
+    json!({ "version": 1, "pipeline": pipeline, "export": export })
+}
+
+impl Preset {
+    pub fn from_json(text: &str) -> Result<Preset, String> {
+        let mut value: Value = serde_json::from_str(text).map_err(|e| format!("invalid JSON: {e}"))?;
+        let Some(obj) = value.as_object() else {
+            return Err("preset must be a JSON object".to_string());
+        };
+
+        let version = match obj.get("version") {
+            Some(v) => v.as_u64().ok_or("\"version\" must be a number")? as u32,
+            None => 0,
+        };
+        if version > PRESET_VERSION {
+            return Err(format!("preset version {version} is newer than supported ({PRESET_VERSION})"));
+        }
+        if version == 0 {
+            value = migrate_v0(obj);
+        }
+
+        let mut preset: Preset = serde_json::from_value(value).map_err(|e| format!("invalid preset: {e}"))?;
+        preset.pipeline.validate().map_err(|e| format!("invalid preset: {e}"))?;
+        preset.version = PRESET_VERSION;
+        Ok(preset)
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap_or_default()
+    }
+
+    /// Reads a preset; relative palette paths are resolved against the preset's folder.
+    pub fn load(path: &Path) -> Result<Preset, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {e}", path.display()))?;
+        let mut preset = Preset::from_json(&text).map_err(|e| format!("{}: {e}", path.display()))?;
+
+        if preset.name.is_empty() {
+            preset.name = path.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+        }
+        if let Some(dir) = path.parent() {
+            preset.pipeline.palette.resolve_relative(dir);
+        }
+        Ok(preset)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        std::fs::write(path, self.to_json()).map_err(|e| format!("{}: {e}", path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BLACK: &str = r#"{"r":0,"g":0,"b":0}"#;
+
+    #[test]
+    fn out_of_range_values_are_rejected() {
+        for (json, field) in [
+            (r#"{"version":1,"pipeline":{"colors":1000}}"#, "colors"),
+            (r#"{"version":1,"pipeline":{"colors":1}}"#, "colors"),
+            (r#"{"version":1,"pipeline":{"scale":-2}}"#, "scale"),
+            (r#"{"version":1,"pipeline":{"width":100000}}"#, "width"),
+            (r#"{"version":1,"pipeline":{"dither_strength":3}}"#, "dither_strength"),
+            (r#"{"colors_palette_size":0}"#, "colors"),
+            (r#"{"version":1,"pipeline":{"palette":{"kind":"fixed","colors":[]}}}"#, "fixed palette"),
+            (&format!(r#"{{"version":1,"pipeline":{{"palette":{{"kind":"fixed","colors":[{}]}}}}}}"#, vec![BLACK; 257].join(",")), "fixed palette"),
+            (&format!(r#"{{"version":1,"pipeline":{{"locked_colors":[{}]}}}}"#, vec![BLACK; 257].join(",")), "locked_colors"),
+            (r#"{"version":1,"pipeline":{"adjustments":{"brightness":2}}}"#, "brightness"),
+            (r#"{"version":1,"pipeline":{"adjustments":{"contrast":-3}}}"#, "contrast"),
+            (r#"{"version":1,"pipeline":{"adjustments":{"gamma":0}}}"#, "gamma"),
+            (r#"{"version":1,"pipeline":{"adjustments":{"saturation":9}}}"#, "saturation"),
+            (r#"{"version":1,"pipeline":{"adjustments":{"hue_shift":720}}}"#, "hue_shift"),
+            (r#"{"version":1,"pipeline":{"adjustments":{"levels_in_black":-1}}}"#, "levels_in_black"),
+            (r#"{"version":1,"pipeline":{"adjustments":{"levels_in_white":2}}}"#, "levels_in_white"),
+            (r#"{"version":1,"pipeline":{"adjustments":{"levels_out_black":-1}}}"#, "levels_out_black"),
+            (r#"{"version":1,"pipeline":{"adjustments":{"levels_out_white":2}}}"#, "levels_out_white"),
+            (r#"{"version":1,"pipeline":{"adjustments":{"sharpen_amount":50}}}"#, "sharpen_amount"),
+            (r#"{"version":1,"pipeline":{"adjustments":{"sharpen_radius":1000}}}"#, "sharpen_radius"),
+            (r#"{"version":1,"pipeline":{"adjustments":{"denoise_radius":200}}}"#, "denoise_radius"),
+            (r#"{"version":1,"pipeline":{"adjustments":{"curve":[[0.5,1.5]]}}}"#, "curve"),
+        ] {
+            let e = Preset::from_json(json).unwrap_err();
+            assert!(e.contains(field), "{json}: {e}");
+        }
+        assert!(Preset::from_json(r#"{"version":1,"pipeline":{"colors":256,"scale":0.5}}"#).is_ok());
+    }
+}
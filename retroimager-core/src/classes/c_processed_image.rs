@@ -0,0 +1,21 @@
+﻿use crate::classes::c_image_frame::ImageFrame;
+use crate::classes::c_indexed_image::IndexedImage;
+use crate::classes::c_palette::Palette;
+use crate::pipeline_utils::frames_to_indexed;
+
+/// Output of the whole pipeline for one input.
+pub struct ProcessedImage {
+    pub frames: Vec<ImageFrame>,
+    /// One shared palette or one per frame.
+    pub palettes: Vec<Palette>,
+    /// Scaled size of every frame.
+    pub w: usize,
+    pub h: usize,
+}
+
+impl ProcessedImage {
+    /// All frames as indexed images plus their delays in ms, ready for the exporters.
+    pub fn to_indexed(&self) -> (Vec<IndexedImage>, Vec<u32>) {
+        frames_to_indexed(&self.frames, &self.palettes)
+    }
+}
@@ -0,0 +1,227 @@
+﻿use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use image::{ImageFormat, RgbaImage};
+use serde::{Deserialize, Serialize};
+use crate::classes::c_export_settings::ExportSettings;
+use crate::classes::c_palette::Palette;
+use crate::classes::c_pipeline_settings::PipelineSettings;
+use crate::classes::c_processed_image::ProcessedImage;
+use crate::classes::c_viewer_state::ViewerState;
+use crate::decode_utils::{decode_bytes, load_frames};
+use crate::palette_utils::MAX_PALETTE_COLORS;
+use crate::pipeline_utils::{apply_palette_overrides, dither_frames, prepare_frames};
+
+pub const PROJECT_VERSION: u32 = 1;
+
+pub const PROJECT_EXTENSION: &str = "dsproj";
+
+/// One decoded source frame stored inside the project as a base64 PNG.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EmbeddedFrame {
+    pub delay_ms: u32,
+    pub png: String,
+}
+
+/// The source image: embedded frames when there are any, otherwise a path
+/// (relative to the project file when it was saved next to it).
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProjectSource {
+    pub path: Option<PathBuf>,
+    pub frames: Vec<EmbeddedFrame>,
+}
+
+/// A saved session: source, settings, the palettes the output was made with and the
+/// user's edits to them. Loading it gives back the same output without re-quantizing.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Project {
+    pub version: u32,
+    pub source: ProjectSource,
+    pub pipeline: PipelineSettings,
+    pub export: ExportSettings,
+    /// Palettes as extracted (one shared or one per frame).
+    pub palettes: Vec<Palette>,
+    /// Edited palettes, same layout as `palettes`.
+    pub overrides: Vec<Palette>,
+    pub viewer: ViewerState,
+}
+
+impl Default for Project {
+    fn default() -> Self {
+        Self {
+            version: PROJECT_VERSION,
+            source: ProjectSource::default(),
+            pipeline: PipelineSettings::default(),
+            export: ExportSettings::default(),
+            palettes: vec![],
+            overrides: vec![],
+            viewer: ViewerState::default(),
+        }
+    }
+}
+
+impl ProjectSource {
+    /// Embeds full resolution frames losslessly.
+    pub fn embed(frames: &[(RgbaImage, u32)]) -> Result<Self, String> {
+        let mut out = Vec::with_capacity(frames.len());
+        for (img, delay_ms) in frames {
+            let mut png = Cursor::new(Vec::new());
+            img.write_to(&mut png, ImageFormat::Png).map_err(|e| e.to_string())?;
+            out.push(EmbeddedFrame { delay_ms: *delay_ms, png: BASE64.encode(png.into_inner()) });
+        }
+        Ok(Self { path: None, frames: out })
+    }
+
+    /// Reference to `source`, relative to `project_dir` when it is inside it.
+    pub fn linked(source: &Path, project_dir: &Path) -> Self {
+        let path = source.strip_prefix(project_dir).unwrap_or(source);
+        Self { path: Some(path.to_path_buf()), frames: vec![] }
+    }
+
+    pub fn decode(&self, project_dir: &Path, sequences: bool) -> Result<Vec<(RgbaImage, u32)>, String> {
+        if !self.frames.is_empty() {
+            let mut out = Vec::with_capacity(self.frames.len());
+            for f in &self.frames {
+                let bytes = BASE64.decode(&f.png).map_err(|e| format!("embedded frame: {e}"))?;
+                let (img, _) = decode_bytes(&bytes, "png")?.swap_remove(0);
+                out.push((img, f.delay_ms));
+            }
+            return Ok(out);
+        }
+
+        let Some(path) = &self.path else {
+            return Err("project has no source image".to_string());
+        };
+        let path = if path.is_relative() { project_dir.join(path) } else { path.clone() };
+        load_frames(&path, sequences).map_err(|e| format!("{}: {e}", path.display()))
+    }
+}
+
+impl Project {
+    pub fn from_json(text: &str) -> Result<Project, String> {
+        let project: Project = serde_json::from_str(text).map_err(|e| format!("invalid project: {e}"))?;
+        if project.version > PROJECT_VERSION {
+            return Err(format!("project version {} is newer than supported ({PROJECT_VERSION})", project.version));
+        }
+        project.validate().map_err(|e| format!("invalid project: {e}"))?;
+        Ok(project)
+    }
+
+    /// Checks what a hand-edited file could get wrong before it reaches the pipeline.
+    fn validate(&self) -> Result<(), String> {
+        self.pipeline.validate()?;
+        // a linked source's frames are only counted when it is decoded, see `render`
+        let frames = if self.source.frames.is_empty() { self.palettes.len() } else { self.source.frames.len() };
+        self.check_palette_slots(frames)?;
+        for (name, palettes) in [("palettes", &self.palettes), ("overrides", &self.overrides)] {
+            if let Some(p) = palettes.iter().find(|p| !(1..=MAX_PALETTE_COLORS).contains(&p.len())) {
+                return Err(format!("{name} must have 1..={MAX_PALETTE_COLORS} colors each, got {}", p.len()));
+            }
+        }
+        Ok(())
+    }
+
+    /// One palette for all frames or one per frame, and the overrides in the same layout.
+    fn check_palette_slots(&self, frames: usize) -> Result<(), String> {
+        if self.palettes.is_empty() {
+            return Err("project has no palette".to_string());
+        }
+        if self.palettes.len() != 1 && self.palettes.len() != frames {
+            return Err(format!("project has {} palettes for {frames} frame(s)", self.palettes.len()));
+        }
+        if !self.overrides.is_empty() && self.overrides.len() != self.palettes.len() {
+            return Err(format!("project has {} palette overrides for {} palettes", self.overrides.len(), self.palettes.len()));
+        }
+        Ok(())
+    }
+
+    pub fn load(path: &Path) -> Result<Project, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {e}", path.display()))?;
+        Project::from_json(&text).map_err(|e| format!("{}: {e}", path.display()))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let json = serde_json::to_string(self).map_err(|e| e.to_string())?;
+        std::fs::write(path, json).map_err(|e| format!("{}: {e}", path.display()))
+    }
+
+    /// Re-creates the output: the source is dithered with the stored palettes (the quantizer
+    /// is not run again) and the overrides are applied on top.
+    pub fn render(&self, decoded: Vec<(RgbaImage, u32)>) -> Result<ProcessedImage, String> {
+        self.check_palette_slots(decoded.len())?;
+
+        let (mut frames, w, h) = prepare_frames(decoded, &self.pipeline);
+        dither_frames(&mut frames, &self.palettes, &self.pipeline);
+        apply_palette_overrides(&mut frames, &self.palettes, &self.overrides);
+
+        let palettes = if self.overrides.len() == self.palettes.len() { self.overrides.clone() } else { self.palettes.clone() };
+        Ok(ProcessedImage { frames, palettes, w, h })
+    }
+}
+
+/// Folder that relative source paths of the project at `path` are resolved against.
+pub fn project_dir(path: &Path) -> PathBuf {
+    path.parent().map(|p| p.to_path_buf()).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::classes::c_rgb16::Rgb16;
+
+    fn project(palettes: usize, overrides: usize, colors: usize) -> Project {
+        let palette = Palette::new(vec![Rgb16::default(); colors]);
+        Project {
+            source: ProjectSource { path: None, frames: vec![EmbeddedFrame::default(); 2] },
+            palettes: vec![palette.clone(); palettes],
+            overrides: vec![palette; overrides],
+            ..Default::default()
+        }
+    }
+
+    fn load(project: &Project) -> Result<Project, String> {
+        Project::from_json(&serde_json::to_string(project).unwrap())
+    }
+
+    #[test]
+    fn valid_layouts_load() {
+        assert!(load(&project(1, 0, 4)).is_ok());
+        assert!(load(&project(1, 1, 4)).is_ok());
+        assert!(load(&project(2, 2, 256)).is_ok());
+    }
+
+    #[test]
+    fn bad_values_are_rejected() {
+        let mut bad_settings = project(1, 1, 4);
+        bad_settings.pipeline.colors = 0;
+        for (project, message) in [
+            (bad_settings, "colors"),
+            (project(0, 0, 4), "no palette"),
+            (project(3, 3, 4), "3 palettes for 2 frame(s)"),
+            (project(2, 1, 4), "1 palette overrides"),
+            (project(1, 1, 257), "palettes must have"),
+            (project(1, 1, 0), "palettes must have"),
+        ] {
+            let e = load(&project).unwrap_err();
+            assert!(e.contains(message), "{e}");
+        }
+
+        let mut edited = project(1, 1, 4);
+        edited.overrides[0] = Palette::new(vec![Rgb16::default(); 300]);
+        assert!(load(&edited).unwrap_err().contains("overrides must have"));
+    }
+
+    #[test]
+    fn render_checks_the_frame_count() {
+        let mut linked = project(3, 0, 4);
+        linked.source.frames.clear();
+        assert!(load(&linked).is_ok());
+        let decoded = vec![(RgbaImage::new(2, 2), 0); 2];
+        let Err(e) = linked.render(decoded) else { panic!("rendered 2 frames with 3 palettes") };
+        assert!(e.contains("3 palettes for 2 frame(s)"), "{e}");
+    }
+}
@@ -0,0 +1,44 @@
+﻿use serde::{Deserialize, Serialize};
+use crate::classes::c_kmeans::KMeans;
+use crate::classes::c_median_cut::MedianCut;
+use crate::classes::t_quantizer::Quantizer;
+
+/// Serializable choice of the built-in quantizers.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum QuantizerKind {
+    #[default]
+    MedianCut,
+    /// Median cut refined with a few k-means (Lloyd) iterations.
+    KMeans,
+}
+
+impl QuantizerKind {
+    pub const ALL: [QuantizerKind; 2] = [QuantizerKind::MedianCut, QuantizerKind::KMeans];
+
+    pub fn quantizer(&self) -> Box<dyn Quantizer> {
+        match self {
+            QuantizerKind::MedianCut => Box::new(MedianCut),
+            QuantizerKind::KMeans => Box::new(KMeans::default()),
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            QuantizerKind::MedianCut => "Median Cut",
+            QuantizerKind::KMeans => "Median Cut + K-Means",
+        }
+    }
+
+    /// Name used on the command line.
+    pub fn cli_name(&self) -> &'static str {
+        match self {
+            QuantizerKind::MedianCut => "median-cut",
+            QuantizerKind::KMeans => "kmeans",
+        }
+    }
+
+    pub fn from_cli_name(name: &str) -> Option<QuantizerKind> {
+        Self::ALL.into_iter().find(|q| q.cli_name().eq_ignore_ascii_case(name))
+    }
+}
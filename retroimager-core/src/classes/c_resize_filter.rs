@@ -0,0 +1,55 @@
+﻿use image::imageops::FilterType;
+use serde::{Deserialize, Serialize};
+
+/// Filter used when the source is scaled before quantizing.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ResizeFilter {
+    /// Keeps hard pixel edges, best for pixel art.
+    #[default]
+    Nearest,
+    Bilinear,
+    CatmullRom,
+    Lanczos3,
+}
+
+impl ResizeFilter {
+    pub const ALL: [ResizeFilter; 4] = [
+        ResizeFilter::Nearest,
+        ResizeFilter::Bilinear,
+        ResizeFilter::CatmullRom,
+        ResizeFilter::Lanczos3,
+    ];
+
+    pub fn filter_type(&self) -> FilterType {
+        match self {
+            ResizeFilter::Nearest => FilterType::Nearest,
+            ResizeFilter::Bilinear => FilterType::Triangle,
+            ResizeFilter::CatmullRom => FilterType::CatmullRom,
+            ResizeFilter::Lanczos3 => FilterType::Lanczos3,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ResizeFilter::Nearest => "Nearest",
+            ResizeFilter::Bilinear => "Bilinear",
+            ResizeFilter::CatmullRom => "Catmull-Rom",
+            ResizeFilter::Lanczos3 => "Lanczos 3",
+        }
+    }
+
+    /// Name used on the command line.
+    pub fn cli_name(&self) -> &'static str {
+        match self {
+            ResizeFilter::Nearest => "nearest",
+            ResizeFilter::Bilinear => "bilinear",
+            ResizeFilter::CatmullRom => "catmull-rom",
+            ResizeFilter::Lanczos3 => "lanczos3",
+        }
+    }
+
+    pub fn from_cli_name(name: &str) -> Option<ResizeFilter> {
+        Self::ALL.into_iter().find(|f| f.cli_name().eq_ignore_ascii_case(name))
+    }
+}
@@ -0,0 +1,9 @@
+﻿use serde::{Deserialize, Serialize};
+
+/// RGB color with 16 bits per channel (0..=65535).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Rgb16 {
+    pub r: u16,
+    pub g: u16,
+    pub b: u16,
+}
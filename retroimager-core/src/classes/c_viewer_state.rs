@@ -0,0 +1,19 @@
+﻿use serde::{Deserialize, Serialize};
+
+/// How the image was being looked at when a project was saved.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ViewerState {
+    pub current_frame: usize,
+    /// Dithered output (true) or the scaled source (false).
+    pub show_dithered: bool,
+}
+
+impl Default for ViewerState {
+    fn default() -> Self {
+        Self {
+            current_frame: 0,
+            show_dithered: true,
+        }
+    }
+}
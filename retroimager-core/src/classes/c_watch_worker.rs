@@ -0,0 +1,99 @@
+﻿use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver};
+use std::time::Duration;
+use crate::classes::c_batch_job::BatchItem;
+use crate::classes::c_folder_watcher::FolderWatcher;
+
+/// Polls a [`FolderWatcher`] every `interval` on a background thread, so converting the
+/// files it picks up doesn't hold up the caller.
+pub struct WatchWorker {
+    updates: Receiver<Result<Vec<BatchItem>, String>>,
+    cancel: Arc<AtomicBool>,
+}
+
+impl WatchWorker {
+    /// Starts watching. `notify` is called from the worker thread after every reported poll.
+    pub fn new(mut watcher: FolderWatcher, interval: Duration, notify: impl Fn() + Send + 'static) -> Self {
+        let (tx, updates) = channel();
+        let cancel = Arc::new(AtomicBool::new(false));
+        let cancelled = cancel.clone();
+
+        // not joined, like the batch worker: a conversion in progress finishes in the background
+        std::thread::spawn(move || {
+            let mut failing = false;
+            while !cancelled.load(Ordering::Relaxed) {
+                let result = watcher.poll();
+                // quiet polls aren't sent, except the first one after an error so it can be cleared
+                let report = match &result {
+                    Ok(items) => !items.is_empty() || failing,
+                    Err(_) => true,
+                };
+                failing = result.is_err();
+                if report {
+                    if tx.send(result).is_err() {
+                        return;
+                    }
+                    notify();
+                }
+                std::thread::sleep(interval);
+            }
+        });
+
+        Self { updates, cancel }
+    }
+
+    /// Polls reported since the last call, oldest first: the files they converted (or
+    /// failed on), or why the folder couldn't be read.
+    pub fn poll(&self) -> impl Iterator<Item = Result<Vec<BatchItem>, String>> + '_ {
+        self.updates.try_iter()
+    }
+
+    /// Stops after the file being converted.
+    pub fn cancel(&self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+}
+
+impl Drop for WatchWorker {
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+    use crate::classes::c_batch_job::BatchStatus;
+    use crate::classes::c_export_settings::ExportSettings;
+    use crate::classes::c_pipeline_settings::PipelineSettings;
+
+    #[test]
+    fn converts_new_files() {
+        let dir = std::env::temp_dir().join(format!("retroimager-{}-watch-worker", std::process::id()));
+        let (input, output) = (dir.join("in"), dir.join("out"));
+        std::fs::create_dir_all(&input).unwrap();
+        let watcher = FolderWatcher::new(input.to_str().unwrap(), &output, "{name}.png", &PipelineSettings::default(), &ExportSettings::default(), Duration::ZERO).unwrap();
+        let worker = WatchWorker::new(watcher, Duration::from_millis(5), || {});
+
+        // written next to the folder and moved in, so a poll never sees half a file
+        let staged = dir.join("new.png");
+        image::RgbaImage::from_pixel(2, 2, image::Rgba([0, 90, 200, 255])).save(&staged).unwrap();
+        std::fs::rename(&staged, input.join("new.png")).unwrap();
+        let start = Instant::now();
+        let mut items = vec![];
+        while items.is_empty() {
+            assert!(start.elapsed() < Duration::from_secs(30), "file was not converted");
+            for result in worker.poll() {
+                items.extend(result.unwrap());
+            }
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        assert_eq!(items[0].status, BatchStatus::Done);
+        assert!(output.join("new.png").is_file());
+
+        drop(worker);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,11 @@
+﻿use crate::classes::c_image_buffer16::ImageBuffer16;
+use crate::classes::c_palette::Palette;
+
+/// Maps an image to a fixed palette.
+pub trait Ditherer {
+    /// Writes RGBA8 output into `dst8` (resized to `w * h * 4`), alpha is kept from `src`,
+    /// and the palette entry of every pixel into `indices` (resized to `w * h`).
+    /// `prev` is the previous frame (source, output) for temporal coherence; ditherers
+    /// that are stable between frames anyway may ignore it.
+    fn dither(&self, src: &ImageBuffer16, palette: &Palette, prev: Option<(&ImageBuffer16, &[u8])>, dst8: &mut Vec<u8>, indices: &mut Vec<u8>);
+}
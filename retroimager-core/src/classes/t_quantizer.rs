@@ -0,0 +1,8 @@
+﻿use crate::classes::c_palette::Palette;
+use crate::classes::c_rgb16::Rgb16;
+
+/// Builds a palette from color samples.
+pub trait Quantizer {
+    /// At most `k` colors; fewer when the samples don't have enough distinct colors.
+    fn quantize(&self, samples: Vec<Rgb16>, k: usize) -> Palette;
+}
@@ -0,0 +1,169 @@
+﻿use crate::classes::c_rgb16::Rgb16;
+
+// Color space conversions for palette tools (sorting, merging, editing). Inputs are
+// sRGB-encoded 16-bit channels; the results are floats.
+
+fn unit(v: u16) -> f32 {
+    v as f32 / 65535.0
+}
+
+fn channel(v: f32) -> u16 {
+    (v.clamp(0.0, 1.0) * 65535.0).round() as u16
+}
+
+/// Hue in degrees from normalized channels, their maximum and the chroma `d`.
+fn hue(r: f32, g: f32, b: f32, max: f32, d: f32) -> f32 {
+    if d == 0.0 {
+        0.0
+    } else if max == r {
+        60.0 * ((g - b) / d).rem_euclid(6.0)
+    } else if max == g {
+        60.0 * ((b - r) / d + 2.0)
+    } else {
+        60.0 * ((r - g) / d + 4.0)
+    }
+}
+
+/// Color with hue `h` (degrees), chroma `c` and offset `m`, the common tail of HSV and HSL.
+fn from_hue_chroma(h: f32, c: f32, m: f32) -> Rgb16 {
+    let h = h.rem_euclid(360.0) / 60.0;
+    let x = c * (1.0 - (h.rem_euclid(2.0) - 1.0).abs());
+    let (r, g, b) = match h as u32 {
+        0 => (c, x, 0.0),
+        1 => (x, c, 0.0),
+        2 => (0.0, c, x),
+        3 => (0.0, x, c),
+        4 => (x, 0.0, c),
+        _ => (c, 0.0, x),
+    };
+    Rgb16 { r: channel(r + m), g: channel(g + m), b: channel(b + m) }
+}
+
+/// sRGB transfer curve, encoded 0..1 → linear 0..1.
+pub fn srgb_to_linear(v: f32) -> f32 {
+    if v <= 0.04045 { v / 12.92 } else { ((v + 0.055) / 1.055).powf(2.4) }
+}
+
+/// Inverse of [`srgb_to_linear`].
+pub fn linear_to_srgb(v: f32) -> f32 {
+    if v <= 0.003_130_8 { v * 12.92 } else { 1.055 * v.powf(1.0 / 2.4) - 0.055 }
+}
+
+/// Relative luminance (Rec. 709 weights on linear light), 0..1.
+pub fn luminance(c: Rgb16) -> f32 {
+    0.2126 * srgb_to_linear(unit(c.r)) + 0.7152 * srgb_to_linear(unit(c.g)) + 0.0722 * srgb_to_linear(unit(c.b))
+}
+
+/// Hue in degrees (0..360), saturation and value (0..1).
+pub fn rgb16_to_hsv(c: Rgb16) -> (f32, f32, f32) {
+    let (r, g, b) = (unit(c.r), unit(c.g), unit(c.b));
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let d = max - min;
+    let s = if max == 0.0 { 0.0 } else { d / max };
+    (hue(r, g, b, max, d), s, max)
+}
+
+/// Inverse of [`rgb16_to_hsv`].
+pub fn hsv_to_rgb16(h: f32, s: f32, v: f32) -> Rgb16 {
+    let (s, v) = (s.clamp(0.0, 1.0), v.clamp(0.0, 1.0));
+    let c = v * s;
+    from_hue_chroma(h, c, v - c)
+}
+
+/// Hue in degrees (0..360), saturation and lightness (0..1).
+pub fn rgb16_to_hsl(c: Rgb16) -> (f32, f32, f32) {
+    let (r, g, b) = (unit(c.r), unit(c.g), unit(c.b));
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let d = max - min;
+    let l = (max + min) / 2.0;
+    let s = if d == 0.0 { 0.0 } else { d / (1.0 - (2.0 * l - 1.0).abs()) };
+    (hue(r, g, b, max, d), s.min(1.0), l)
+}
+
+/// Inverse of [`rgb16_to_hsl`].
+pub fn hsl_to_rgb16(h: f32, s: f32, l: f32) -> Rgb16 {
+    let (s, l) = (s.clamp(0.0, 1.0), l.clamp(0.0, 1.0));
+    let c = (1.0 - (2.0 * l - 1.0).abs()) * s;
+    from_hue_chroma(h, c, l - c / 2.0)
+}
+
+/// OKLab of linear sRGB.
+fn linear_to_oklab(r: f32, g: f32, b: f32) -> [f32; 3] {
+    let l = (0.412_221_46 * r + 0.536_332_55 * g + 0.051_445_995 * b).cbrt();
+    let m = (0.211_903_5 * r + 0.680_699_5 * g + 0.107_396_96 * b).cbrt();
+    let s = (0.088_302_46 * r + 0.281_718_85 * g + 0.629_978_7 * b).cbrt();
+    [
+        0.210_454_26 * l + 0.793_617_8 * m - 0.004_072_047 * s,
+        1.977_998_5 * l - 2.428_592_2 * m + 0.450_593_7 * s,
+        0.025_904_037 * l + 0.782_771_77 * m - 0.808_675_77 * s,
+    ]
+}
+
+/// Linear sRGB of OKLab, not clamped to the gamut.
+fn oklab_to_linear(lab: [f32; 3]) -> [f32; 3] {
+    let l = (lab[0] + 0.396_337_78 * lab[1] + 0.215_803_76 * lab[2]).powi(3);
+    let m = (lab[0] - 0.105_561_346 * lab[1] - 0.063_854_17 * lab[2]).powi(3);
+    let s = (lab[0] - 0.089_484_18 * lab[1] - 1.291_485_5 * lab[2]).powi(3);
+    [
+        4.076_741_7 * l - 3.307_711_6 * m + 0.230_969_94 * s,
+        -1.268_438 * l + 2.609_757_4 * m - 0.341_319_38 * s,
+        -0.004_196_086_3 * l - 0.703_418_6 * m + 1.707_614_7 * s,
+    ]
+}
+
+/// OKLCh: lightness (0..1), chroma (0..~0.37) and hue in degrees (0..360).
+pub fn rgb16_to_oklch(c: Rgb16) -> (f32, f32, f32) {
+    let [l, a, b] = linear_to_oklab(srgb_to_linear(unit(c.r)), srgb_to_linear(unit(c.g)), srgb_to_linear(unit(c.b)));
+    (l, a.hypot(b), b.atan2(a).to_degrees().rem_euclid(360.0))
+}
+
+/// Inverse of [`rgb16_to_oklch`]. Colors outside sRGB keep lightness and hue and get the
+/// largest chroma that fits.
+pub fn oklch_to_rgb16(l: f32, c: f32, h: f32) -> Rgb16 {
+    let l = l.clamp(0.0, 1.0);
+    let h = h.to_radians();
+    let lab = |c: f32| oklab_to_linear([l, c * h.cos(), c * h.sin()]);
+    let fits = |rgb: [f32; 3]| rgb.iter().all(|v| (-1e-4..=1.0 + 1e-4).contains(v));
+
+    let mut rgb = lab(c.max(0.0));
+    if !fits(rgb) {
+        let (mut lo, mut hi) = (0.0, c.max(0.0));
+        for _ in 0..20 {
+            let mid = (lo + hi) / 2.0;
+            if fits(lab(mid)) { lo = mid } else { hi = mid }
+        }
+        rgb = lab(lo);
+    }
+    Rgb16 {
+        r: channel(linear_to_srgb(rgb[0].max(0.0))),
+        g: channel(linear_to_srgb(rgb[1].max(0.0))),
+        b: channel(linear_to_srgb(rgb[2].max(0.0))),
+    }
+}
+
+/// `c` with the hue rotated by `hue` degrees and saturation and value scaled by
+/// `1 + saturation` and `1 + brightness` (both -1..=1), as used to shift a whole palette.
+pub fn shift_color(c: Rgb16, hue: f32, saturation: f32, brightness: f32) -> Rgb16 {
+    let (h, s, v) = rgb16_to_hsv(c);
+    hsv_to_rgb16(h + hue, s * (1.0 + saturation), v * (1.0 + brightness))
+}
+
+/// CIE L*a*b* (D65). L is 0..100.
+pub fn rgb16_to_lab(c: Rgb16) -> [f32; 3] {
+    let (r, g, b) = (srgb_to_linear(unit(c.r)), srgb_to_linear(unit(c.g)), srgb_to_linear(unit(c.b)));
+    let x = (0.412_456_4 * r + 0.357_576_1 * g + 0.180_437_5 * b) / 0.950_47;
+    let y = 0.212_672_9 * r + 0.715_152_2 * g + 0.072_175 * b;
+    let z = (0.019_333_9 * r + 0.119_192 * g + 0.950_304_1 * b) / 1.088_83;
+
+    let f = |t: f32| if t > 216.0 / 24389.0 { t.cbrt() } else { (24389.0 / 27.0 * t + 16.0) / 116.0 };
+    let (fx, fy, fz) = (f(x), f(y), f(z));
+    [116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz)]
+}
+
+/// CIE76 ΔE: Euclidean distance in L*a*b*. About 2.3 is a just noticeable difference.
+pub fn delta_e(a: Rgb16, b: Rgb16) -> f32 {
+    let (la, lb) = (rgb16_to_lab(a), rgb16_to_lab(b));
+    ((la[0] - lb[0]).powi(2) + (la[1] - lb[1]).powi(2) + (la[2] - lb[2]).powi(2)).sqrt()
+}
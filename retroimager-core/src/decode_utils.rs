@@ -0,0 +1,217 @@
+﻿use std::collections::HashMap;
+use std::fs;
+use std::fs::File;
+use std::io::{BufReader, Cursor};
+use std::path::{Path, PathBuf};
+use image::{AnimationDecoder, Frame, RgbaImage};
+use image::codecs::gif::GifDecoder;
+use image::codecs::png::PngDecoder;
+
+/// Used when a GIF frame has no delay or the frames come from a numbered sequence.
+pub const DEFAULT_FRAME_DELAY_MS: u32 = 100;
+
+/// Decodes `path` into a list of full-canvas RGBA frames with their delays in ms.
+/// Animated GIF/APNG give all their frames, "name_0001.png" gives the whole zero-padded
+/// numbered sequence when `sequences` is set, anything else gives a single frame.
+pub fn load_frames(path: &Path, sequences: bool) -> Result<Vec<(RgbaImage, u32)>, String> {
+    let ext = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or("")
+        .to_ascii_lowercase();
+
+    match ext.as_str() {
+        "gif" => {
+            let decoder = GifDecoder::new(open_reader(path)?).map_err(|e| e.to_string())?;
+            return collect_frames(decoder.into_frames().collect_frames().map_err(|e| e.to_string())?);
+        }
+        "png" | "apng" => {
+            let decoder = PngDecoder::new(open_reader(path)?).map_err(|e| e.to_string())?;
+            if decoder.is_apng().map_err(|e| e.to_string())? {
+                let apng = decoder.apng().map_err(|e| e.to_string())?;
+                return collect_frames(apng.into_frames().collect_frames().map_err(|e| e.to_string())?);
+            }
+
+            if sequences {
+                let seq = find_numbered_sequence(path);
+                if seq.len() > 1 {
+                    return load_sequence(&seq);
+                }
+            }
+        }
+        _ => {}
+    }
+
+    let img = image::open(path).map_err(|e| e.to_string())?;
+    Ok(vec![(img.to_rgba8(), 0)])
+}
+
+/// Like [`load_frames`] for a file already in memory; `ext` picks GIF/APNG handling.
+pub fn decode_bytes(bytes: &[u8], ext: &str) -> Result<Vec<(RgbaImage, u32)>, String> {
+    match ext.to_ascii_lowercase().as_str() {
+        "gif" => {
+            let decoder = GifDecoder::new(Cursor::new(bytes)).map_err(|e| e.to_string())?;
+            return collect_frames(decoder.into_frames().collect_frames().map_err(|e| e.to_string())?);
+        }
+        "png" | "apng" => {
+            let decoder = PngDecoder::new(Cursor::new(bytes)).map_err(|e| e.to_string())?;
+            if decoder.is_apng().map_err(|e| e.to_string())? {
+                let apng = decoder.apng().map_err(|e| e.to_string())?;
+                return collect_frames(apng.into_frames().collect_frames().map_err(|e| e.to_string())?);
+            }
+        }
+        _ => {}
+    }
+
+    let img = image::load_from_memory(bytes).map_err(|e| e.to_string())?;
+    Ok(vec![(img.to_rgba8(), 0)])
+}
+
+fn open_reader(path: &Path) -> Result<BufReader<File>, String> {
+    File::open(path).map(BufReader::new).map_err(|e| e.to_string())
+}
+
+fn collect_frames(frames: Vec<Frame>) -> Result<Vec<(RgbaImage, u32)>, String> {
+    if frames.is_empty() {
+        return Err("animation has no frames".to_string());
+    }
+
+    Ok(frames
+        .into_iter()
+        .map(|f| {
+            let (n, d) = f.delay().numer_denom_ms();
+            let ms = n.checked_div(d).unwrap_or(0);
+            (f.into_buffer(), if ms == 0 { DEFAULT_FRAME_DELAY_MS } else { ms })
+        })
+        .collect())
+}
+
+fn load_sequence(paths: &[PathBuf]) -> Result<Vec<(RgbaImage, u32)>, String> {
+    let mut out: Vec<(RgbaImage, u32)> = Vec::with_capacity(paths.len());
+    for p in paths {
+        let img = image::open(p)
+            .map_err(|e| format!("{}: {e}", p.display()))?
+            .to_rgba8();
+
+        if let Some((first, _)) = out.first()
+            && first.dimensions() != img.dimensions() {
+            return Err(format!("{}: frame size differs from the first frame", p.display()));
+        }
+        out.push((img, DEFAULT_FRAME_DELAY_MS));
+    }
+    Ok(out)
+}
+
+/// Splits "walk_0012.png" into ("walk_", "0012", ".png"). None if the stem has no trailing digits.
+fn split_stem(path: &Path) -> Option<(&str, &str, String)> {
+    let stem = path.file_stem()?.to_str()?;
+    let digits = stem.chars().rev().take_while(|c| c.is_ascii_digit()).count();
+    if digits == 0 {
+        return None;
+    }
+
+    let (prefix, number) = stem.split_at(stem.len() - digits);
+    let ext = match path.extension().and_then(|e| e.to_str()) {
+        Some(e) => format!(".{e}"),
+        None => String::new(),
+    };
+    Some((prefix, number, ext))
+}
+
+/// Splits "walk_0012.png" into ("walk_", 12, ".png"). None if the stem has no trailing digits.
+pub fn split_numbered_name(path: &Path) -> Option<(String, u64, String)> {
+    let (prefix, number, ext) = split_stem(path)?;
+    Some((prefix.to_string(), number.parse().ok()?, ext))
+}
+
+/// The zero-padded, gapless numbering `path` is part of: the files next to it with the
+/// same prefix, extension and digit count whose numbers run on from its own without a
+/// gap, sorted by number. Returns just `path` when there is no such sequence, so e.g.
+/// "photo_2023.png" and "photo_2024.png" stay separate images.
+pub fn find_numbered_sequence(path: &Path) -> Vec<PathBuf> {
+    let single = || vec![path.to_path_buf()];
+    let Some((prefix, digits, ext)) = split_stem(path) else {
+        return single();
+    };
+    let Ok(number) = digits.parse::<u64>() else {
+        return single();
+    };
+    let Some(dir) = path.parent() else {
+        return single();
+    };
+    let Ok(entries) = fs::read_dir(if dir.as_os_str().is_empty() { Path::new(".") } else { dir }) else {
+        return single();
+    };
+
+    let mut found: HashMap<u64, PathBuf> = entries
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.is_file())
+        .filter_map(|p| {
+            let (pr, d, ex) = split_stem(&p)?;
+            if pr == prefix && d.len() == digits.len() && ex.eq_ignore_ascii_case(&ext) { Some((d.parse().ok()?, p)) } else { None }
+        })
+        .collect();
+    if !found.contains_key(&number) {
+        return single();
+    }
+
+    let mut first = number;
+    while let Some(prev) = first.checked_sub(1)
+        && found.contains_key(&prev) {
+        first = prev;
+    }
+    let mut last = number;
+    while let Some(next) = last.checked_add(1)
+        && found.contains_key(&next) {
+        last = next;
+    }
+
+    // frame counters are padded ("0001"); unpadded numbers are more likely dates or ids
+    let padded = digits.len() > 1 && first < 10u64.pow(digits.len() as u32 - 1);
+    if first == last || !padded {
+        return single();
+    }
+    (first..=last).filter_map(|n| found.remove(&n)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(paths: &[PathBuf]) -> Vec<String> {
+        paths.iter().map(|p| p.file_name().unwrap().to_string_lossy().into_owned()).collect()
+    }
+
+    #[test]
+    fn numbered_sequences_need_padding_and_no_gaps() {
+        let dir = std::env::temp_dir().join(format!("retroimager-{}-sequence", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for name in [
+            "walk_0001.png", "walk_0002.PNG", "walk_0003.png", "walk_0005.png", "walk_0006.png", "walk_001.png",
+            "photo_2023.png", "photo_2024.png", "frame1.png", "frame2.png", "run_0099.png", "run_0100.png",
+            "solo_0001.png",
+        ] {
+            fs::write(dir.join(name), b"").unwrap();
+        }
+        let seq = |name: &str| names(&find_numbered_sequence(&dir.join(name)));
+
+        assert_eq!(seq("walk_0002.PNG"), ["walk_0001.png", "walk_0002.PNG", "walk_0003.png"]);
+        assert_eq!(seq("walk_0006.png"), ["walk_0005.png", "walk_0006.png"]);
+        assert_eq!(seq("walk_001.png"), ["walk_001.png"]);
+        assert_eq!(seq("photo_2023.png"), ["photo_2023.png"]);
+        assert_eq!(seq("frame1.png"), ["frame1.png"]);
+        assert_eq!(seq("run_0100.png"), ["run_0099.png", "run_0100.png"]);
+        assert_eq!(seq("solo_0001.png"), ["solo_0001.png"]);
+        assert_eq!(seq("missing_0001.png"), ["missing_0001.png"]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn split_numbered_name_parts() {
+        assert_eq!(split_numbered_name(Path::new("a/walk_0012.png")), Some(("walk_".to_string(), 12, ".png".to_string())));
+        assert_eq!(split_numbered_name(Path::new("7")), Some((String::new(), 7, String::new())));
+        assert_eq!(split_numbered_name(Path::new("walk.png")), None);
+    }
+}
@@ -0,0 +1,630 @@
+﻿use std::borrow::Cow;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::BufWriter;
+use std::path::Path;
+use crate::classes::c_export_settings::ExportSettings;
+use crate::classes::c_indexed_image::IndexedImage;
+use crate::classes::c_rgb16::Rgb16;
+use crate::image_utils::u16_to_u8;
+
+/// Smallest number of bitplanes (1..=8) that can address `colors` entries.
+pub fn bit_planes_for(colors: usize) -> u8 {
+    let mut planes = 1u8;
+    while planes < 8 && (1usize << planes) < colors {
+        planes += 1;
+    }
+    planes
+}
+
+fn push_u16_be(out: &mut Vec<u8>, v: u16) {
+    out.extend_from_slice(&v.to_be_bytes());
+}
+
+fn push_u32_be(out: &mut Vec<u8>, v: u32) {
+    out.extend_from_slice(&v.to_be_bytes());
+}
+
+fn push_chunk(out: &mut Vec<u8>, id: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(id);
+    push_u32_be(out, data.len() as u32);
+    out.extend_from_slice(data);
+    // IFF chunks are word aligned
+    if data.len() % 2 == 1 {
+        out.push(0);
+    }
+}
+
+/// ByteRun1 (PackBits) compression of one row.
+/// n in 0..=127 -> copy next n+1 bytes, n in -127..=-1 -> repeat next byte -n+1 times.
+pub fn byterun1_pack(src: &[u8], out: &mut Vec<u8>) {
+    let mut i = 0;
+    while i < src.len() {
+        let mut run = 1;
+        while i + run < src.len() && run < 128 && src[i + run] == src[i] {
+            run += 1;
+        }
+
+        if run >= 3 {
+            out.push((257 - run) as u8);
+            out.push(src[i]);
+            i += run;
+            continue;
+        }
+
+        // literal until the next run of 3 or 128 bytes
+        let start = i;
+        while i < src.len() && i - start < 128 {
+            if i + 2 < src.len() && src[i] == src[i + 1] && src[i] == src[i + 2] {
+                break;
+            }
+            i += 1;
+        }
+        out.push((i - start - 1) as u8);
+        out.extend_from_slice(&src[start..i]);
+    }
+}
+
+/// FORM ILBM with BMHD, CMAP and an interleaved BODY.
+/// Plane count follows the palette size, the colormap is padded to 2^planes entries.
+pub fn encode_ilbm(img: &IndexedImage, compress: bool) -> Vec<u8> {
+    assert_eq!(img.indices.len(), img.w * img.h);
+
+    let planes = bit_planes_for(img.palette.len());
+    let row_bytes = img.w.div_ceil(16) * 2;
+
+    // BMHD
+    let mut bmhd = Vec::with_capacity(20);
+    push_u16_be(&mut bmhd, img.w as u16);
+    push_u16_be(&mut bmhd, img.h as u16);
+    push_u16_be(&mut bmhd, 0); // x
+    push_u16_be(&mut bmhd, 0); // y
+    bmhd.push(planes);
+    bmhd.push(if img.transparent.is_some() { 2 } else { 0 }); // mskHasTransparentColor
+    bmhd.push(if compress { 1 } else { 0 });
+    bmhd.push(0); // pad
+    push_u16_be(&mut bmhd, img.transparent.unwrap_or(0) as u16);
+    bmhd.push(1); // x aspect
+    bmhd.push(1); // y aspect
+    push_u16_be(&mut bmhd, img.w as u16);
+    push_u16_be(&mut bmhd, img.h as u16);
+
+    // CMAP
+    let mut cmap = Vec::with_capacity(3 << planes);
+    for i in 0..(1usize << planes) {
+        let c = img.palette.get(i).copied().unwrap_or_default();
+        cmap.push(u16_to_u8(c.r));
+        cmap.push(u16_to_u8(c.g));
+        cmap.push(u16_to_u8(c.b));
+    }
+
+    // BODY
+    let mut body = Vec::with_capacity(row_bytes * planes as usize * img.h);
+    let mut row = vec![0u8; row_bytes];
+    for y in 0..img.h {
+        let line = &img.indices[y * img.w..(y + 1) * img.w];
+        for plane in 0..planes {
+            row.fill(0);
+            for (x, &id) in line.iter().enumerate() {
+                if (id >> plane) & 1 == 1 {
+                    row[x >> 3] |= 0x80 >> (x & 7);
+                }
+            }
+            if compress {
+                byterun1_pack(&row, &mut body);
+            } else {
+                body.extend_from_slice(&row);
+            }
+        }
+    }
+
+    let mut form = Vec::with_capacity(body.len() + cmap.len() + 64);
+    form.extend_from_slice(b"ILBM");
+    push_chunk(&mut form, b"BMHD", &bmhd);
+    push_chunk(&mut form, b"CMAP", &cmap);
+    push_chunk(&mut form, b"BODY", &body);
+
+    let mut out = Vec::with_capacity(form.len() + 8);
+    push_chunk(&mut out, b"FORM", &form);
+    out
+}
+
+pub fn write_ilbm(path: &Path, img: &IndexedImage, compress: bool) -> io::Result<()> {
+    if img.w > u16::MAX as usize || img.h > u16::MAX as usize {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "image is too large for ILBM"));
+    }
+    if img.palette.is_empty() || img.palette.len() > 256 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "palette must have 1..=256 colors"));
+    }
+    fs::write(path, encode_ilbm(img, compress))
+}
+
+
+/// Flat `[r, g, b, ...]` table as stored in PNG/GIF palettes.
+pub fn palette_to_rgb8(palette: &[Rgb16]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(palette.len() * 3);
+    for c in palette {
+        out.push(u16_to_u8(c.r));
+        out.push(u16_to_u8(c.g));
+        out.push(u16_to_u8(c.b));
+    }
+    out
+}
+
+/// PNG bit depth (1, 2, 4 or 8) for a palette of `colors` entries.
+pub fn png_bit_depth_for(colors: usize) -> u8 {
+    match bit_planes_for(colors) {
+        1 => 1,
+        2 => 2,
+        3 | 4 => 4,
+        _ => 8,
+    }
+}
+
+/// Packs one index per pixel into rows of `bits`-wide samples, MSB first, rows byte aligned.
+pub fn pack_indices(indices: &[u8], w: usize, h: usize, bits: u8) -> Vec<u8> {
+    assert_eq!(indices.len(), w * h);
+    if bits == 8 {
+        return indices.to_vec();
+    }
+
+    let per_byte = 8 / bits as usize;
+    let row_bytes = w.div_ceil(per_byte);
+    let mask = (1u8 << bits) - 1;
+
+    let mut out = vec![0u8; row_bytes * h];
+    for y in 0..h {
+        let row = &mut out[y * row_bytes..(y + 1) * row_bytes];
+        for x in 0..w {
+            let id = indices[y * w + x] & mask;
+            let shift = 8 - bits as usize * (x % per_byte + 1);
+            row[x / per_byte] |= id << shift;
+        }
+    }
+    out
+}
+
+/// Palette PNG (PLTE + tRNS when the image has a transparent index).
+pub fn write_indexed_png(path: &Path, img: &IndexedImage) -> io::Result<()> {
+    if img.palette.is_empty() || img.palette.len() > 256 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "palette must have 1..=256 colors"));
+    }
+
+    let bits = png_bit_depth_for(img.palette.len());
+    let depth = match bits {
+        1 => png::BitDepth::One,
+        2 => png::BitDepth::Two,
+        4 => png::BitDepth::Four,
+        _ => png::BitDepth::Eight,
+    };
+
+    let plte = palette_to_rgb8(&img.palette);
+
+    let file = File::create(path)?;
+    let mut encoder = png::Encoder::new(BufWriter::new(file), img.w as u32, img.h as u32);
+    encoder.set_color(png::ColorType::Indexed);
+    encoder.set_depth(depth);
+    encoder.set_palette(plte);
+
+    if let Some(t) = img.transparent {
+        // tRNS only has to cover entries up to the transparent one
+        let mut trns = vec![255u8; t as usize + 1];
+        trns[t as usize] = 0;
+        encoder.set_trns(trns);
+    }
+
+    let mut writer = encoder.write_header().map_err(io::Error::other)?;
+    writer
+        .write_image_data(&pack_indices(&img.indices, img.w, img.h, bits))
+        .map_err(io::Error::other)?;
+    writer.finish().map_err(io::Error::other)
+}
+
+
+/// GIF with the first frame's palette as global color table.
+/// Frames with a different palette get a local table. `loop_count`: None - no
+/// NETSCAPE extension (still image), Some(0) - loop forever, Some(n) - n repeats.
+pub fn write_gif(path: &Path, frames: &[IndexedImage], delays_ms: &[u32], loop_count: Option<u16>) -> io::Result<()> {
+    let Some(first) = frames.first() else {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "no frames to write"));
+    };
+    if first.w > u16::MAX as usize || first.h > u16::MAX as usize {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "image is too large for GIF"));
+    }
+    if frames.iter().any(|f| f.palette.is_empty() || f.palette.len() > 256) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "palette must have 1..=256 colors"));
+    }
+    if frames.iter().any(|f| f.w != first.w || f.h != first.h) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "all frames must have the same size"));
+    }
+
+    let global = palette_to_rgb8(&first.palette);
+
+    let file = File::create(path)?;
+    let mut encoder = gif::Encoder::new(BufWriter::new(file), first.w as u16, first.h as u16, &global)
+        .map_err(io::Error::other)?;
+
+    if let Some(n) = loop_count {
+        let repeat = if n == 0 { gif::Repeat::Infinite } else { gif::Repeat::Finite(n) };
+        encoder.set_repeat(repeat).map_err(io::Error::other)?;
+    }
+
+    for (i, img) in frames.iter().enumerate() {
+        let local = palette_to_rgb8(&img.palette);
+        let delay_ms = delays_ms.get(i).copied().unwrap_or(0);
+
+        let frame = gif::Frame {
+            width: img.w as u16,
+            height: img.h as u16,
+            // GIF delays are in 1/100 s
+            delay: ((delay_ms + 5) / 10).min(u16::MAX as u32) as u16,
+            transparent: img.transparent,
+            // clear transparent frames so the previous one doesn't show through
+            dispose: if img.transparent.is_some() { gif::DisposalMethod::Background } else { gif::DisposalMethod::Keep },
+            palette: if local != global { Some(local) } else { None },
+            buffer: Cow::Borrowed(&img.indices),
+            ..Default::default()
+        };
+
+        encoder.write_frame(&frame).map_err(io::Error::other)?;
+    }
+
+    Ok(())
+}
+
+pub const OUTPUT_EXTENSIONS: [&str; 5] = ["png", "gif", "iff", "ilbm", "lbm"];
+
+pub fn is_supported_output(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| OUTPUT_EXTENSIONS.iter().any(|x| x.eq_ignore_ascii_case(e)))
+}
+
+/// Picks the format by extension: indexed PNG, GIF (all frames) or IFF ILBM.
+/// PNG and ILBM hold a single image, `current` selects the frame.
+pub fn write_by_extension(path: &Path, frames: &[IndexedImage], delays_ms: &[u32], current: usize, settings: &ExportSettings) -> io::Result<()> {
+    let ext = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or("")
+        .to_ascii_lowercase();
+
+    let Some(frame) = frames.get(current) else {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "no frames to write"));
+    };
+
+    match ext.as_str() {
+        "png" => write_indexed_png(path, frame),
+        "gif" => {
+            let loop_count = if frames.len() > 1 { Some(settings.gif_loop_count) } else { None };
+            write_gif(path, frames, delays_ms, loop_count)
+        }
+        "iff" | "ilbm" | "lbm" => write_ilbm(path, frame, settings.ilbm_compression),
+        _ => Err(io::Error::new(io::ErrorKind::Unsupported, format!("unsupported output format: .{ext}"))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::classes::c_palette::Palette;
+
+    /// Reverse of [`byterun1_pack`].
+    fn byterun1_unpack(mut src: &[u8]) -> Vec<u8> {
+        let mut out = vec![];
+        while let Some((&n, rest)) = src.split_first() {
+            let n = n as i8;
+            if n >= 0 {
+                let len = n as usize + 1;
+                out.extend_from_slice(&rest[..len]);
+                src = &rest[len..];
+            } else if n != -128 {
+                out.extend(std::iter::repeat_n(rest[0], (1 - n as isize) as usize));
+                src = &rest[1..];
+            } else {
+                src = rest;
+            }
+        }
+        out
+    }
+
+    fn pack(src: &[u8]) -> Vec<u8> {
+        let mut out = vec![];
+        byterun1_pack(src, &mut out);
+        out
+    }
+
+    fn gray_palette(n: usize) -> Palette {
+        Palette::new((0..n).map(|i| {
+            let v = (i * 65535 / n.max(2).saturating_sub(1).max(1)) as u16;
+            Rgb16 { r: v, g: v / 2, b: 65535 - v }
+        }).collect())
+    }
+
+    fn test_image(w: usize, h: usize, colors: usize) -> IndexedImage {
+        let indices = (0..w * h).map(|i| ((i * 7 + i / w) % colors) as u8).collect();
+        IndexedImage { w, h, palette: gray_palette(colors), indices, transparent: None }
+    }
+
+    #[test]
+    fn byterun1_round_trips() {
+        let mut noise = vec![];
+        let mut seed = 7u32;
+        for _ in 0..1000 {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            noise.push((seed >> 24) as u8 & 3);
+        }
+        let cases: Vec<Vec<u8>> = vec![
+            vec![],
+            vec![5],
+            vec![1, 2],
+            vec![9; 3],
+            vec![9; 128],
+            vec![9; 129],
+            vec![9; 300],
+            (0..128).map(|i| i as u8).collect(),
+            (0..129).map(|i| i as u8).collect(),
+            (0..400).map(|i| (i * 31 % 251) as u8).collect(),
+            [vec![1, 2, 3], vec![7; 3], vec![4, 5], vec![8; 2], vec![6]].concat(),
+            noise,
+        ];
+        for case in cases {
+            let packed = pack(&case);
+            assert_eq!(byterun1_unpack(&packed), case, "{case:?}");
+        }
+    }
+
+    #[test]
+    fn byterun1_uses_runs_of_at_most_128() {
+        assert_eq!(pack(&[9; 128]), vec![(257 - 128) as u8, 9]);
+        assert_eq!(pack(&[9; 130]), vec![(257 - 128) as u8, 9, 1, 9, 9]);
+        // a 128 byte literal is one block, the 129th byte starts the next one
+        let literal: Vec<u8> = (0..129).map(|i| i as u8).collect();
+        let packed = pack(&literal);
+        assert_eq!(packed[0], 127);
+        assert_eq!(packed[129], 0);
+        assert_eq!(packed.len(), 131);
+    }
+
+    /// Chunks of an IFF FORM body: (id, data).
+    fn chunks(mut data: &[u8]) -> Vec<([u8; 4], Vec<u8>)> {
+        let mut out = vec![];
+        while data.len() >= 8 {
+            let id: [u8; 4] = data[..4].try_into().unwrap();
+            let len = u32::from_be_bytes(data[4..8].try_into().unwrap()) as usize;
+            out.push((id, data[8..8 + len].to_vec()));
+            data = &data[(8 + len + len % 2).min(data.len())..];
+        }
+        out
+    }
+
+    fn decode_ilbm(bytes: &[u8]) -> (usize, usize, u8, Vec<u8>, Vec<u8>) {
+        let form = chunks(bytes);
+        assert_eq!(&form[0].0, b"FORM");
+        assert_eq!(form[0].1.len() + 8, bytes.len());
+        assert_eq!(&form[0].1[..4], b"ILBM");
+        let inner = chunks(&form[0].1[4..]);
+        let find = |id: &[u8; 4]| inner.iter().find(|c| &c.0 == id).map(|c| c.1.clone()).unwrap();
+
+        let bmhd = find(b"BMHD");
+        assert_eq!(bmhd.len(), 20);
+        let w = u16::from_be_bytes([bmhd[0], bmhd[1]]) as usize;
+        let h = u16::from_be_bytes([bmhd[2], bmhd[3]]) as usize;
+        let planes = bmhd[8];
+        let compressed = bmhd[10] == 1;
+
+        let body = find(b"BODY");
+        let body = if compressed { byterun1_unpack(&body) } else { body };
+        let row_bytes = w.div_ceil(16) * 2;
+        assert_eq!(body.len(), row_bytes * planes as usize * h);
+
+        let mut indices = vec![0u8; w * h];
+        for y in 0..h {
+            for plane in 0..planes as usize {
+                let row = &body[(y * planes as usize + plane) * row_bytes..][..row_bytes];
+                for x in 0..w {
+                    if row[x >> 3] & (0x80 >> (x & 7)) != 0 {
+                        indices[y * w + x] |= 1 << plane;
+                    }
+                }
+            }
+        }
+        (w, h, planes, find(b"CMAP"), indices)
+    }
+
+    #[test]
+    fn ilbm_round_trips_indices_and_colormap() {
+        for (colors, planes) in [(2, 1), (5, 3), (16, 4), (17, 5), (256, 8)] {
+            for compress in [false, true] {
+                let img = test_image(21, 5, colors);
+                let (w, h, p, cmap, indices) = decode_ilbm(&encode_ilbm(&img, compress));
+                assert_eq!((w, h, p), (21, 5, planes));
+                assert_eq!(indices, img.indices);
+                assert_eq!(cmap.len(), 3 << planes);
+                assert_eq!(&cmap[..colors * 3], palette_to_rgb8(&img.palette).as_slice());
+                assert!(cmap[colors * 3..].iter().all(|&b| b == 0));
+            }
+        }
+    }
+
+    #[test]
+    fn ilbm_marks_the_transparent_index() {
+        let mut img = test_image(4, 4, 4);
+        img.transparent = Some(3);
+        let bytes = encode_ilbm(&img, true);
+        let form = chunks(&bytes);
+        let bmhd = &chunks(&form[0].1[4..])[0].1;
+        assert_eq!(bmhd[9], 2);
+        assert_eq!(u16::from_be_bytes([bmhd[12], bmhd[13]]), 3);
+    }
+
+    #[test]
+    fn ilbm_rejects_bad_palette_sizes() {
+        for colors in [0, 257] {
+            let img = IndexedImage { w: 1, h: 1, palette: gray_palette(colors), indices: vec![0], transparent: None };
+            let path = temp_path(&format!("palette-{colors}.iff"));
+            let e = write_ilbm(&path, &img, true).unwrap_err();
+            assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+            assert!(!path.exists());
+        }
+    }
+
+    /// Path in the temp dir for a test's output, unique per process.
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("retroimager-{}-{name}", std::process::id()))
+    }
+
+    /// Reverse of [`pack_indices`].
+    fn unpack_indices(packed: &[u8], w: usize, h: usize, bits: u8) -> Vec<u8> {
+        let per_byte = 8 / bits as usize;
+        let row_bytes = w.div_ceil(per_byte);
+        let mask = ((1u16 << bits) - 1) as u8;
+        (0..w * h)
+            .map(|i| {
+                let (x, y) = (i % w, i / w);
+                let shift = 8 - bits as usize * (x % per_byte + 1);
+                (packed[y * row_bytes + x / per_byte] >> shift) & mask
+            })
+            .collect()
+    }
+
+    #[test]
+    fn pack_indices_round_trips_odd_widths() {
+        for bits in [1u8, 2, 4, 8] {
+            for w in [1, 3, 5, 7, 9, 13] {
+                let h = 3;
+                let colors = 1usize << bits;
+                let indices: Vec<u8> = (0..w * h).map(|i| ((i * 5 + 1) % colors) as u8).collect();
+                let packed = pack_indices(&indices, w, h, bits);
+                assert_eq!(packed.len(), w.div_ceil(8 / bits as usize) * h, "{bits} bpp, width {w}");
+                assert_eq!(unpack_indices(&packed, w, h, bits), indices, "{bits} bpp, width {w}");
+            }
+        }
+    }
+
+    #[test]
+    fn pack_indices_is_msb_first_with_padded_rows() {
+        assert_eq!(pack_indices(&[1, 0, 1], 3, 1, 1), vec![0b1010_0000]);
+        assert_eq!(pack_indices(&[3, 2, 1, 0, 1, 2], 3, 2, 2), vec![0b1110_0100, 0b0001_1000]);
+        assert_eq!(pack_indices(&[0xa, 0x5, 0xf], 3, 1, 4), vec![0xa5, 0xf0]);
+    }
+
+    #[test]
+    fn png_bit_depth_fits_the_palette() {
+        assert_eq!(png_bit_depth_for(1), 1);
+        assert_eq!(png_bit_depth_for(2), 1);
+        assert_eq!(png_bit_depth_for(3), 2);
+        assert_eq!(png_bit_depth_for(4), 2);
+        assert_eq!(png_bit_depth_for(5), 4);
+        assert_eq!(png_bit_depth_for(16), 4);
+        assert_eq!(png_bit_depth_for(17), 8);
+        assert_eq!(png_bit_depth_for(256), 8);
+    }
+
+    #[test]
+    fn indexed_png_round_trips() {
+        for (colors, depth) in [(2, png::BitDepth::One), (4, png::BitDepth::Two), (9, png::BitDepth::Four), (200, png::BitDepth::Eight)] {
+            for transparent in [None, Some(1u8)] {
+                let mut img = test_image(11, 3, colors);
+                img.transparent = transparent;
+                let path = temp_path(&format!("indexed-{colors}-{}.png", transparent.is_some()));
+                write_indexed_png(&path, &img).unwrap();
+                let bytes = fs::read(&path).unwrap();
+                fs::remove_file(&path).unwrap();
+
+                let mut decoder = png::Decoder::new(io::Cursor::new(bytes));
+                decoder.set_transformations(png::Transformations::IDENTITY);
+                let mut reader = decoder.read_info().unwrap();
+                let mut buf = vec![0; reader.output_buffer_size().unwrap()];
+                let frame = reader.next_frame(&mut buf).unwrap();
+                let info = reader.info();
+
+                assert_eq!(frame.color_type, png::ColorType::Indexed);
+                assert_eq!(frame.bit_depth, depth);
+                assert_eq!((info.width, info.height), (11, 3));
+                assert_eq!(info.palette.as_deref(), Some(palette_to_rgb8(&img.palette).as_slice()));
+                assert_eq!(info.trns.as_deref(), transparent.map(|_| &[255u8, 0][..]));
+                assert_eq!(unpack_indices(&buf[..frame.buffer_size()], 11, 3, depth as u8), img.indices);
+            }
+        }
+    }
+
+    #[test]
+    fn indexed_png_rejects_empty_palette() {
+        let img = IndexedImage { w: 1, h: 1, palette: Palette::new(vec![]), indices: vec![0], transparent: None };
+        let path = temp_path("empty.png");
+        assert!(write_indexed_png(&path, &img).is_err());
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn gif_round_trips_tables_and_delays() {
+        let first = test_image(6, 4, 4);
+        let mut same = test_image(6, 4, 4);
+        same.indices.reverse();
+        let mut other = test_image(6, 4, 8);
+        other.transparent = Some(2);
+        let frames = [first, same, other];
+        let path = temp_path("frames.gif");
+        write_gif(&path, &frames, &[100, 35, 4], Some(3)).unwrap();
+        let bytes = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let mut options = gif::DecodeOptions::new();
+        options.set_color_output(gif::ColorOutput::Indexed);
+        let mut decoder = options.read_info(io::Cursor::new(bytes)).unwrap();
+        assert_eq!((decoder.width(), decoder.height()), (6, 4));
+        assert_eq!(decoder.global_palette(), Some(palette_to_rgb8(&frames[0].palette).as_slice()));
+
+        let mut decoded = vec![];
+        while let Some(frame) = decoder.read_next_frame().unwrap() {
+            decoded.push(frame.clone());
+        }
+        assert_eq!(decoder.repeat(), gif::Repeat::Finite(3));
+        assert_eq!(decoded.len(), 3);
+        // delays are rounded to 1/100 s
+        assert_eq!(decoded.iter().map(|f| f.delay).collect::<Vec<_>>(), vec![10, 4, 0]);
+        // only the frame with a different palette carries a local table
+        assert_eq!(decoded[0].palette, None);
+        assert_eq!(decoded[1].palette, None);
+        assert_eq!(decoded[2].palette.as_deref(), Some(palette_to_rgb8(&frames[2].palette).as_slice()));
+        assert_eq!(decoded[2].transparent, Some(2));
+        assert_eq!(decoded[2].dispose, gif::DisposalMethod::Background);
+        for (frame, img) in decoded.iter().zip(&frames) {
+            assert_eq!(&frame.buffer[..], &img.indices[..]);
+        }
+    }
+
+    #[test]
+    fn gif_loops_forever_for_zero_and_not_at_all_without_count() {
+        let frames = [test_image(2, 2, 2)];
+        for (count, repeat) in [(Some(0), gif::Repeat::Infinite), (None, gif::Repeat::Finite(0))] {
+            let path = temp_path(&format!("loop-{count:?}.gif"));
+            write_gif(&path, &frames, &[], count).unwrap();
+            let bytes = fs::read(&path).unwrap();
+            fs::remove_file(&path).unwrap();
+            let mut decoder = gif::DecodeOptions::new().read_info(io::Cursor::new(bytes)).unwrap();
+            while decoder.read_next_frame().unwrap().is_some() {}
+            assert_eq!(decoder.repeat(), repeat);
+        }
+    }
+
+    #[test]
+    fn gif_rejects_mismatched_frames() {
+        let path = temp_path("bad.gif");
+        assert!(write_gif(&path, &[], &[], None).is_err());
+        assert!(write_gif(&path, &[test_image(2, 2, 2), test_image(3, 2, 2)], &[], None).is_err());
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn bit_planes_cover_the_palette() {
+        assert_eq!(bit_planes_for(1), 1);
+        assert_eq!(bit_planes_for(2), 1);
+        assert_eq!(bit_planes_for(3), 2);
+        assert_eq!(bit_planes_for(32), 5);
+        assert_eq!(bit_planes_for(33), 6);
+        assert_eq!(bit_planes_for(256), 8);
+    }
+}
@@ -0,0 +1,546 @@
+﻿use crate::classes::c_color_box::ColorBox16;
+use crate::classes::c_color_metric::ColorMetric;
+use crate::classes::c_rgb16::Rgb16;
+
+pub fn build_palette_median_cut_rgba16(src16: &[u16], w: usize, h: usize, k: usize) -> Vec<Rgb16> {
+    // src16: [r,g,b,a, r,g,b,a ...], len = w*h*4
+    assert_eq!(src16.len(), w * h * 4);
+
+    let samples = sample_rgb16_from_rgba16(src16, w, h, 50_000, 512); // max_samples, alpha_threshold(u16)
+    median_cut_palette(samples, k.max(2))
+}
+
+pub fn sample_rgb16_from_rgba16(
+    src16: &[u16],
+    w: usize,
+    h: usize,
+    max_samples: usize,
+    alpha_threshold: u16,
+) -> Vec<Rgb16> {
+    let total = w * h;
+    let step = (total / max_samples).max(1);
+
+    let mut out = Vec::with_capacity(total.min(max_samples));
+    for i in (0..total).step_by(step) {
+        let p = i * 4;
+        let a = src16[p + 3];
+        if a <= alpha_threshold {
+            continue;
+        }
+        out.push(Rgb16 {
+            r: src16[p],
+            g: src16[p + 1],
+            b: src16[p + 2],
+        });
+    }
+
+    if out.is_empty() && total > 0 {
+        out.push(Rgb16 { r: src16[0], g: src16[1], b: src16[2] });
+    }
+    out
+}
+
+
+pub fn median_cut_palette(samples: Vec<Rgb16>, k: usize) -> Vec<Rgb16> {
+    if samples.is_empty() {
+        return vec![Rgb16 { r: 0, g: 0, b: 0 }];
+    }
+
+    let mut boxes = vec![ColorBox16 { colors: samples }];
+
+    while boxes.len() < k {
+        let (best_i, _) = boxes
+            .iter()
+            .enumerate()
+            .max_by_key(|(_, b)| (b.score(), b.colors.len()))
+            .unwrap();
+
+        let b = boxes.remove(best_i);
+        let Some((b1, b2)) = b.clone().split() else {
+            boxes.push(b);
+            break;
+        };
+
+        boxes.push(b1);
+        boxes.push(b2);
+    }
+
+    boxes.into_iter().map(|b| b.average()).collect()
+}
+
+
+pub fn u16_to_u8(v: u16) -> u8 {
+    ((v as u32 + 128) / 257) as u8   // check1 overflow
+}
+
+pub fn nearest_palette_color(c: Rgb16, palette: &[Rgb16]) -> Rgb16 {
+    let mut best = palette[0];
+    let mut best_d: u64 = u64::MAX;
+
+    for &p in palette {
+        let dr = c.r as i32 - p.r as i32;
+        let dg = c.g as i32 - p.g as i32;
+        let db = c.b as i32 - p.b as i32;
+        let d = (dr as i64 * dr as i64 + dg as i64 * dg as i64 + db as i64 * db as i64) as u64;
+        if d < best_d {
+            best_d = d;
+            best = p;
+        }
+    }
+    best
+}
+
+pub fn nearest_palette_index(c: Rgb16, palette: &[Rgb16]) -> usize {
+    let mut best = 0;
+    let mut best_d: u64 = u64::MAX;
+
+    for (i, &p) in palette.iter().enumerate() {
+        let dr = c.r as i32 - p.r as i32;
+        let dg = c.g as i32 - p.g as i32;
+        let db = c.b as i32 - p.b as i32;
+        let d = (dr as i64 * dr as i64 + dg as i64 * dg as i64 + db as i64 * db as i64) as u64;
+        if d < best_d {
+            best_d = d;
+            best = i;
+        }
+    }
+    best
+}
+
+pub fn clamp_u16_i32(v: i32) -> u16 {
+    if v < 0 { 0 } else if v > 65535 { 65535 } else { v as u16 }
+}
+
+
+
+pub fn dither_fs_palette_rgba16_to_rgba8(
+    src16: &[u16],
+    dst8: &mut Vec<u8>,
+    indices: &mut Vec<u8>,
+    w: usize,
+    h: usize,
+    palette: &[Rgb16],
+) {
+    dither_diffusion_palette_rgba16_to_rgba8(&FLOYD_STEINBERG, 1.0, ColorMetric::Rgb, src16, None, dst8, indices, w, h, palette);
+}
+
+/// Entry `metric` picks for `c` and its index (as stored in the index buffers, so at most 256 entries).
+fn pick(metric: ColorMetric, c: Rgb16, palette: &[Rgb16]) -> (Rgb16, u8) {
+    let i = metric.nearest_index(c, palette);
+    (palette.get(i).copied().unwrap_or_default(), i as u8)
+}
+
+/// Max per-channel difference (16-bit) for a pixel to count as unchanged between frames.
+pub const TEMPORAL_THRESHOLD: u16 = 3 * 257;
+
+fn is_static_pixel(src16: &[u16], prev16: &[u16], p: usize) -> bool {
+    (0..4).all(|c| src16[p + c].abs_diff(prev16[p + c]) <= TEMPORAL_THRESHOLD)
+}
+
+/// Error diffusion kernel: (dx, dy, weight) taps, weights are divided by `divisor`.
+pub struct DiffusionKernel {
+    pub taps: &'static [(i32, usize, i32)],
+    pub divisor: i32,
+}
+
+// Floyd–Steinberg weights (divide on 16)
+pub const FLOYD_STEINBERG: DiffusionKernel = DiffusionKernel {
+    taps: &[(1, 0, 7), (-1, 1, 3), (0, 1, 5), (1, 1, 1)],
+    divisor: 16,
+};
+
+// Atkinson spreads only 6/8 of the error -> lighter, higher contrast look
+pub const ATKINSON: DiffusionKernel = DiffusionKernel {
+    taps: &[(1, 0, 1), (2, 0, 1), (-1, 1, 1), (0, 1, 1), (1, 1, 1), (0, 2, 1)],
+    divisor: 8,
+};
+
+/// `prev` is (source16, output8) of the previous frame for temporal coherence: pixels whose
+/// source didn't change keep the previous output color (snapped to `palette`), so static
+/// regions don't flicker. The error is still diffused from them as usual.
+/// `strength` (0..=1) scales the diffused error. `indices` gets the palette index of every pixel.
+#[allow(clippy::too_many_arguments)]
+pub fn dither_diffusion_palette_rgba16_to_rgba8(
+    kernel: &DiffusionKernel,
+    strength: f32,
+    metric: ColorMetric,
+    src16: &[u16],
+    prev: Option<(&[u16], &[u8])>,
+    dst8: &mut Vec<u8>,
+    indices: &mut Vec<u8>,
+    w: usize,
+    h: usize,
+    palette: &[Rgb16],
+) {
+    assert_eq!(src16.len(), w * h * 4);
+    dst8.resize(w * h * 4, 0);
+    indices.resize(w * h, 0);
+
+    // ring of error rows: current row + rows the kernel reaches down to
+    let rows = kernel.taps.iter().map(|t| t.1).max().unwrap_or(0) + 1;
+    let mut err = vec![[0i32; 3]; w * rows];
+    // error is scaled by strength/256 in integer math
+    let scale = (strength.clamp(0.0, 1.0) * 256.0) as i32;
+
+    for y in 0..h {
+        let row = y % rows;
+
+        for x in 0..w {
+            let p = (y * w + x) * 4;
+            let e = err[row * w + x];
+
+            let r = clamp_u16_i32(src16[p] as i32 + e[0]);
+            let g = clamp_u16_i32(src16[p + 1] as i32 + e[1]);
+            let b = clamp_u16_i32(src16[p + 2] as i32 + e[2]);
+            let a = src16[p + 3];
+
+            let cur = Rgb16 { r, g, b };
+            let (q, id) = match prev {
+                Some((prev16, prev8)) if is_static_pixel(src16, prev16, p) => {
+                    let old = Rgb16 { r: prev8[p] as u16 * 257, g: prev8[p + 1] as u16 * 257, b: prev8[p + 2] as u16 * 257 };
+                    pick(metric, old, palette)
+                }
+                _ => pick(metric, cur, palette),
+            };
+            indices[p / 4] = id;
+
+            dst8[p]     = u16_to_u8(q.r);
+            dst8[p + 1] = u16_to_u8(q.g);
+            dst8[p + 2] = u16_to_u8(q.b);
+            dst8[p + 3] = u16_to_u8(a);
+
+            let err_r = (cur.r as i32 - q.r as i32) * scale / 256;
+            let err_g = (cur.g as i32 - q.g as i32) * scale / 256;
+            let err_b = (cur.b as i32 - q.b as i32) * scale / 256;
+
+            for &(dx, dy, weight) in kernel.taps {
+                let nx = x as i32 + dx;
+                if nx < 0 || nx >= w as i32 || y + dy >= h {
+                    continue;
+                }
+                let cell = &mut err[((y + dy) % rows) * w + nx as usize];
+                cell[0] += (err_r * weight) / kernel.divisor;
+                cell[1] += (err_g * weight) / kernel.divisor;
+                cell[2] += (err_b * weight) / kernel.divisor;
+            }
+        }
+
+        // row is done -> reuse it for y + rows
+        err[row * w..(row + 1) * w].fill([0; 3]);
+    }
+}
+
+/// Ordered dithering to a palette: Bayer threshold is added before the nearest color lookup.
+/// The offset range follows the average palette step per channel.
+/// `strength` (0..=1) scales the threshold spread. `indices` gets the palette index of every pixel.
+#[allow(clippy::too_many_arguments)]
+pub fn dither_ordered_palette_rgba16_to_rgba8(
+    strength: f32,
+    metric: ColorMetric,
+    src16: &[u16],
+    dst8: &mut Vec<u8>,
+    indices: &mut Vec<u8>,
+    w: usize,
+    h: usize,
+    palette: &[Rgb16],
+) {
+    assert_eq!(src16.len(), w * h * 4);
+    dst8.resize(w * h * 4, 0);
+    indices.resize(w * h, 0);
+
+    let spread = 65535.0 / (palette.len().max(2) as f32).cbrt() * strength.clamp(0.0, 1.0);
+
+    for y in 0..h {
+        for x in 0..w {
+            let p = (y * w + x) * 4;
+
+            // step -0.5..0.5
+            let t = (BAYER8[y & 7][x & 7] as f32 + 0.5) / 64.0 - 0.5;
+            let offset = (t * spread) as i32;
+
+            let cur = Rgb16 {
+                r: clamp_u16_i32(src16[p] as i32 + offset),
+                g: clamp_u16_i32(src16[p + 1] as i32 + offset),
+                b: clamp_u16_i32(src16[p + 2] as i32 + offset),
+            };
+            let (q, id) = pick(metric, cur, palette);
+            indices[p / 4] = id;
+
+            dst8[p]     = u16_to_u8(q.r);
+            dst8[p + 1] = u16_to_u8(q.g);
+            dst8[p + 2] = u16_to_u8(q.b);
+            dst8[p + 3] = u16_to_u8(src16[p + 3]);
+        }
+    }
+}
+
+pub fn map_nearest_palette_rgba16_to_rgba8(
+    metric: ColorMetric,
+    src16: &[u16],
+    dst8: &mut Vec<u8>,
+    indices: &mut Vec<u8>,
+    w: usize,
+    h: usize,
+    palette: &[Rgb16],
+) {
+    assert_eq!(src16.len(), w * h * 4);
+    dst8.resize(w * h * 4, 0);
+    indices.resize(w * h, 0);
+
+    for p in (0..src16.len()).step_by(4) {
+        let (q, id) = pick(metric, Rgb16 { r: src16[p], g: src16[p + 1], b: src16[p + 2] }, palette);
+        indices[p / 4] = id;
+        dst8[p]     = u16_to_u8(q.r);
+        dst8[p + 1] = u16_to_u8(q.g);
+        dst8[p + 2] = u16_to_u8(q.b);
+        dst8[p + 3] = u16_to_u8(src16[p + 3]);
+    }
+}
+
+/// Lloyd iterations: move every entry to the mean of the samples closest to it.
+pub fn kmeans_refine_palette(samples: &[Rgb16], palette: Vec<Rgb16>, iterations: usize) -> Vec<Rgb16> {
+    kmeans_refine_around(samples, &[], palette, iterations)
+}
+
+/// [`kmeans_refine_palette`] for `free` next to entries that must not move: samples closest
+/// to a `fixed` color belong to it, the free entries spread over the rest. Returns the free entries.
+pub fn kmeans_refine_around(samples: &[Rgb16], fixed: &[Rgb16], mut palette: Vec<Rgb16>, iterations: usize) -> Vec<Rgb16> {
+    if palette.is_empty() {
+        return palette;
+    }
+
+    let mut all: Vec<Rgb16> = fixed.to_vec();
+    all.extend_from_slice(&palette);
+    for _ in 0..iterations {
+        let mut sums = vec![[0u64; 4]; palette.len()];
+        for &s in samples {
+            let Some(i) = nearest_palette_index(s, &all).checked_sub(fixed.len()) else { continue; };
+            let sum = &mut sums[i];
+            sum[0] += s.r as u64;
+            sum[1] += s.g as u64;
+            sum[2] += s.b as u64;
+            sum[3] += 1;
+        }
+
+        let mut moved = false;
+        for (c, sum) in palette.iter_mut().zip(sums.iter()) {
+            if sum[3] == 0 {
+                continue;
+            }
+            let next = Rgb16 {
+                r: (sum[0] / sum[3]) as u16,
+                g: (sum[1] / sum[3]) as u16,
+                b: (sum[2] / sum[3]) as u16,
+            };
+            if next.r != c.r || next.g != c.g || next.b != c.b {
+                moved = true;
+            }
+            *c = next;
+        }
+        all[fixed.len()..].copy_from_slice(&palette);
+
+        if !moved {
+            break;
+        }
+    }
+    palette
+}
+
+const BAYER8: [[u8; 8]; 8] = [
+    [0, 48, 12, 60, 3, 51, 15, 63],
+    [32, 16, 44, 28, 35, 19, 47, 31],
+    [8, 56, 4, 52, 11, 59, 7, 55],
+    [40, 24, 36, 20, 43, 27, 39, 23],
+    [2, 50, 14, 62, 1, 49, 13, 61],
+    [34, 18, 46, 30, 33, 17, 45, 29],
+    [10, 58, 6, 54, 9, 57, 5, 53],
+    [42, 26, 38, 22, 41, 25, 37, 21],
+];
+
+
+pub fn dither_ordered_levels_rgba16_to_rgba8(
+    src16: &[u16],
+    dst8: &mut [u8],
+    w: usize,
+    h: usize,
+    levels: u16,
+) {
+    debug_assert_eq!(src16.len(), w * h * 4);
+    debug_assert_eq!(dst8.len(), w * h * 4);
+
+    let max_q = (levels - 1) as f32;
+
+    for y in 0..h {
+        for x in 0..w {
+            let p = (y * w + x) * 4;
+
+            // step 0..1
+            let t = (BAYER8[y & 7][x & 7] as f32 + 0.5) / 64.0;
+
+            for c in 0..4 {
+                let v16 = src16[p + c] as f32 / 65535.0; // 0..1
+                let q = v16 * max_q;                     // 0..(levels-1)
+                let base = q.floor();
+                let frac = q - base;
+
+                let mut qi = base + if frac > t { 1.0 } else { 0.0 };
+                if qi < 0.0 { qi = 0.0; }
+                if qi > max_q { qi = max_q; }
+
+                let out16 = (qi / max_q) * 65535.0;
+                let out16u = (out16 + 0.5) as u16;
+
+                // u16 -> u8
+                dst8[p + c] = (out16u >> 8) as u8;
+            }
+        }
+    }
+}
+
+
+pub fn dither_ordered_levels_rgba8_inplace(
+    rgba: &mut [u8],
+    w: usize,
+    h: usize,
+    levels: u8,
+) {
+    debug_assert_eq!(rgba.len(), w * h * 4);
+
+    let levels = levels.max(2);
+    let max_q = (levels - 1) as f32;
+
+    for y in 0..h {
+        for x in 0..w {
+            let p = (y * w + x) * 4;
+
+            // step 0..1
+            let t = (BAYER8[y & 7][x & 7] as f32 + 0.5) / 64.0;
+
+            for c in 0..4 {
+                let v = rgba[p + c] as f32 / 255.0; // 0..1
+                let q = v * max_q;
+                let base = q.floor();
+                let frac = q - base;
+
+                let mut qi = base + if frac > t { 1.0 } else { 0.0 };
+                if qi < 0.0 { qi = 0.0; }
+                if qi > max_q { qi = max_q; }
+
+                let out = (qi / max_q) * 255.0;
+                rgba[p + c] = (out + 0.5) as u8;
+            }
+        }
+    }
+}
+
+
+/// Nearest resize for interleaved pixelx: [c0,c1,c2,(c3), c0,c1,c2,(c3), ...]
+/// T: u8 or u16
+/// C: channel size (3=RGB, 4=RGBA)
+pub fn resize_interleaved_nearest<T: Copy + Default, const C: usize>(
+    src: &[T],
+    w: usize,
+    h: usize,
+    scale: f32,
+) -> (Vec<T>, usize, usize) {
+    assert!(C > 0);
+    assert_eq!(src.len(), w * h * C);
+
+    let scale = scale.max(0.0001);
+    let new_w = ((w as f32) * scale).round().max(1.0) as usize;
+    let new_h = ((h as f32) * scale).round().max(1.0) as usize;
+
+    let mut dst = vec![T::default(); new_w * new_h * C];
+
+    for y in 0..new_h {
+        let sy = y * h / new_h;
+        for x in 0..new_w {
+            let sx = x * w / new_w;
+
+            let si = (sy * w + sx) * C;
+            let di = (y * new_w + x) * C;
+
+            dst[di..di + C].copy_from_slice(&src[si..si + C]);
+        }
+    }
+
+    (dst, new_w, new_h)
+}
+
+
+pub fn rgb16_to_u8(c: Rgb16) -> (u8, u8, u8) {
+    ((c.r >> 8) as u8, (c.g >> 8) as u8, (c.b >> 8) as u8)
+}
+
+pub fn rgb16_to_u8_exact(c: Rgb16) -> (u8, u8, u8) {
+    (u16_to_u8(c.r), u16_to_u8(c.g), u16_to_u8(c.b))
+}
+
+pub fn pack_rgb(r: u8, g: u8, b: u8) -> u32 {
+    ((r as u32) << 16) | ((g as u32) << 8) | (b as u32)
+}
+
+
+pub fn rgba8_to_rgba16(src: &[u8], dst: &mut Vec<u16>) {
+    dst.resize(src.len(), 0);
+    for (i, &v) in src.iter().enumerate() {
+        dst[i] = (v as u16) * 257; // 0..255 -> 0..65535
+    }
+}
+/// Median of each RGB channel over the (2r+1)² window around every pixel (clamped at the
+/// edges), in place. Removes speckle noise while keeping edges; alpha is kept.
+pub fn median_filter_rgba16(data: &mut [u16], w: usize, h: usize, radius: usize) {
+    if radius == 0 || w == 0 || h == 0 {
+        return;
+    }
+    let src = data.to_vec();
+    let mut window: Vec<u16> = Vec::with_capacity((2 * radius + 1).pow(2));
+    for y in 0..h {
+        let (y0, y1) = (y.saturating_sub(radius), (y + radius).min(h - 1));
+        for x in 0..w {
+            let (x0, x1) = (x.saturating_sub(radius), (x + radius).min(w - 1));
+            for c in 0..3 {
+                window.clear();
+                for yy in y0..=y1 {
+                    window.extend((x0..=x1).map(|xx| src[(yy * w + xx) * 4 + c]));
+                }
+                let mid = window.len() / 2;
+                data[(y * w + x) * 4 + c] = *window.select_nth_unstable(mid).1;
+            }
+        }
+    }
+}
+
+/// Gaussian blur (standard deviation `sigma` in pixels) of the RGB channels of 16-bit RGBA,
+/// returned as interleaved RGB floats 0..1. Separable, edges are clamped.
+pub fn gaussian_blur_rgb16(data: &[u16], w: usize, h: usize, sigma: f32) -> Vec<f32> {
+    let radius = (sigma * 3.0).ceil().max(1.0) as isize;
+    let kernel: Vec<f32> = (-radius..=radius).map(|i| (-(i * i) as f32 / (2.0 * sigma * sigma)).exp()).collect();
+    let sum: f32 = kernel.iter().sum();
+    let kernel: Vec<f32> = kernel.iter().map(|k| k / sum).collect();
+
+    let at = |v: isize, n: usize| v.clamp(0, n as isize - 1) as usize;
+    let mut rows = vec![0.0f32; w * h * 3];
+    for y in 0..h {
+        for x in 0..w {
+            for (k, weight) in kernel.iter().enumerate() {
+                let p = (y * w + at(x as isize + k as isize - radius, w)) * 4;
+                for c in 0..3 {
+                    rows[(y * w + x) * 3 + c] += data[p + c] as f32 / 65535.0 * weight;
+                }
+            }
+        }
+    }
+    let mut out = vec![0.0f32; w * h * 3];
+    for y in 0..h {
+        for x in 0..w {
+            for (k, weight) in kernel.iter().enumerate() {
+                let p = (at(y as isize + k as isize - radius, h) * w + x) * 3;
+                for c in 0..3 {
+                    out[(y * w + x) * 3 + c] += rows[p + c] * weight;
+                }
+            }
+        }
+    }
+    out
+}
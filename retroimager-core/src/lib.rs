@@ -0,0 +1,62 @@
+﻿//! Image processing core of Dithering Slave, without any GUI dependencies.
+//!
+//! The pipeline is: decode ([`decode_utils::load_frames`]) → scale and widen to
+//! [`ImageBuffer16`] ([`pipeline_utils::prepare_frames`]) → build a [`Palette`] with a
+//! [`Quantizer`] → map pixels to it with a [`Ditherer`] → export an [`IndexedImage`]
+//! ([`export_utils::write_by_extension`]).
+//!
+//! ```no_run
+//! use retroimager_core::{PipelineSettings, ExportSettings};
+//! use retroimager_core::pipeline_utils::process_file;
+//! use retroimager_core::export_utils::write_by_extension;
+//!
+//! let settings = PipelineSettings { colors: 16, ..Default::default() };
+//! let result = process_file("in.png".as_ref(), &settings, None).unwrap();
+//! let (frames, delays) = result.to_indexed();
+//! write_by_extension("out.png".as_ref(), &frames, &delays, 0, &ExportSettings::default()).unwrap();
+//! ```
+
+pub mod classes;
+pub mod image_utils;
+pub mod decode_utils;
+pub mod pipeline_utils;
+pub mod export_utils;
+pub mod palette_utils;
+pub mod color_utils;
+pub mod batch_utils;
+
+pub use classes::c_rgb16::Rgb16;
+pub use classes::c_palette::Palette;
+pub use classes::c_image_buffer16::ImageBuffer16;
+pub use classes::c_image_frame::ImageFrame;
+pub use classes::c_indexed_image::IndexedImage;
+pub use classes::c_processed_image::ProcessedImage;
+pub use classes::t_quantizer::Quantizer;
+pub use classes::c_median_cut::MedianCut;
+pub use classes::c_kmeans::KMeans;
+pub use classes::c_quantizer_kind::QuantizerKind;
+pub use classes::t_ditherer::Ditherer;
+pub use classes::c_error_diffusion::ErrorDiffusion;
+pub use classes::c_ordered_bayer::OrderedBayer8;
+pub use classes::c_nearest_color::NearestColor;
+pub use classes::c_dither_algorithm::DitherAlgorithm;
+pub use classes::c_color_metric::ColorMetric;
+pub use classes::c_resize_filter::ResizeFilter;
+pub use classes::c_adjustments::Adjustments;
+pub use classes::c_palette_source::PaletteSource;
+pub use classes::c_palette_format::PaletteFormat;
+pub use classes::c_palette_sort::PaletteSort;
+pub use classes::c_hardware_palette::HardwarePalette;
+pub use classes::c_pipeline_settings::PipelineSettings;
+pub use classes::c_export_format::ExportFormat;
+pub use classes::c_export_settings::ExportSettings;
+pub use classes::c_preset::Preset;
+pub use classes::c_batch_job::{BatchItem, BatchJob, BatchStatus};
+pub use classes::c_batch_worker::BatchWorker;
+pub use classes::c_folder_watcher::FolderWatcher;
+pub use classes::c_watch_worker::WatchWorker;
+pub use classes::c_viewer_state::ViewerState;
+pub use classes::c_project::{EmbeddedFrame, Project, ProjectSource};
+pub use classes::c_job_progress::JobProgress;
+pub use classes::c_pipeline_worker::{JobOutput, JobSource, PipelineJob, PipelineWorker, PreviewPass};
+pub use classes::c_palette_worker::PaletteWorker;
@@ -0,0 +1,554 @@
+﻿use std::fs;
+use std::collections::HashSet;
+use std::io::Cursor;
+use std::path::Path;
+use image::{ImageFormat, RgbImage, RgbaImage};
+use crate::classes::c_palette::Palette;
+use crate::classes::c_palette_format::PaletteFormat;
+use crate::classes::c_palette_sort::PaletteSort;
+use crate::classes::c_rgb16::Rgb16;
+use crate::color_utils::{delta_e, luminance, rgb16_to_hsv};
+use crate::image_utils::rgb16_to_u8_exact;
+
+/// Below this HSV saturation an entry sorts with the grays.
+const GRAY_SATURATION: f32 = 0.08;
+
+/// Most colors an imported palette may have (the indexed exports address 8 bits).
+pub const MAX_PALETTE_COLORS: usize = 256;
+
+/// Paint.NET only shows the first 96 entries of a palette file.
+pub const PAINT_NET_MAX_COLORS: usize = 96;
+
+fn rgb8(r: u8, g: u8, b: u8) -> Rgb16 {
+    Rgb16 { r: r as u16 * 257, g: g as u16 * 257, b: b as u16 * 257 }
+}
+
+fn unit_to_u16(v: f32) -> u16 {
+    (v.clamp(0.0, 1.0) * 65535.0).round() as u16
+}
+
+/// "#RRGGBB", "RRGGBB" or "0xRRGGBB", or 16 bits per channel as "#RRRRGGGGBBBB".
+pub fn parse_hex_color(s: &str) -> Option<Rgb16> {
+    let hex = s.trim();
+    let hex = hex.strip_prefix('#').or_else(|| hex.strip_prefix("0x")).unwrap_or(hex);
+    if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+
+    match hex.len() {
+        6 => {
+            let v = u32::from_str_radix(hex, 16).ok()?;
+            Some(Rgb16 {
+                r: ((v >> 16) & 0xFF) as u16 * 257,
+                g: ((v >> 8) & 0xFF) as u16 * 257,
+                b: (v & 0xFF) as u16 * 257,
+            })
+        }
+        12 => {
+            let v = u64::from_str_radix(hex, 16).ok()?;
+            Some(Rgb16 { r: (v >> 32) as u16, g: (v >> 16) as u16, b: v as u16 })
+        }
+        _ => None,
+    }
+}
+
+/// "#RRGGBB" when the color is exact at 8 bits per channel, "#RRRRGGGGBBBB" otherwise.
+pub fn format_hex_color(c: Rgb16) -> String {
+    if [c.r, c.g, c.b].iter().all(|v| v % 257 == 0) {
+        format!("#{:02X}{:02X}{:02X}", c.r / 257, c.g / 257, c.b / 257)
+    } else {
+        format!("#{:04X}{:04X}{:04X}", c.r, c.g, c.b)
+    }
+}
+
+/// Plain hex list: one color per line, empty lines and ';' / '//' comments are skipped.
+pub fn parse_hex_palette(text: &str) -> Result<Vec<Rgb16>, String> {
+    let mut out = vec![];
+    for (n, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with(';') || line.starts_with("//") {
+            continue;
+        }
+        let token = line.split_whitespace().next().unwrap_or("");
+        match parse_hex_color(token) {
+            Some(c) => out.push(c),
+            None => return Err(format!("line {}: '{token}' is not a hex color", n + 1)),
+        }
+    }
+    Ok(out)
+}
+
+/// GIMP palette: "GIMP Palette" header, optional Name/Columns lines, '#' comments,
+/// then "R G B [name]" in decimal.
+pub fn parse_gpl(text: &str) -> Result<Vec<Rgb16>, String> {
+    let mut lines = text.lines().enumerate();
+    match lines.next() {
+        Some((_, first)) if first.trim_start_matches('\u{feff}').trim() == "GIMP Palette" => {}
+        _ => return Err("missing 'GIMP Palette' header".to_string()),
+    }
+
+    let mut out = vec![];
+    for (n, line) in lines {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with("Name:") || line.starts_with("Columns:") {
+            continue;
+        }
+        let mut parts = line.split_whitespace().map(|t| t.parse::<u8>());
+        match (parts.next(), parts.next(), parts.next()) {
+            (Some(Ok(r)), Some(Ok(g)), Some(Ok(b))) => out.push(rgb8(r, g, b)),
+            _ => return Err(format!("line {}: '{line}' is not an 'R G B' entry", n + 1)),
+        }
+    }
+    Ok(out)
+}
+
+/// JASC-PAL: "JASC-PAL", version, color count, then "R G B" lines.
+pub fn parse_jasc_pal(text: &str) -> Result<Vec<Rgb16>, String> {
+    let mut lines = text.lines().map(str::trim);
+    if lines.next().map(|l| l.trim_start_matches('\u{feff}')) != Some("JASC-PAL") {
+        return Err("missing 'JASC-PAL' header".to_string());
+    }
+    let _version = lines.next();
+    let count: usize = lines.next().and_then(|l| l.parse().ok()).ok_or("missing color count")?;
+
+    let mut out = Vec::with_capacity(count);
+    for (n, line) in lines.filter(|l| !l.is_empty()).take(count).enumerate() {
+        let mut parts = line.split_whitespace().map(|t| t.parse::<u8>());
+        match (parts.next(), parts.next(), parts.next()) {
+            (Some(Ok(r)), Some(Ok(g)), Some(Ok(b))) => out.push(rgb8(r, g, b)),
+            _ => return Err(format!("entry {}: '{line}' is not an 'R G B' entry", n + 1)),
+        }
+    }
+    if out.len() < count {
+        return Err(format!("header says {count} colors, found {}", out.len()));
+    }
+    Ok(out)
+}
+
+/// Microsoft RIFF palette: "RIFF" size "PAL " with a "data" chunk of
+/// version, count and R G B flags entries.
+pub fn parse_riff_pal(bytes: &[u8]) -> Result<Vec<Rgb16>, String> {
+    let mut pos = 12;
+    while pos + 8 <= bytes.len() {
+        let id = &bytes[pos..pos + 4];
+        let size = u32::from_le_bytes([bytes[pos + 4], bytes[pos + 5], bytes[pos + 6], bytes[pos + 7]]) as usize;
+        let data = bytes.get(pos + 8..pos + 8 + size).ok_or("truncated RIFF chunk")?;
+        if id == b"data" {
+            let count = data.get(2..4).map(|c| u16::from_le_bytes([c[0], c[1]]) as usize).ok_or("truncated RIFF palette")?;
+            let entries = data.get(4..4 + count * 4).ok_or("truncated RIFF palette")?;
+            return Ok(entries.chunks_exact(4).map(|e| rgb8(e[0], e[1], e[2])).collect());
+        }
+        pos += 8 + size + (size & 1);
+    }
+    Err("RIFF palette has no data chunk".to_string())
+}
+
+/// Adobe Color Table: 256 RGB triplets, optionally followed by a big-endian color
+/// count and transparent index.
+pub fn parse_act(bytes: &[u8]) -> Result<Vec<Rgb16>, String> {
+    if bytes.len() < 768 {
+        return Err(format!("ACT file is {} bytes, expected at least 768", bytes.len()));
+    }
+    let count = match bytes.get(768..770) {
+        Some(c) => (u16::from_be_bytes([c[0], c[1]]) as usize).min(256),
+        None => 256,
+    };
+    Ok(bytes[..count * 3].chunks_exact(3).map(|c| rgb8(c[0], c[1], c[2])).collect())
+}
+
+/// Adobe Swatch Exchange. RGB, gray and (naively converted) CMYK swatches are read,
+/// groups are flattened; LAB swatches are rejected.
+pub fn parse_ase(bytes: &[u8]) -> Result<Vec<Rgb16>, String> {
+    if !bytes.starts_with(b"ASEF") || bytes.len() < 12 {
+        return Err("not an ASE file".to_string());
+    }
+    let be_u16 = |p: usize| bytes.get(p..p + 2).map(|b| u16::from_be_bytes([b[0], b[1]]));
+    let be_u32 = |p: usize| bytes.get(p..p + 4).map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]));
+    let be_f32 = |p: usize| be_u32(p).map(f32::from_bits);
+
+    let blocks = be_u32(8).unwrap_or(0);
+    let mut out = vec![];
+    let mut pos = 12;
+    for _ in 0..blocks {
+        let (Some(kind), Some(len)) = (be_u16(pos), be_u32(pos + 2)) else {
+            return Err("truncated ASE block".to_string());
+        };
+        let body = pos + 6;
+        pos = body + len as usize;
+        if kind != 0x0001 {
+            continue;
+        }
+
+        let name_len = be_u16(body).ok_or("truncated ASE color")? as usize;
+        let model_at = body + 2 + name_len * 2;
+        let model = bytes.get(model_at..model_at + 4).ok_or("truncated ASE color")?;
+        let v = |i: usize| be_f32(model_at + 4 + i * 4).ok_or("truncated ASE color");
+        let color = match model {
+            b"RGB " => Rgb16 { r: unit_to_u16(v(0)?), g: unit_to_u16(v(1)?), b: unit_to_u16(v(2)?) },
+            b"Gray" => {
+                let l = unit_to_u16(v(0)?);
+                Rgb16 { r: l, g: l, b: l }
+            }
+            b"CMYK" => {
+                let k = 1.0 - v(3)?;
+                Rgb16 { r: unit_to_u16((1.0 - v(0)?) * k), g: unit_to_u16((1.0 - v(1)?) * k), b: unit_to_u16((1.0 - v(2)?) * k) }
+            }
+            _ => return Err(format!("ASE color model '{}' is not supported", String::from_utf8_lossy(model).trim())),
+        };
+        out.push(color);
+    }
+    Ok(out)
+}
+
+/// Paint.NET: one `AARRGGBB` per line (alpha ignored), ';' comments.
+pub fn parse_paint_net(text: &str) -> Result<Vec<Rgb16>, String> {
+    let mut out = vec![];
+    for (n, line) in text.lines().enumerate() {
+        let line = line.trim().trim_start_matches('\u{feff}');
+        if line.is_empty() || line.starts_with(';') {
+            continue;
+        }
+        let color = match line.len() {
+            8 => line.get(2..).and_then(parse_hex_color),
+            _ => parse_hex_color(line),
+        };
+        match color {
+            Some(c) => out.push(c),
+            None => return Err(format!("line {}: '{line}' is not an AARRGGBB color", n + 1)),
+        }
+    }
+    Ok(out)
+}
+
+/// Palette image: every distinct opaque color in reading order, so both 1 px high
+/// strips and upscaled swatch images work.
+pub fn parse_png_strip(bytes: &[u8]) -> Result<Vec<Rgb16>, String> {
+    let img = image::load_from_memory(bytes).map_err(|e| e.to_string())?.to_rgba8();
+    distinct_colors([&img], MAX_PALETTE_COLORS).ok_or_else(|| format!("image has more than {MAX_PALETTE_COLORS} colors"))
+}
+
+/// Opaque colors of `images` in order of first appearance, None when there are more than `limit`.
+pub fn distinct_colors<'a>(images: impl IntoIterator<Item = &'a RgbaImage>, limit: usize) -> Option<Vec<Rgb16>> {
+    let mut seen: HashSet<[u8; 3]> = HashSet::new();
+    let mut out: Vec<Rgb16> = vec![];
+    for img in images {
+        for p in img.pixels() {
+            if p[3] == 0 || !seen.insert([p[0], p[1], p[2]]) {
+                continue;
+            }
+            if out.len() == limit {
+                return None;
+            }
+            out.push(rgb8(p[0], p[1], p[2]));
+        }
+    }
+    Some(out)
+}
+
+/// Parses palette file contents in the given format.
+pub fn parse_palette(bytes: &[u8], format: PaletteFormat) -> Result<Vec<Rgb16>, String> {
+    let text = || String::from_utf8_lossy(bytes);
+    match format {
+        PaletteFormat::Hex => parse_hex_palette(text().trim_start_matches('\u{feff}')),
+        PaletteFormat::Gpl => parse_gpl(&text()),
+        PaletteFormat::JascPal if bytes.starts_with(b"RIFF") => parse_riff_pal(bytes),
+        PaletteFormat::JascPal => parse_jasc_pal(&text()),
+        PaletteFormat::Act => parse_act(bytes),
+        PaletteFormat::Ase => parse_ase(bytes),
+        PaletteFormat::PaintNet => parse_paint_net(&text()),
+        PaletteFormat::PngStrip => parse_png_strip(bytes),
+    }
+}
+
+/// Serializes `palette` in `format`. `name` goes into formats that store one (GPL, ASE).
+pub fn encode_palette(palette: &[Rgb16], format: PaletteFormat, name: &str) -> Result<Vec<u8>, String> {
+    let rgb: Vec<(u8, u8, u8)> = palette.iter().map(|&c| rgb16_to_u8_exact(c)).collect();
+    let hex = |&(r, g, b): &(u8, u8, u8)| format!("{r:02x}{g:02x}{b:02x}");
+
+    let bytes = match format {
+        PaletteFormat::Hex => rgb.iter().map(|c| hex(c) + "\n").collect::<String>().into_bytes(),
+        PaletteFormat::Gpl => {
+            let mut s = format!("GIMP Palette\nName: {name}\nColumns: {}\n#\n", rgb.len().min(16));
+            for c in &rgb {
+                s += &format!("{:3} {:3} {:3}\t#{}\n", c.0, c.1, c.2, hex(c));
+            }
+            s.into_bytes()
+        }
+        PaletteFormat::JascPal => {
+            let mut s = format!("JASC-PAL\r\n0100\r\n{}\r\n", rgb.len());
+            for (r, g, b) in &rgb {
+                s += &format!("{r} {g} {b}\r\n");
+            }
+            s.into_bytes()
+        }
+        PaletteFormat::Act => {
+            if rgb.len() > 256 {
+                return Err(format!("ACT holds 256 colors, palette has {}", rgb.len()));
+            }
+            let mut out = vec![0u8; 772];
+            for (i, (r, g, b)) in rgb.iter().enumerate() {
+                out[i * 3..i * 3 + 3].copy_from_slice(&[*r, *g, *b]);
+            }
+            out[768..770].copy_from_slice(&(rgb.len() as u16).to_be_bytes());
+            out[770..772].copy_from_slice(&0xFFFFu16.to_be_bytes());
+            out
+        }
+        PaletteFormat::Ase => {
+            let mut out = b"ASEF".to_vec();
+            out.extend_from_slice(&1u16.to_be_bytes());
+            out.extend_from_slice(&0u16.to_be_bytes());
+            out.extend_from_slice(&(palette.len() as u32).to_be_bytes());
+            for (c, c8) in palette.iter().zip(&rgb) {
+                let label: Vec<u16> = format!("{name} #{}", hex(c8)).encode_utf16().chain([0]).collect();
+                let mut body = (label.len() as u16).to_be_bytes().to_vec();
+                label.iter().for_each(|u| body.extend_from_slice(&u.to_be_bytes()));
+                body.extend_from_slice(b"RGB ");
+                for v in [c.r, c.g, c.b] {
+                    body.extend_from_slice(&(v as f32 / 65535.0).to_be_bytes());
+                }
+                body.extend_from_slice(&2u16.to_be_bytes()); // normal (not global/spot)
+                out.extend_from_slice(&0x0001u16.to_be_bytes());
+                out.extend_from_slice(&(body.len() as u32).to_be_bytes());
+                out.extend_from_slice(&body);
+            }
+            out
+        }
+        PaletteFormat::PaintNet => {
+            if rgb.len() > PAINT_NET_MAX_COLORS {
+                return Err(format!("Paint.NET palettes hold {PAINT_NET_MAX_COLORS} colors, palette has {}", rgb.len()));
+            }
+            let mut s = format!(";paint.net Palette File\r\n;{name}\r\n;Colors: {}\r\n", rgb.len());
+            for c in &rgb {
+                s += &format!("FF{}\r\n", hex(c).to_ascii_uppercase());
+            }
+            s.into_bytes()
+        }
+        PaletteFormat::PngStrip => {
+            if rgb.is_empty() {
+                return Err("palette is empty".to_string());
+            }
+            let flat: Vec<u8> = rgb.iter().flat_map(|&(r, g, b)| [r, g, b]).collect();
+            let img = RgbImage::from_raw(rgb.len() as u32, 1, flat).ok_or("palette strip size mismatch")?;
+            let mut png = Cursor::new(Vec::new());
+            img.write_to(&mut png, ImageFormat::Png).map_err(|e| e.to_string())?;
+            png.into_inner()
+        }
+    };
+    Ok(bytes)
+}
+
+/// Reads a palette in any supported format (see [`PaletteFormat::detect`]).
+pub fn read_palette_file(path: &Path) -> Result<Palette, String> {
+    let bytes = fs::read(path).map_err(|e| format!("{}: {e}", path.display()))?;
+    let format = PaletteFormat::detect(path, &bytes);
+    let palette = parse_palette(&bytes, format).map_err(|e| format!("{}: {e}", path.display()))?;
+
+    if palette.is_empty() {
+        return Err(format!("{}: palette is empty", path.display()));
+    }
+    if palette.len() > MAX_PALETTE_COLORS {
+        return Err(format!("{}: palette has {} colors, {MAX_PALETTE_COLORS} max", path.display(), palette.len()));
+    }
+    Ok(Palette::new(palette))
+}
+
+/// Writes `palette` in the format picked by the extension of `path` (hex list for unknown ones).
+/// The file stem is used as the palette name.
+pub fn write_palette_file(path: &Path, palette: &[Rgb16]) -> Result<(), String> {
+    let format = PaletteFormat::from_path(path).unwrap_or(PaletteFormat::Hex);
+    let name = path.file_stem().and_then(|s| s.to_str()).unwrap_or("palette");
+    let bytes = encode_palette(palette, format, name).map_err(|e| format!("{}: {e}", path.display()))?;
+    fs::write(path, bytes).map_err(|e| format!("{}: {e}", path.display()))
+}
+
+/// Entry indices of `palette` in `sort` order. `usage` (pixels per entry) is only used
+/// by [`PaletteSort::Usage`]; ties keep their current order.
+pub fn sorted_order(palette: &[Rgb16], sort: PaletteSort, usage: &[usize]) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..palette.len()).collect();
+    match sort {
+        PaletteSort::Hue => {
+            let key = |i: usize| {
+                let (h, s, _) = rgb16_to_hsv(palette[i]);
+                let gray = s < GRAY_SATURATION;
+                (!gray, if gray { 0.0 } else { h }, luminance(palette[i]))
+            };
+            order.sort_by(|&a, &b| key(a).partial_cmp(&key(b)).unwrap_or(std::cmp::Ordering::Equal));
+        }
+        PaletteSort::Luminance => {
+            order.sort_by(|&a, &b| luminance(palette[a]).total_cmp(&luminance(palette[b])));
+        }
+        PaletteSort::Usage => {
+            order.sort_by_key(|&i| std::cmp::Reverse(usage.get(i).copied().unwrap_or(0)));
+        }
+    }
+    order
+}
+
+/// Drops entries closer than `max_delta_e` (CIE76) to an entry that is kept. More used
+/// entries are kept first, so a rare near-duplicate goes and its pixels re-dither to the
+/// common one. The kept entries stay in their order.
+pub fn merge_similar(palette: &[Rgb16], usage: &[usize], max_delta_e: f32) -> Vec<Rgb16> {
+    let mut by_usage: Vec<usize> = (0..palette.len()).collect();
+    by_usage.sort_by_key(|&i| std::cmp::Reverse(usage.get(i).copied().unwrap_or(0)));
+
+    let mut keep = vec![false; palette.len()];
+    for i in by_usage {
+        let close = (0..palette.len()).any(|k| keep[k] && delta_e(palette[k], palette[i]) < max_delta_e);
+        keep[i] = !close;
+    }
+    palette.iter().zip(keep).filter(|(_, k)| *k).map(|(&c, _)| c).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Colors that are exact at 8 bits per channel, so every format can hold them.
+    fn test_palette(n: usize) -> Vec<Rgb16> {
+        (0..n).map(|i| rgb8((i * 37) as u8, 255u8.wrapping_sub((i * 11) as u8), (i * i) as u8)).collect()
+    }
+
+    #[test]
+    fn every_format_round_trips() {
+        let palette = test_palette(20);
+        for format in PaletteFormat::ALL {
+            let bytes = encode_palette(&palette, format, "Test").unwrap();
+            assert_eq!(parse_palette(&bytes, format).unwrap(), palette, "{}", format.label());
+            let path = format!("palette.{}", format.extension());
+            assert_eq!(PaletteFormat::detect(Path::new(&path), &bytes), format, "{}", format.label());
+        }
+    }
+
+    #[test]
+    fn ase_keeps_16_bit_colors() {
+        let palette = vec![Rgb16 { r: 1, g: 32769, b: 65534 }, Rgb16 { r: 0, g: 65535, b: 12345 }];
+        let bytes = encode_palette(&palette, PaletteFormat::Ase, "Deep").unwrap();
+        assert_eq!(parse_ase(&bytes).unwrap(), palette);
+    }
+
+    #[test]
+    fn encoders_reject_palettes_the_format_cannot_hold() {
+        assert!(encode_palette(&test_palette(257), PaletteFormat::Act, "").is_err());
+        assert!(encode_palette(&test_palette(PAINT_NET_MAX_COLORS + 1), PaletteFormat::PaintNet, "").is_err());
+        assert!(encode_palette(&[], PaletteFormat::PngStrip, "").is_err());
+    }
+
+    #[test]
+    fn hex_colors_parse_and_format() {
+        let c = rgb8(0x12, 0xab, 0xff);
+        for s in ["#12abff", "12ABFF", "0x12abff", " #12abff "] {
+            assert_eq!(parse_hex_color(s), Some(c), "{s}");
+        }
+        assert_eq!(format_hex_color(c), "#12ABFF");
+        let deep = Rgb16 { r: 0x1234, g: 0xabcd, b: 0x0001 };
+        assert_eq!(parse_hex_color(&format_hex_color(deep)), Some(deep));
+        for s in ["", "#12345", "#1234567", "#12abfg", "0x", "#ff00ff00"] {
+            assert_eq!(parse_hex_color(s), None, "{s}");
+        }
+    }
+
+    #[test]
+    fn hex_list_rejects_bad_lines() {
+        assert_eq!(parse_hex_palette("; comment\n\n// other\nff0000 red\n").unwrap(), vec![rgb8(255, 0, 0)]);
+        assert!(parse_hex_palette("ff0000\nzzzzzz\n").is_err());
+        assert!(parse_hex_palette("#12345\n").is_err());
+    }
+
+    #[test]
+    fn gpl_rejects_bad_input() {
+        assert!(parse_gpl("").is_err());
+        assert!(parse_gpl("0 0 0\n").is_err());
+        assert!(parse_gpl("GIMP Palette\n1 2\n").is_err());
+        assert!(parse_gpl("GIMP Palette\n256 0 0\n").is_err());
+        assert!(parse_gpl("GIMP Palette\nred green blue\n").is_err());
+    }
+
+    #[test]
+    fn jasc_rejects_bad_input() {
+        assert!(parse_jasc_pal("").is_err());
+        assert!(parse_jasc_pal("JASC\n0100\n1\n0 0 0\n").is_err());
+        assert!(parse_jasc_pal("JASC-PAL\n0100\nmany\n0 0 0\n").is_err());
+        assert!(parse_jasc_pal("JASC-PAL\n0100\n3\n0 0 0\n1 1 1\n").is_err());
+        assert!(parse_jasc_pal("JASC-PAL\n0100\n1\n0 0 300\n").is_err());
+    }
+
+    /// RIFF palette with the given entries, `count` as stored in the header.
+    fn riff(entries: &[[u8; 4]], count: u16) -> Vec<u8> {
+        let mut data = vec![0, 3];
+        data.extend_from_slice(&count.to_le_bytes());
+        entries.iter().for_each(|e| data.extend_from_slice(e));
+        let mut out = b"RIFF".to_vec();
+        out.extend_from_slice(&(data.len() as u32 + 12).to_le_bytes());
+        out.extend_from_slice(b"PAL data");
+        out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        out.extend_from_slice(&data);
+        out
+    }
+
+    #[test]
+    fn riff_pal_reads_entries_and_rejects_truncation() {
+        let bytes = riff(&[[255, 0, 0, 0], [0, 128, 255, 0]], 2);
+        assert_eq!(parse_palette(&bytes, PaletteFormat::JascPal).unwrap(), vec![rgb8(255, 0, 0), rgb8(0, 128, 255)]);
+        assert!(parse_riff_pal(&riff(&[[255, 0, 0, 0]], 2)).is_err());
+        assert!(parse_riff_pal(&bytes[..bytes.len() - 3]).is_err());
+        assert!(parse_riff_pal(b"RIFF\x04\x00\x00\x00PAL ").is_err());
+    }
+
+    #[test]
+    fn act_rejects_short_files_and_caps_the_count() {
+        assert!(parse_act(&[0; 767]).is_err());
+        assert_eq!(parse_act(&[0; 768]).unwrap().len(), 256);
+        let mut bytes = vec![0; 772];
+        bytes[768..770].copy_from_slice(&999u16.to_be_bytes());
+        assert_eq!(parse_act(&bytes).unwrap().len(), 256);
+    }
+
+    #[test]
+    fn ase_rejects_bad_input() {
+        assert!(parse_ase(b"").is_err());
+        assert!(parse_ase(b"ASEF").is_err());
+        assert!(parse_ase(b"GIMP Palette\n0 0 0\n").is_err());
+
+        let bytes = encode_palette(&test_palette(2), PaletteFormat::Ase, "x").unwrap();
+        assert!(parse_ase(&bytes[..bytes.len() - 10]).is_err());
+
+        let mut lab = bytes.clone();
+        let model = lab.windows(4).position(|w| w == b"RGB ").unwrap();
+        lab[model..model + 4].copy_from_slice(b"LAB ");
+        let e = parse_ase(&lab).unwrap_err();
+        assert!(e.contains("not supported"), "{e}");
+    }
+
+    #[test]
+    fn paint_net_rejects_bad_lines() {
+        assert_eq!(parse_paint_net(";c\r\nFFFF0000\r\n00ff00\r\n").unwrap(), vec![rgb8(255, 0, 0), rgb8(0, 255, 0)]);
+        assert!(parse_paint_net("FFGG0000\n").is_err());
+        assert!(parse_paint_net("FF00000\n").is_err());
+        // 8 bytes, but the second character is multi-byte
+        assert!(parse_paint_net("Fé000000\n").is_err());
+    }
+
+    #[test]
+    fn png_strip_rejects_bad_input() {
+        assert!(parse_png_strip(b"not an image").is_err());
+        let many = RgbaImage::from_fn(300, 1, |x, _| image::Rgba([x as u8, (x >> 8) as u8, 0, 255]));
+        let mut png = Cursor::new(Vec::new());
+        many.write_to(&mut png, ImageFormat::Png).unwrap();
+        assert!(parse_png_strip(png.get_ref()).is_err());
+    }
+
+    #[test]
+    fn png_strip_skips_transparent_and_repeated_pixels() {
+        let img = RgbaImage::from_fn(4, 2, |x, y| match (x, y) {
+            (0, 0) => image::Rgba([0, 0, 0, 0]),
+            (_, 0) => image::Rgba([10, 20, 30, 255]),
+            _ => image::Rgba([x as u8, 0, 0, 255]),
+        });
+        let mut png = Cursor::new(Vec::new());
+        img.write_to(&mut png, ImageFormat::Png).unwrap();
+        assert_eq!(
+            parse_png_strip(png.get_ref()).unwrap(),
+            vec![rgb8(10, 20, 30), rgb8(0, 0, 0), rgb8(1, 0, 0), rgb8(2, 0, 0), rgb8(3, 0, 0)]
+        );
+    }
+}
@@ -0,0 +1,467 @@
+﻿use std::path::Path;
+use image::{imageops, RgbaImage};
+use crate::classes::c_adjustments::Adjustments;
+use crate::classes::c_image_buffer16::ImageBuffer16;
+use crate::classes::c_image_frame::ImageFrame;
+use crate::classes::c_indexed_image::IndexedImage;
+use crate::classes::c_job_progress::JobProgress;
+use crate::classes::c_palette::Palette;
+use crate::classes::c_pipeline_settings::PipelineSettings;
+use crate::classes::c_processed_image::ProcessedImage;
+use crate::classes::c_resize_filter::ResizeFilter;
+use crate::classes::c_rgb16::Rgb16;
+use crate::decode_utils::load_frames;
+use crate::palette_utils::{distinct_colors, MAX_PALETTE_COLORS};
+use crate::image_utils::{kmeans_refine_around, resize_interleaved_nearest, rgb16_to_u8_exact, sample_rgb16_from_rgba16, u16_to_u8};
+
+// The processing steps shared by the GUI and the command line.
+
+/// Max number of color samples the quantizer gets, split evenly between frames.
+pub const MAX_SAMPLES: usize = 50_000;
+
+/// Samples within this distance per channel (about 6 in 8-bit) of a locked color count as covered by it.
+const LOCKED_COVER_RADIUS: u16 = 6 * 257;
+
+/// Lloyd iterations that move the free palette entries around the locked ones.
+const LOCKED_REFINE_ITERATIONS: usize = 8;
+
+/// Output size for a `w0`×`h0` source: the target size when set (a missing side keeps
+/// the aspect ratio), otherwise the scale. At least 1x1.
+pub fn target_size(w0: usize, h0: usize, settings: &PipelineSettings) -> (usize, usize) {
+    let (tw, th) = (settings.width as usize, settings.height as usize);
+    let (w, h) = match (tw, th) {
+        (0, 0) => (
+            (w0 as f32 * settings.scale).round() as usize,
+            (h0 as f32 * settings.scale).round() as usize,
+        ),
+        (tw, 0) => (tw, (h0 * tw) / w0.max(1)),
+        (0, th) => ((w0 * th) / h0.max(1), th),
+        size => size,
+    };
+    (w.max(1), h.max(1))
+}
+
+fn resize_rgba8(img: &RgbaImage, settings: &PipelineSettings) -> (Vec<u8>, usize, usize) {
+    let (w0, h0) = (img.width() as usize, img.height() as usize);
+
+    // the plain scale + nearest path keeps the exact sampling of earlier versions
+    if settings.width == 0 && settings.height == 0 && settings.resize_filter == ResizeFilter::Nearest {
+        return resize_interleaved_nearest::<u8, 4>(img.as_raw(), w0, h0, settings.scale);
+    }
+
+    let (w, h) = target_size(w0, h0, settings);
+    if (w, h) == (w0, h0) {
+        return (img.as_raw().clone(), w, h);
+    }
+    let resized = imageops::resize(img, w as u32, h as u32, settings.resize_filter.filter_type());
+    (resized.into_raw(), w, h)
+}
+
+/// Scales decoded frames, converts them to RGBA16 and applies the adjustments.
+/// Returns frames and the scaled size.
+pub fn prepare_frames(decoded: Vec<(RgbaImage, u32)>, settings: &PipelineSettings) -> (Vec<ImageFrame>, usize, usize) {
+    prepare_frames_with(decoded, settings, &JobProgress::new()).unwrap_or_default()
+}
+
+/// [`prepare_frames`] reporting to `progress` after every frame. None when it gets cancelled.
+pub fn prepare_frames_with(decoded: Vec<(RgbaImage, u32)>, settings: &PipelineSettings, progress: &JobProgress) -> Option<(Vec<ImageFrame>, usize, usize)> {
+    let total = decoded.len();
+    let mut frames = Vec::with_capacity(total);
+    let (mut w, mut h) = (0, 0);
+
+    for (i, (rgba8_full, delay_ms)) in decoded.into_iter().enumerate() {
+        if progress.is_cancelled() {
+            return None;
+        }
+        progress.set("Scaling", i as f32 / total as f32);
+
+        let (mut resized8, nw, nh) = resize_rgba8(&rgba8_full, settings);
+
+        let mut source16 = ImageBuffer16::from_rgba8(nw, nh, &resized8);
+        if !settings.adjustments.is_identity() {
+            settings.adjustments.apply(&mut source16);
+            resized8 = source16.data.iter().map(|&v| u16_to_u8(v)).collect();
+        }
+
+        w = nw;
+        h = nh;
+        frames.push(ImageFrame {
+            pure8: rgba8_full.into_raw(),
+            source16,
+            bytes8: resized8,
+            delay_ms,
+            ..Default::default()
+        });
+    }
+
+    Some((frames, w, h))
+}
+
+/// Evenly spread opaque samples from all `frames`, at most `MAX_SAMPLES` in total.
+pub fn sample_frames(frames: &[ImageFrame]) -> Vec<Rgb16> {
+    let per_frame = (MAX_SAMPLES / frames.len().max(1)).max(1);
+    let mut samples = Vec::with_capacity(MAX_SAMPLES);
+    for frame in frames {
+        let src = &frame.source16;
+        samples.extend(sample_rgb16_from_rgba16(&src.data, src.w, src.h, per_frame, 512));
+    }
+    samples
+}
+
+/// Quantizes `samples` to `settings.colors` entries, the locked colors included.
+/// Only the free entries are optimized: samples a locked color already covers are left out
+/// of the quantizer, then the free entries are refined around the locked ones. Locked
+/// colors are always kept; with as many locks as colors no entry is extracted.
+/// A locked color keeps its index in `previous` (the palette it was locked in), the free
+/// entries fill the other slots; locks without a previous slot go to the end.
+pub fn quantize_with_locked(samples: Vec<Rgb16>, settings: &PipelineSettings, previous: Option<&Palette>) -> Palette {
+    let k = (settings.colors as usize).clamp(2, MAX_PALETTE_COLORS);
+    if settings.locked_colors.is_empty() {
+        return settings.quantizer.quantizer().quantize(samples, k);
+    }
+
+    let mut locked: Vec<Rgb16> = vec![];
+    for &c in &settings.locked_colors {
+        if !locked.contains(&c) {
+            locked.push(c);
+        }
+    }
+    locked.truncate(MAX_PALETTE_COLORS);
+
+    let free = k.saturating_sub(locked.len()).min(MAX_PALETTE_COLORS - locked.len());
+    let mut colors = if free == 0 {
+        vec![]
+    } else {
+        let covered = |s: &Rgb16| locked.iter().any(|l| {
+            l.r.abs_diff(s.r) <= LOCKED_COVER_RADIUS && l.g.abs_diff(s.g) <= LOCKED_COVER_RADIUS && l.b.abs_diff(s.b) <= LOCKED_COVER_RADIUS
+        });
+        let uncovered: Vec<Rgb16> = samples.iter().copied().filter(|s| !covered(s)).collect();
+        let seeds = if uncovered.is_empty() { samples.clone() } else { uncovered };
+
+        // extracted entries that land on a locked color are dropped, so ask for more of them
+        // until the free slots are full (or the samples have no more colors to give)
+        let quantizer = settings.quantizer.quantizer();
+        let mut free_colors = vec![];
+        for n in free..=free + locked.len() {
+            free_colors = quantizer.quantize(seeds.clone(), n).into_colors();
+            free_colors.retain(|c| !locked.contains(c));
+            if free_colors.len() >= free {
+                break;
+            }
+        }
+        free_colors.truncate(free);
+        kmeans_refine_around(&samples, &locked, free_colors, LOCKED_REFINE_ITERATIONS)
+    };
+
+    // extracted entries make room for the locked ones, never the other way round
+    colors.retain(|c| !locked.contains(c));
+    colors.truncate(free);
+
+    let mut slots: Vec<Option<Rgb16>> = vec![None; colors.len() + locked.len()];
+    let mut homeless = vec![];
+    for &c in &locked {
+        match previous.and_then(|p| p.iter().position(|&e| e == c)) {
+            Some(i) if i < slots.len() && slots[i].is_none() => slots[i] = Some(c),
+            _ => homeless.push(c),
+        }
+    }
+    for (slot, c) in slots.iter_mut().rev().filter(|s| s.is_none()).zip(homeless.into_iter().rev()) {
+        *slot = Some(c);
+    }
+    // slots the samples had no color for are left out
+    let mut free_colors = colors.into_iter();
+    Palette::new(slots.into_iter().filter_map(|s| s.or_else(|| free_colors.next())).collect())
+}
+
+/// Palette taken from a reference image (e.g. a screenshot of an existing level): its exact
+/// colors when it has at most `MAX_PALETTE_COLORS`, otherwise `settings.colors` entries picked
+/// by the current quantizer, the locked colors included. The reference is read unscaled and
+/// without the adjustments, which belong to the image being converted.
+pub fn palette_from_image(path: &Path, settings: &PipelineSettings) -> Result<Palette, String> {
+    palette_from_image_with(path, settings, &JobProgress::new()).unwrap_or_else(|| Err("cancelled".to_string()))
+}
+
+/// [`palette_from_image`] reporting to `progress`. None when it gets cancelled.
+pub fn palette_from_image_with(path: &Path, settings: &PipelineSettings, progress: &JobProgress) -> Option<Result<Palette, String>> {
+    progress.set("Loading", 0.0);
+    let decoded = match load_frames(path, false) {
+        Ok(d) => d,
+        Err(e) => return Some(Err(e)),
+    };
+    if progress.is_cancelled() {
+        return None;
+    }
+    if let Some(colors) = distinct_colors(decoded.iter().map(|(img, _)| img), MAX_PALETTE_COLORS) {
+        if colors.is_empty() {
+            return Some(Err("image has no opaque pixels".to_string()));
+        }
+        return Some(Ok(Palette::new(colors)));
+    }
+
+    let reference = PipelineSettings {
+        scale: 1.0,
+        width: 0,
+        height: 0,
+        adjustments: Adjustments::default(),
+        ..settings.clone()
+    };
+    let (frames, _, _) = prepare_frames_with(decoded, &reference, progress)?;
+    progress.set("Building palette", 0.5);
+    let palette = quantize_with_locked(sample_frames(&frames), &reference, None);
+    progress.set("Done", 1.0);
+    Some(Ok(palette))
+}
+
+/// One palette for all frames when `settings.shared_palette` is set, otherwise one per frame.
+pub fn build_palettes(frames: &[ImageFrame], settings: &PipelineSettings) -> Vec<Palette> {
+    build_palettes_with(frames, settings, &[], &JobProgress::new()).unwrap_or_default()
+}
+
+/// [`build_palettes`] reporting to `progress` after every palette. None when it gets cancelled.
+/// Locked colors keep their slots in `previous` (same layout as the result, may be empty).
+pub fn build_palettes_with(frames: &[ImageFrame], settings: &PipelineSettings, previous: &[Palette], progress: &JobProgress) -> Option<Vec<Palette>> {
+    let previous_for = |i: usize| (!previous.is_empty()).then(|| palette_for_frame(previous, i));
+    if !settings.shared_palette && frames.len() > 1 {
+        let mut palettes = Vec::with_capacity(frames.len());
+        for (i, f) in frames.iter().enumerate() {
+            if progress.is_cancelled() {
+                return None;
+            }
+            progress.set("Building palette", i as f32 / frames.len() as f32);
+            palettes.push(quantize_with_locked(sample_frames(std::slice::from_ref(f)), settings, previous_for(i)));
+        }
+        return Some(palettes);
+    }
+
+    progress.set("Building palette", 0.0);
+    let palette = quantize_with_locked(sample_frames(frames), settings, previous_for(0));
+    if progress.is_cancelled() { None } else { Some(vec![palette]) }
+}
+
+/// Palette used by `frame_id`: the shared one or the frame's own. `palettes` must not be empty.
+pub fn palette_for_frame(palettes: &[Palette], frame_id: usize) -> &Palette {
+    &palettes[frame_id.min(palettes.len() - 1)]
+}
+
+/// Dithers every frame into `dithered_pure` (and resets `dithered` to it).
+/// With temporal coherence each frame looks at the previous one.
+pub fn dither_frames(frames: &mut [ImageFrame], palettes: &[Palette], settings: &PipelineSettings) {
+    dither_frames_with(frames, palettes, settings, &JobProgress::new());
+}
+
+/// [`dither_frames`] reporting to `progress` after every frame. False when it gets cancelled;
+/// the frames after that are left as they were.
+pub fn dither_frames_with(frames: &mut [ImageFrame], palettes: &[Palette], settings: &PipelineSettings, progress: &JobProgress) -> bool {
+    let ditherer = settings.dither.ditherer(settings.metric, settings.dither_strength);
+
+    for i in 0..frames.len() {
+        if progress.is_cancelled() {
+            return false;
+        }
+        progress.set("Dithering", i as f32 / frames.len() as f32);
+
+        let (done, rest) = frames.split_at_mut(i);
+        let frame = &mut rest[0];
+
+        let prev = if settings.temporal_coherence {
+            done.last().map(|p| (&p.source16, p.dithered_pure.as_slice()))
+        } else {
+            None
+        };
+
+        ditherer.dither(&frame.source16, palette_for_frame(palettes, i), prev, &mut frame.dithered_pure, &mut frame.indices);
+
+        frame.dithered.clear();
+        frame.dithered.extend_from_slice(&frame.dithered_pure);
+    }
+    true
+}
+
+/// Decoded frames → palettes → dithered frames. `fixed` skips the quantizer
+/// (`settings.palette` is not looked at, see [`process_file`]).
+pub fn process_frames(decoded: Vec<(RgbaImage, u32)>, settings: &PipelineSettings, fixed: Option<Palette>) -> ProcessedImage {
+    let (mut frames, w, h) = prepare_frames(decoded, settings);
+
+    let palettes = match fixed {
+        Some(p) => vec![p],
+        None => build_palettes(&frames, settings),
+    };
+
+    dither_frames(&mut frames, &palettes, settings);
+
+    ProcessedImage { frames, palettes, w, h }
+}
+
+/// The whole pipeline for one input file. `fixed` overrides `settings.palette`.
+pub fn process_file(path: &Path, settings: &PipelineSettings, fixed: Option<Palette>) -> Result<ProcessedImage, String> {
+    let fixed = match fixed {
+        Some(p) => Some(p),
+        None => settings.palette.load()?,
+    };
+    let decoded = load_frames(path, settings.load_sequences)?;
+    Ok(process_frames(decoded, settings, fixed))
+}
+
+/// Rebuilds every frame's `dithered` from `dithered_pure`, each pixel getting the color of
+/// its entry (from the frame's index buffer) in `overrides` (same layout as `palettes`).
+pub fn apply_palette_overrides(frames: &mut [ImageFrame], palettes: &[Palette], overrides: &[Palette]) {
+    if palettes.is_empty() || overrides.len() != palettes.len() {
+        return;
+    }
+
+    for (i, frame) in frames.iter_mut().enumerate() {
+        let colors: Vec<(u8, u8, u8)> = palette_for_frame(overrides, i).iter().map(|&c| rgb16_to_u8_exact(c)).collect();
+
+        frame.dithered.clear();
+        frame.dithered.extend_from_slice(&frame.dithered_pure);
+        if frame.indices.len() * 4 != frame.dithered.len() {
+            continue;
+        }
+        for (dst, &id) in frame.dithered.chunks_exact_mut(4).zip(&frame.indices) {
+            if let Some(&(r, g, b)) = colors.get(id as usize) {
+                dst[..3].copy_from_slice(&[r, g, b]);
+            }
+        }
+    }
+}
+
+/// Pixels per entry of palette `slot`, over every frame dithered with it.
+/// Transparent pixels don't count.
+pub fn palette_usage(frames: &[ImageFrame], palettes: &[Palette], slot: usize) -> Vec<usize> {
+    let Some(palette) = palettes.get(slot) else { return vec![]; };
+
+    let mut usage = vec![0; palette.len()];
+    for (i, frame) in frames.iter().enumerate() {
+        if i.min(palettes.len() - 1) != slot {
+            continue;
+        }
+        for (p, &id) in frame.dithered_pure.chunks_exact(4).zip(&frame.indices) {
+            if p[3] >= 128
+                && let Some(n) = usage.get_mut(id as usize) {
+                *n += 1;
+            }
+        }
+    }
+    usage
+}
+
+pub fn has_transparency(frame: &ImageFrame) -> bool {
+    frame.dithered.chunks_exact(4).any(|p| p[3] < 128)
+}
+
+/// Frame output as indices into `palette` (the frame's palette, edited or not: the ditherer's
+/// index buffer is used as is). Transparent pixels get an extra entry appended after the
+/// palette when `with_transparent` is set.
+pub fn frame_to_indexed(frame: &ImageFrame, palette: &Palette, with_transparent: bool) -> IndexedImage {
+    let (w, h) = (frame.source16.w, frame.source16.h);
+    let mut out_palette = palette.clone();
+
+    let transparent = if with_transparent && out_palette.len() < 256 {
+        out_palette.colors_mut().push(Rgb16::default());
+        Some((out_palette.len() - 1) as u8)
+    } else {
+        None
+    };
+
+    let mut indices = Vec::with_capacity(w * h);
+    for (n, p) in frame.dithered.chunks_exact(4).enumerate() {
+        if let Some(t) = transparent
+            && p[3] < 128 {
+            indices.push(t);
+            continue;
+        }
+        // frames that weren't dithered (no index buffer) fall back to the nearest entry
+        let id = match frame.indices.get(n) {
+            Some(&id) => id,
+            None => palette.nearest_index(Rgb16 { r: p[0] as u16 * 257, g: p[1] as u16 * 257, b: p[2] as u16 * 257 }) as u8,
+        };
+        indices.push(id);
+    }
+
+    IndexedImage {
+        w,
+        h,
+        palette: out_palette,
+        indices,
+        transparent,
+    }
+}
+
+/// All frames as indexed images plus their delays in ms.
+pub fn frames_to_indexed(frames: &[ImageFrame], palettes: &[Palette]) -> (Vec<IndexedImage>, Vec<u32>) {
+    let with_transparent = frames.iter().any(has_transparency);
+    let images = frames
+        .iter()
+        .enumerate()
+        .map(|(i, f)| frame_to_indexed(f, palette_for_frame(palettes, i), with_transparent))
+        .collect();
+    let delays = frames.iter().map(|f| f.delay_ms).collect();
+    (images, delays)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gray(v: u16) -> Rgb16 {
+        Rgb16 { r: v, g: v, b: v }
+    }
+
+    fn ramp() -> Vec<Rgb16> {
+        (0..=255u16).map(|v| Rgb16 { r: v * 257, g: (255 - v) * 257, b: 128 * 257 }).collect()
+    }
+
+    #[test]
+    fn locked_colors_fill_free_slots() {
+        let settings = PipelineSettings { colors: 8, locked_colors: vec![gray(0), gray(65535)], ..Default::default() };
+        let palette = quantize_with_locked(ramp(), &settings, None).into_colors();
+        assert_eq!(palette.len(), 8);
+        assert!(palette.contains(&gray(0)) && palette.contains(&gray(65535)));
+    }
+
+    #[test]
+    fn locked_colors_keep_their_slot() {
+        let mut settings = PipelineSettings { colors: 8, ..Default::default() };
+        let extracted = quantize_with_locked(ramp(), &settings, None);
+        let locked = extracted[2];
+        settings.locked_colors = vec![locked];
+
+        for colors in [8, 5, 12] {
+            settings.colors = colors;
+            let palette = quantize_with_locked(ramp(), &settings, Some(&extracted)).into_colors();
+            assert_eq!(palette.len(), colors as usize);
+            assert_eq!(palette[2], locked, "{colors} colors");
+            assert_eq!(palette.iter().filter(|&&c| c == locked).count(), 1);
+        }
+
+        // without a previous palette the lock goes to the end
+        let palette = quantize_with_locked(ramp(), &settings, None).into_colors();
+        assert_eq!(palette.last(), Some(&locked));
+    }
+
+    #[test]
+    fn an_extracted_color_on_a_lock_is_replaced() {
+        // median cut puts 10000 and 30000 in one box, whose mean is the locked color
+        let samples: Vec<Rgb16> = [(10_000, 50), (30_000, 50), (60_000, 100)].iter().flat_map(|&(v, n)| vec![gray(v); n]).collect();
+        let settings = PipelineSettings { colors: 3, locked_colors: vec![gray(20_000)], ..Default::default() };
+        let palette = quantize_with_locked(samples, &settings, None).into_colors();
+        assert_eq!(palette.len(), 3, "{palette:?}");
+        assert_eq!(palette[2], gray(20_000));
+    }
+
+    #[test]
+    fn more_locks_than_colors_keeps_every_lock() {
+        let locked: Vec<Rgb16> = (0..5).map(|i| gray(i * 10_000)).collect();
+        let settings = PipelineSettings { colors: 3, locked_colors: locked.clone(), ..Default::default() };
+        let palette = quantize_with_locked(ramp(), &settings, None).into_colors();
+        assert_eq!(palette, locked);
+    }
+
+    #[test]
+    fn duplicate_locks_count_once() {
+        let settings = PipelineSettings { colors: 4, locked_colors: vec![gray(0), gray(0)], ..Default::default() };
+        let palette = quantize_with_locked(ramp(), &settings, None).into_colors();
+        assert_eq!(palette.len(), 4);
+        assert_eq!(palette.iter().filter(|&&c| c == gray(0)).count(), 1);
+    }
+}
@@ -3,9 +3,18 @@ pub mod c_dithered_image;
 pub mod c_ok_dialog;
 pub mod t_widget;
 pub mod c_top_panel;
-pub mod c_color_box;
-pub mod c_rgb16;
-mod c_config;
+pub mod c_config;
 mod c_config_window;
 mod c_palette_menu;
-mod c_change_color_window;
\ No newline at end of file
+mod c_change_color_window;
+mod c_batch_window;
+mod c_image_state;
+mod c_edit_command;
+mod c_history;
+mod c_history_panel;
+mod c_pending_edit;
+mod c_viewport;
+mod c_compare_mode;
+mod c_eyedropper_pick;
+mod c_color_editor;
+mod c_curve_editor;
\ No newline at end of file
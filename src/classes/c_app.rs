@@ -1,14 +1,27 @@
 ﻿use std::path::PathBuf;
 use eframe::egui;
 use egui::Context;
+use crate::classes::c_batch_window::{BatchWindow, BatchWindowCommands};
 use crate::classes::c_change_color_window::{ColorReplaceCommand, ColorReplaceWindow};
 use crate::classes::c_config::Config;
 use crate::classes::c_config_window::{ConfigWindow, ConfigWindowCommands};
-use crate::classes::c_dithered_image::DitheredImage;
+use crate::classes::c_dithered_image::{DitheredImage, DitheredImageCommand};
+use crate::classes::c_edit_command::EditCommand;
+use crate::classes::c_eyedropper_pick::EyedropperPick;
+use crate::classes::c_history::History;
+use crate::classes::c_history_panel::{HistoryPanel, HistoryPanelCommand};
+use crate::classes::c_image_state::ImageState;
+use crate::classes::c_pending_edit::PendingEdit;
 use crate::classes::c_ok_dialog::{Action, OkDialog};
 use crate::classes::c_palette_menu::{PaletteMenu, PaletteMenuCommand};
 use crate::classes::c_top_panel::{TopMenu, TopPanelCommands};
 use crate::classes::t_widget::UIWidget;
+use retroimager_core::classes::c_preset::PRESET_EXTENSION;
+use retroimager_core::classes::c_project::PROJECT_EXTENSION;
+use retroimager_core::color_utils::shift_color;
+use retroimager_core::export_utils::write_by_extension;
+use retroimager_core::palette_utils::{merge_similar, read_palette_file, sorted_order, write_palette_file, MAX_PALETTE_COLORS};
+use retroimager_core::{PaletteFormat, PaletteSource, PaletteWorker, Preset, Project, Rgb16};
 
 #[derive(Default)]
 pub struct App {
@@ -18,7 +31,16 @@ pub struct App {
     ok_dialog: OkDialog,
     config_window: ConfigWindow,
     palette_menu: PaletteMenu,
-    color_swap: ColorReplaceWindow
+    color_swap: ColorReplaceWindow,
+    batch_window: BatchWindow,
+    history: History,
+    history_panel: HistoryPanel,
+    // settings and image before the config window was opened, for the history entry on save
+    settings_before: Option<(Config, ImageState)>,
+    // history entry for the running job, with the job's version
+    pending_edit: Option<(u64, PendingEdit)>,
+    // "Palette from image" reading its reference image in the background
+    palette_job: Option<PaletteWorker>,
 }
 
 
@@ -29,21 +51,36 @@ impl App {
         // Use the cc.gl (a glow::Context) to create graphics shaders and buffers that you can use
         // for e.g. egui::PaintCallback.
         let mut config = Config::default();
-        config.read_file();
+        let mut ok_dialog = OkDialog::default();
+        if let Err(e) = config.read_file() {
+            eprintln!("{e}");
+            ok_dialog.show_message("Settings problem", &e);
+        }
         Self {
             config_window: ConfigWindow::new(&config),
             dithered_image: DitheredImage::new(&cc.egui_ctx),
-            ok_dialog: OkDialog::default(),
+            ok_dialog,
             top_menu: TopMenu::default(),
             palette_menu: PaletteMenu::default(),
             color_swap: ColorReplaceWindow::default(),
+            batch_window: BatchWindow::default(),
+            history: History::default(),
+            history_panel: HistoryPanel::default(),
+            settings_before: None,
+            pending_edit: None,
+            palette_job: None,
             config: config,
         }
     }
 
     fn match_top_panel_commands(&mut self, ctx: &Context){
         match self.top_menu.get_active_command() {
-            TopPanelCommands::None => {}
+            TopPanelCommands::None => {
+                // message-only dialogs: any button closes them
+                if (!matches!(self.ok_dialog.get_command(), Action::None)) {
+                    self.ok_dialog.close();
+                }
+            }
             TopPanelCommands::OpenFile => {
                 if (self.dithered_image.has_image()) {
                     self.ok_dialog.open_dialog("Rewrite image?", "Cancel", "Open new image?");
@@ -75,9 +112,62 @@ impl App {
                 ctx.send_viewport_cmd(egui::ViewportCommand::Close);
             }
             TopPanelCommands::OpenConfig => {
+                self.settings_before = Some((self.config.clone(), self.dithered_image.state()));
                 self.config_window.open_config_window(&self.config, &self.dithered_image);
                 self.top_menu.clear_active_command();
             }
+            TopPanelCommands::LoadPreset => {
+                self.load_preset(ctx);
+                self.top_menu.clear_active_command();
+            }
+            TopPanelCommands::SavePreset => {
+                self.save_preset();
+                self.top_menu.clear_active_command();
+            }
+            TopPanelCommands::OpenProject => {
+                self.open_project(ctx);
+                self.top_menu.clear_active_command();
+            }
+            TopPanelCommands::SaveProject => {
+                self.save_project(false);
+                self.top_menu.clear_active_command();
+            }
+            TopPanelCommands::SaveProjectEmbedded => {
+                self.save_project(true);
+                self.top_menu.clear_active_command();
+            }
+            TopPanelCommands::Undo => {
+                self.undo(ctx);
+                self.top_menu.clear_active_command();
+            }
+            TopPanelCommands::Redo => {
+                self.redo(ctx);
+                self.top_menu.clear_active_command();
+            }
+            TopPanelCommands::ToggleHistory => {
+                self.history_panel.toggle();
+                self.top_menu.clear_active_command();
+            }
+            TopPanelCommands::ImportPalette => {
+                self.import_palette(ctx);
+                self.top_menu.clear_active_command();
+            }
+            TopPanelCommands::PaletteFromImage => {
+                self.palette_from_image(ctx);
+                self.top_menu.clear_active_command();
+            }
+            TopPanelCommands::ExportPalette => {
+                self.export_palette();
+                self.top_menu.clear_active_command();
+            }
+            TopPanelCommands::UseExtractedPalette => {
+                self.set_palette_source(ctx, PaletteSource::Extract, "Extracted palette".to_string());
+                self.top_menu.clear_active_command();
+            }
+            TopPanelCommands::OpenBatch => {
+                self.batch_window.open_batch_window(&self.config);
+                self.top_menu.clear_active_command();
+            }
         };
 
         self.ok_dialog.clear_command();
@@ -88,44 +178,42 @@ impl App {
             ConfigWindowCommands::None => {}
             ConfigWindowCommands::Save => {
 
-                let (colors_count, size) = self.config_window.get_data_cfg();
+                self.config = self.config_window.get_data_cfg();
 
-                self.config.set_colors_count(colors_count);
-                self.config.set_size(size);
-
-                self.config.write_file();
+                self.save_config();
 
                 self.config_window.close();
 
                 self.dithered_image.reload(ctx, &self.config);
                 self.palette_menu.clear_selection();
+
+                if let Some((config, image)) = self.settings_before.take() {
+                    self.record_settings_change(config, image, None);
+                }
             }
             ConfigWindowCommands::Cancel => {
 
-                let has_change = 
-                    self.config.colors_palette_size != self.config_window.old_colors_palette_size ||
-                        self.config.image_percent != self.config_window.old_image_percent/100.0
-                || self.config_window.is_previewed
-                    ;
-                
-                self.config.set_colors_count(self.config_window.old_colors_palette_size);
-                self.config.set_size(self.config_window.old_image_percent/100.0);
-                
-                self.dithered_image.reload(ctx, &self.config);
-                
+                let has_change = self.config != self.config_window.old_config
+                    || self.config_window.is_previewed;
+
+                self.config = self.config_window.old_config.clone();
+
                 if (has_change) {
-                    self.dithered_image.set_override_colors(self.config_window.palette_override.clone(), ctx);
+                    match self.settings_before.take() {
+                        Some((_, image)) => self.dithered_image.restore(&image, ctx, &self.config),
+                        None => {
+                            self.dithered_image.reload(ctx, &self.config);
+                            self.dithered_image.set_override_colors(self.config_window.palette_override.clone(), ctx);
+                        }
+                    }
                 }
                 self.config_window.close();
+                self.settings_before = None;
             }
             ConfigWindowCommands::Reload => {
-                let (colors_count, size) = self.config_window.get_data_cfg();
+                self.config = self.config_window.get_data_cfg();
 
-                self.config.set_colors_count(colors_count);
-                self.config.set_size(size);
-
-
-                self.dithered_image.reload(ctx, &self.config);
+                self.dithered_image.preview(ctx, &self.config);
 
                 self.config_window.clear_last_command();
                 self.palette_menu.clear_selection();
@@ -133,27 +221,172 @@ impl App {
         }
     }
 
+    pub fn match_batch_window_commands(&mut self, ctx: &Context) {
+        match self.batch_window.get_active_command() {
+            BatchWindowCommands::None => {}
+            BatchWindowCommands::Start => {
+                self.batch_window.start(ctx, &self.config);
+
+                if (self.config.batch_name_pattern != self.batch_window.name_pattern
+                    || self.config.batch_shared_palette != self.batch_window.shared_palette
+                    || self.config.watch_debounce_ms != self.batch_window.debounce_ms
+                    || self.config.watch_preset != self.batch_window.watch_preset) {
+                    self.config.batch_name_pattern = self.batch_window.name_pattern.clone();
+                    self.config.batch_shared_palette = self.batch_window.shared_palette;
+                    self.config.watch_debounce_ms = self.batch_window.debounce_ms;
+                    self.config.watch_preset = self.batch_window.watch_preset.clone();
+                    self.save_config();
+                }
+                self.batch_window.clear_last_command();
+            }
+            BatchWindowCommands::Close => {
+                self.batch_window.close();
+            }
+        }
+    }
+
     pub fn match_palette_panel_commands(&mut self, ctx: &Context) {
 
         match self.palette_menu.last_command {
             PaletteMenuCommand::None => {}
             PaletteMenuCommand::OpenPaletteWindow => {
 
-                self.color_swap.open_window(self.palette_menu.selected.unwrap(), &self.dithered_image);
+                self.color_swap.open_window(self.palette_menu.selected.unwrap(), &self.dithered_image, self.config.hardware_palette);
 
                 self.palette_menu.clear_selection();
             }
+            PaletteMenuCommand::ToggleLock(index) => {
+                self.toggle_lock(index);
+                self.palette_menu.clear_selection();
+            }
+            PaletteMenuCommand::AddColor(color) => {
+                let mut colors = self.dithered_image.get_palette_colors().clone();
+                colors.push(color);
+                self.edit_palette(ctx, colors, format!("Add color {}", self.dithered_image.get_palette_colors().len() + 1));
+                self.palette_menu.clear_selection();
+            }
+            PaletteMenuCommand::DeleteColor(index) => {
+                let mut colors = self.dithered_image.get_palette_colors().clone();
+                if (index < colors.len()) {
+                    colors.remove(index);
+                    self.edit_palette(ctx, colors, format!("Delete color {}", index + 1));
+                }
+                self.palette_menu.clear_selection();
+            }
+            PaletteMenuCommand::MoveColor { from, to } => {
+                let mut colors = self.dithered_image.get_palette_colors().clone();
+                if (from < colors.len() && to < colors.len()) {
+                    let color = colors.remove(from);
+                    colors.insert(to, color);
+                    self.edit_palette(ctx, colors, format!("Move color {} → {}", from + 1, to + 1));
+                }
+                self.palette_menu.clear_selection();
+            }
+            PaletteMenuCommand::Sort(sort) => {
+                let colors = self.dithered_image.get_palette_colors();
+                let order = sorted_order(colors, sort, self.dithered_image.palette_usage());
+                let sorted: Vec<Rgb16> = order.iter().map(|&i| colors[i]).collect();
+                self.edit_palette(ctx, sorted, format!("Sort palette by {}", sort.label().to_lowercase()));
+                self.palette_menu.clear_selection();
+            }
+            PaletteMenuCommand::Merge(max_delta_e) => {
+                let colors = self.dithered_image.get_palette_colors().clone();
+                let merged = merge_similar(&colors, self.dithered_image.palette_usage(), max_delta_e);
+                if (merged.len() < colors.len()) {
+                    let label = format!("Merge {} similar colors", colors.len() - merged.len());
+                    self.edit_palette(ctx, merged, label);
+                } else {
+                    self.ok_dialog.show_message("Merge similar colors", &format!("No two entries are closer than ΔE {max_delta_e:.1}."));
+                }
+                self.palette_menu.clear_selection();
+            }
+            PaletteMenuCommand::Shift { hue, saturation, brightness } => {
+                let from = self.dithered_image.get_palette_colors().clone();
+                let to: Vec<Rgb16> = from.iter().map(|&c| shift_color(c, hue, saturation, brightness)).collect();
+                if (to != from) {
+                    let slot = self.dithered_image.current_palette_slot();
+                    self.dithered_image.recolor_palette_in(ctx, slot, &to);
+                    let label = format!("Shift palette (hue {hue:+.0}°, sat {:+.0}%, bright {:+.0}%)", saturation * 100.0, brightness * 100.0);
+                    self.history.push(EditCommand::RecolorPalette { slot, label, from, to });
+                }
+                self.palette_menu.clear_selection();
+            }
+        }
+    }
+
+    /// Re-dithers the current image against `colors` (added, deleted, reordered or merged
+    /// entries) and records it once the result is shown.
+    fn edit_palette(&mut self, ctx: &Context, colors: Vec<Rgb16>, label: String) {
+        if (colors == *self.dithered_image.get_palette_colors()) {
+            return;
+        }
+        let before = (self.config.clone(), self.dithered_image.state());
+        self.dithered_image.set_palette(ctx, &self.config, colors);
+        self.record_settings_change(before.0, before.1, Some(label));
+    }
+
+    /// Locks or unlocks the color shown in slot `index`. Nothing is re-dithered: the lock
+    /// only matters the next time the palette is rebuilt.
+    fn toggle_lock(&mut self, index: usize) {
+        let Some(&color) = self.dithered_image.get_palette_colors().get(index) else { return; };
+
+        let before = (self.config.clone(), self.dithered_image.state());
+        let label = if let Some(pos) = self.config.locked_colors.iter().position(|&c| c == color) {
+            self.config.locked_colors.remove(pos);
+            format!("Unlock color {}", index + 1)
+        } else {
+            self.config.locked_colors.push(color);
+            format!("Lock color {}", index + 1)
+        };
+        self.save_config();
+        self.config_window = ConfigWindow::new(&self.config);
+        self.record_settings_change(before.0, before.1, Some(label));
+    }
+
+    pub fn match_eyedropper_picks(&mut self, ctx: &Context) {
+        let Some(pick) = self.dithered_image.picked.take() else { return; };
+        if (self.dithered_image.is_busy() || self.config_window.is_open) {
+            return;
+        }
+
+        match pick {
+            EyedropperPick::Entry(index) => {
+                if (index >= self.dithered_image.get_palette_colors().len()) {
+                    return;
+                }
+                // a previewed color of the entry edited so far goes back first
+                if (self.color_swap.is_open && self.color_swap.previewed) {
+                    self.dithered_image.replace_color(ctx, self.color_swap.start_color, self.color_swap.palette_id);
+                }
+                self.color_swap.close();
+                self.color_swap.open_window(index, &self.dithered_image, self.config.hardware_palette);
+            }
+            EyedropperPick::Color(color) => {
+                if (self.color_swap.is_open) {
+                    self.color_swap.end_color = color;
+                } else if (self.dithered_image.get_palette_colors().len() < MAX_PALETTE_COLORS) {
+                    let mut colors = self.dithered_image.get_palette_colors().clone();
+                    colors.push(color);
+                    self.edit_palette(ctx, colors, format!("Add color {}", self.dithered_image.get_palette_colors().len() + 1));
+                }
+            }
         }
     }
 
     pub fn match_color_swap_commands(&mut self, ctx: &Context) {
+        // the hardware palette picked in the editor is a setting, not an undoable edit
+        if (self.color_swap.is_open && self.color_swap.hardware_palette != self.config.hardware_palette) {
+            self.config.hardware_palette = self.color_swap.hardware_palette;
+            self.config_window.set_hardware_palette(self.config.hardware_palette);
+            self.save_config();
+        }
+
         match self.color_swap.last_command {
 
             ColorReplaceCommand::None => {}
             ColorReplaceCommand::Replace => {
-                if (!self.color_swap.previewed) {
-                    self.dithered_image.replace_color(ctx, self.color_swap.end_color, self.color_swap.palette_id);
-                }
+                self.dithered_image.replace_color(ctx, self.color_swap.end_color, self.color_swap.palette_id);
+                self.push_color_change(self.color_swap.start_color, self.color_swap.end_color);
                 self.color_swap.close();
             }
             ColorReplaceCommand::Cancel => {
@@ -167,62 +400,390 @@ impl App {
                 self.color_swap.clear_command();
             }
             ColorReplaceCommand::Reset => {
-                self.dithered_image.replace_color(ctx, self.dithered_image.get_pure_palette_colors()[self.color_swap.palette_id], self.color_swap.palette_id);
+                // the entry may be gone by now, e.g. deleted while the dialog was open
+                if let Some(&original) = self.dithered_image.get_pure_palette_colors().get(self.color_swap.palette_id) {
+                    self.dithered_image.replace_color(ctx, original, self.color_swap.palette_id);
+                    self.push_color_change(self.color_swap.start_color, original);
+                }
                 self.color_swap.close();
             }
         }
     }
-    fn open_save_file(&mut self, ctx: &egui::Context){
+    pub fn match_history_commands(&mut self, ctx: &Context) {
+        match self.history_panel.last_command {
+            HistoryPanelCommand::None => {}
+            HistoryPanelCommand::JumpTo(position) => {
+                while self.history.position() > position && self.undo(ctx) {}
+                while self.history.position() < position && self.redo(ctx) {}
+                self.history_panel.clear_command();
+            }
+        }
+    }
 
-        if (!self.dithered_image.has_image()) {return};
+    fn match_shortcuts(&mut self, ctx: &Context) {
+        if (self.ok_dialog.is_open || self.config_window.is_open || self.color_swap.is_open) {
+            return;
+        }
+        let redo = egui::KeyboardShortcut::new(egui::Modifiers::COMMAND | egui::Modifiers::SHIFT, egui::Key::Z);
+        let undo = egui::KeyboardShortcut::new(egui::Modifiers::COMMAND, egui::Key::Z);
+
+        // shift first: Ctrl+Z also matches Ctrl+Shift+Z
+        if (ctx.input_mut(|i| i.consume_shortcut(&redo))) {
+            self.redo(ctx);
+        } else if (ctx.input_mut(|i| i.consume_shortcut(&undo))) {
+            self.undo(ctx);
+        }
+    }
 
-        let (w, h) = self.dithered_image.size();
-        let bytes: &[u8] = self.dithered_image.get_bytes();
+    fn push_color_change(&mut self, from: Rgb16, to: Rgb16) {
+        if (from != to) {
+            self.history.push(EditCommand::ReplaceColor {
+                slot: self.dithered_image.current_palette_slot(),
+                index: self.color_swap.palette_id,
+                from,
+                to,
+            });
+        }
+    }
 
-        if bytes.len() != w * h * 4 {
-            eprintln!("Bad buffer size: {} != {}", bytes.len(), w * h * 4);
+    /// Records a change from `config`/`image` to the current settings once the
+    /// reload it started is shown. Without a label nothing is recorded if the config is the same.
+    fn record_settings_change(&mut self, config: Config, image: ImageState, label: Option<String>) {
+        if (label.is_none() && config == self.config) {
             return;
         }
+        self.record_edit(PendingEdit::Settings { label, config: Box::new(config), image });
+    }
+
+    fn record_edit(&mut self, edit: PendingEdit) {
+        if (self.dithered_image.is_busy()) {
+            self.pending_edit = Some((self.dithered_image.job_version(), edit));
+        } else {
+            self.finish_edit(edit);
+        }
+    }
+
+    fn finish_edit(&mut self, edit: PendingEdit) {
+        let command = match edit {
+            PendingEdit::LoadImage { from } => EditCommand::LoadImage { from, to: self.dithered_image.state() },
+            PendingEdit::Settings { label, config, image } => EditCommand::ChangeSettings {
+                label: label.unwrap_or_else(|| EditCommand::settings_label(&config, &self.config)),
+                from: config,
+                to: Box::new(self.config.clone()),
+                image_from: image,
+                image_to: self.dithered_image.state(),
+            },
+        };
+        self.history.push(command);
+    }
 
+    pub fn match_image_commands(&mut self) {
+        match self.dithered_image.last_command.clone() {
+            DitheredImageCommand::None => {}
+            DitheredImageCommand::Loaded(version) => {
+                // a newer job replaced the one the entry was waiting for: drop it
+                if let Some((pending_version, edit)) = self.pending_edit.take()
+                    && pending_version == version {
+                    self.finish_edit(edit);
+                }
+                self.dithered_image.clear_command();
+            }
+            DitheredImageCommand::Failed(e) => {
+                self.pending_edit = None;
+                self.ok_dialog.show_message("Can't process image", &e);
+                self.dithered_image.clear_command();
+            }
+        }
+    }
+
+    fn undo(&mut self, ctx: &Context) -> bool {
+        let Some(command) = self.history.undo() else { return false; };
+        self.apply_edit(&command, false, ctx);
+        true
+    }
+
+    fn redo(&mut self, ctx: &Context) -> bool {
+        let Some(command) = self.history.redo() else { return false; };
+        self.apply_edit(&command, true, ctx);
+        true
+    }
+
+    /// Applies one side of `command`: the new state when `forward`, the old one otherwise.
+    fn apply_edit(&mut self, command: &EditCommand, forward: bool, ctx: &Context) {
+        match command {
+            EditCommand::ReplaceColor { slot, index, from, to } => {
+                let color = if (forward) { *to } else { *from };
+                self.dithered_image.replace_color_in(ctx, *slot, color, *index);
+            }
+            EditCommand::RecolorPalette { slot, from, to, .. } => {
+                let colors = if (forward) { to } else { from };
+                self.dithered_image.recolor_palette_in(ctx, *slot, colors);
+            }
+            EditCommand::ChangeSettings { from, to, image_from, image_to, .. } => {
+                let (config, image) = if (forward) { (to, image_to) } else { (from, image_from) };
+                self.config = config.as_ref().clone();
+                self.save_config();
+                self.config_window = ConfigWindow::new(&self.config);
+                self.dithered_image.restore(image, ctx, &self.config);
+            }
+            EditCommand::LoadImage { from, to } => {
+                let image = if (forward) { to } else { from };
+                self.dithered_image.restore(image, ctx, &self.config);
+            }
+        }
+        self.palette_menu.clear_selection();
+    }
+
+    fn save_config(&mut self) {
+        if let Err(e) = self.config.write_file() {
+            eprintln!("{e}");
+            self.ok_dialog.show_message("Settings problem", &e);
+        }
+    }
+
+    fn load_preset(&mut self, ctx: &Context) {
+        let Some(path) = rfd::FileDialog::new()
+            .add_filter("Preset", &[PRESET_EXTENSION])
+            .pick_file()
+        else {
+            return;
+        };
+
+        match Preset::load(&path) {
+            Ok(preset) => {
+                let before = (self.config.clone(), self.dithered_image.state());
+                self.config.apply_preset(&preset);
+                self.save_config();
+                self.config_window = ConfigWindow::new(&self.config);
+                self.dithered_image.reload(ctx, &self.config);
+                self.palette_menu.clear_selection();
+                self.record_settings_change(before.0, before.1, None);
+            }
+            Err(e) => {
+                self.ok_dialog.show_message("Can't load preset", &e);
+            }
+        }
+    }
+
+    fn save_preset(&mut self) {
         let Some(mut path) = rfd::FileDialog::new()
-            .set_file_name("dithered.png")
-            .add_filter("PNG Image", &["png"])
+            .set_file_name("preset.json")
+            .add_filter("Preset", &[PRESET_EXTENSION])
             .save_file()
         else {
             return;
         };
+        if path.extension().is_none() {
+            path.set_extension(PRESET_EXTENSION);
+        }
 
+        let name = path.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+        if let Err(e) = self.config.to_preset(&name).save(&path) {
+            self.ok_dialog.show_message("Can't save preset", &e);
+        }
+    }
+
+    fn open_project(&mut self, ctx: &Context) {
+        let Some(path) = rfd::FileDialog::new()
+            .add_filter("Project", &[PROJECT_EXTENSION])
+            .pick_file()
+        else {
+            return;
+        };
+
+        let project = match Project::load(&path) {
+            Ok(project) => project,
+            Err(e) => {
+                self.ok_dialog.show_message("Can't open project", &e);
+                return;
+            }
+        };
+
+        let before = (self.config.clone(), self.dithered_image.state());
+        self.config.apply_settings(&project.pipeline, &project.export);
+        self.save_config();
+        self.config_window = ConfigWindow::new(&self.config);
+        self.palette_menu.clear_selection();
+        if let Err(e) = self.dithered_image.load_project(&project, &path, ctx, &self.config) {
+            self.ok_dialog.show_message("Can't open project", &e);
+            return;
+        }
+
+        let name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+        self.record_settings_change(before.0, before.1, Some(format!("Open project {name}")));
+    }
+
+    fn save_project(&mut self, embed: bool) {
+        if (!self.dithered_image.has_image()) {return};
+
+        let Some(mut path) = rfd::FileDialog::new()
+            .set_file_name(format!("project.{PROJECT_EXTENSION}"))
+            .add_filter("Project", &[PROJECT_EXTENSION])
+            .save_file()
+        else {
+            return;
+        };
         if path.extension().is_none() {
-            path.set_extension("png");
+            path.set_extension(PROJECT_EXTENSION);
+        }
+
+        let saved = self.dithered_image
+            .to_project(&self.config, embed, &path)
+            .and_then(|project| project.save(&path));
+        if let Err(e) = saved {
+            self.ok_dialog.show_message("Can't save project", &e);
+        }
+    }
+
+    /// Loads a palette file as the fixed target: the image is re-dithered to it without
+    /// running the quantizer, and it replaces the current palettes and edits.
+    fn import_palette(&mut self, ctx: &Context) {
+        let extensions: Vec<&str> = PaletteFormat::ALL.iter().map(|f| f.extension()).collect();
+        let Some(path) = rfd::FileDialog::new()
+            .add_filter("Palette", &extensions)
+            .pick_file()
+        else {
+            return;
+        };
+
+        match read_palette_file(&path) {
+            Ok(colors) => {
+                let name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+                self.set_palette_source(ctx, PaletteSource::Fixed { colors }, format!("Import palette {name}"));
+            }
+            Err(e) => {
+                self.ok_dialog.show_message("Can't import palette", &e);
+            }
         }
+    }
 
-        let buf = bytes.to_vec();
+    /// Uses the colors of a reference image as fixed palette, so a new image matches an
+    /// existing one; see `pipeline_utils::palette_from_image`. A big reference is read in
+    /// the background, [`Self::poll_palette_job`] takes the palette over.
+    fn palette_from_image(&mut self, ctx: &Context) {
+        let Some(path) = rfd::FileDialog::new()
+            .add_filter("Image", &["png", "apng", "jpg", "jpeg", "bmp", "tga", "gif"])
+            .pick_file()
+        else {
+            return;
+        };
 
-        let Some(img) = image::RgbaImage::from_raw(w as u32, h as u32, buf) else {
-            eprintln!("Failed to create RgbaImage from raw bytes");
+        let repaint = ctx.clone();
+        self.palette_job = Some(PaletteWorker::new(&path, self.config.pipeline(), move || repaint.request_repaint()));
+    }
+
+    /// Shows the progress of "Palette from image" and uses the palette once it is done.
+    fn poll_palette_job(&mut self, ctx: &Context) {
+        let Some(job) = &mut self.palette_job else { return; };
+        let Some(result) = job.poll() else {
+            let (stage, fraction) = (job.progress().stage(), job.progress().fraction());
+            let mut cancel = false;
+            egui::Window::new("Palette from image")
+                .collapsible(false)
+                .resizable(false)
+                .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+                .show(ctx, |ui| {
+                    ui.label(job.path().display().to_string());
+                    ui.horizontal(|ui| {
+                        ui.add(egui::ProgressBar::new(fraction).desired_width(240.0).text(format!("{stage} {:.0}%", fraction * 100.0)));
+                        cancel = ui.button("Cancel").clicked();
+                    });
+                });
+            if (cancel) {
+                self.palette_job = None;
+            }
+            // progress changes without input events
+            ctx.request_repaint_after(std::time::Duration::from_millis(50));
             return;
         };
 
-        if let Err(e) = img.save_with_format(&path, image::ImageFormat::Png) {
-            eprintln!("Save failed: {e}");
+        let path = job.path().to_path_buf();
+        self.palette_job = None;
+        match result {
+            Ok(colors) => {
+                let name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+                self.set_palette_source(ctx, PaletteSource::Fixed { colors }, format!("Palette from {name}"));
+            }
+            Err(e) => {
+                self.ok_dialog.show_message("Can't load palette from image", &format!("{}: {e}", path.display()));
+            }
+        }
+    }
+
+    fn set_palette_source(&mut self, ctx: &Context, source: PaletteSource, label: String) {
+        let before = (self.config.clone(), self.dithered_image.state());
+        self.config.palette_source = source;
+        self.save_config();
+        self.config_window = ConfigWindow::new(&self.config);
+        self.dithered_image.reload(ctx, &self.config);
+        self.palette_menu.clear_selection();
+        if (self.dithered_image.has_image()) {
+            self.record_settings_change(before.0, before.1, Some(label));
+        }
+    }
+
+    /// Saves the palette shown in the bottom bar, edits included.
+    fn export_palette(&mut self) {
+        if (!self.dithered_image.has_image()) {return};
+
+        let mut dialog = rfd::FileDialog::new().set_file_name(format!("palette.{}", PaletteFormat::Gpl.extension()));
+        for f in PaletteFormat::ALL {
+            dialog = dialog.add_filter(f.label(), &[f.extension()]);
+        }
+        let Some(mut path) = dialog.save_file() else {
             return;
+        };
+        if path.extension().is_none() {
+            path.set_extension(PaletteFormat::Gpl.extension());
+        }
+
+        if let Err(e) = write_palette_file(&path, self.dithered_image.get_palette_colors()) {
+            self.ok_dialog.show_message("Can't export palette", &e);
+        }
+    }
+
+    fn open_save_file(&mut self, ctx: &egui::Context){
+
+        if (!self.dithered_image.has_image()) {return};
+
+        let (w, h) = self.dithered_image.size();
+        let bytes: &[u8] = self.dithered_image.get_bytes();
+
+        if bytes.len() != w * h * 4 {
+            self.ok_dialog.show_message("Save failed", &format!("Bad buffer size: {} != {}", bytes.len(), w * h * 4));
+            return;
+        }
+
+        let Some(mut path) = rfd::FileDialog::new()
+            .set_file_name(format!("dithered.{}", self.config.export_format.extension()))
+            .add_filter("PNG Image (indexed)", &["png"])
+            .add_filter("IFF ILBM", &["iff", "ilbm", "lbm"])
+            .add_filter("GIF Image", &["gif"])
+            .save_file()
+        else {
+            return;
+        };
+
+        if path.extension().is_none() {
+            path.set_extension(self.config.export_format.extension());
+        }
+
+        let (frames, delays) = self.dithered_image.to_indexed_frames();
+        if let Err(e) = write_by_extension(&path, &frames, &delays, self.dithered_image.current_frame(), &self.config.export()) {
+            self.ok_dialog.show_message("Save failed", &format!("{}: {e}", path.display()));
         }
     }
     fn open_image_picker(&mut self, ctx: &egui::Context) {
         let path: Option<PathBuf> = rfd::FileDialog::new()
-            .add_filter("Image", &["png", "jpg", "jpeg", "bmp", "tga", "gif"])
+            .add_filter("Image", &["png", "apng", "jpg", "jpeg", "bmp", "tga", "gif"])
             .pick_file();
 
         let Some(path) = path else { return; };
 
-        match image::open(&path) {
-            Ok(img) => {
-                self.dithered_image.upload_image(&path, ctx, &self.config, true);
-            }
-            Err(e) => {
-                self.ok_dialog.open_dialog("Ошибка", "OK", &format!("Не удалось открыть файл:\n{e}"));
-            }
-        }
+        // decoding errors come back from the worker as DitheredImageCommand::Failed
+        let before = self.dithered_image.state();
+        self.dithered_image.upload_image(&path, ctx, &self.config, true);
+        self.record_edit(PendingEdit::LoadImage { from: before });
     }
 }
 
@@ -232,18 +793,28 @@ impl eframe::App for App {
 
 
 
-        self.top_menu.update_menu(ctx, &self.dithered_image, !self.ok_dialog.is_open && !self.color_swap.is_open);
+        self.top_menu.update_menu(ctx, &self.dithered_image, &self.history, &self.config, !self.ok_dialog.is_open && !self.color_swap.is_open);
+        let highlight = self.palette_menu.hovered.or(if (self.color_swap.is_open) { Some(self.color_swap.palette_id) } else { None });
+        self.dithered_image.set_highlight(highlight);
         self.dithered_image.update(ctx);
-        self.palette_menu.update_palette(ctx, &self.dithered_image, !self.ok_dialog.is_open && !self.config_window.is_open && !self.color_swap.is_open);
+        self.palette_menu.update_palette(ctx, &self.dithered_image, &self.config.locked_colors, !self.ok_dialog.is_open && !self.config_window.is_open && !self.color_swap.is_open && !self.dithered_image.is_busy());
         self.color_swap.update_color_window(ctx);
 
         self.ok_dialog.update(ctx);
         self.config_window.update(ctx);
+        self.batch_window.update(ctx);
+        self.history_panel.update_history(ctx, &self.history, !self.ok_dialog.is_open && !self.config_window.is_open && !self.color_swap.is_open);
 
+        self.match_shortcuts(ctx);
+        self.match_image_commands();
         self.match_top_panel_commands(ctx);
         self.match_config_window_commands(ctx);
+        self.match_batch_window_commands(ctx);
         self.match_palette_panel_commands(ctx);
         self.match_color_swap_commands(ctx);
+        self.match_eyedropper_picks(ctx);
+        self.match_history_commands(ctx);
+        self.poll_palette_job(ctx);
         // self.open_file_dialog.open_dialog();
     }
 }
@@ -0,0 +1,308 @@
+﻿use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use egui::{Color32, Context};
+use crate::classes::c_config::Config;
+use crate::classes::t_widget::UIWidget;
+use retroimager_core::batch_utils::collect_inputs;
+use retroimager_core::export_utils::is_supported_output;
+use retroimager_core::classes::c_folder_watcher::{clock_time, DEFAULT_POLL_INTERVAL_MS};
+use retroimager_core::classes::c_preset::PRESET_EXTENSION;
+use retroimager_core::{BatchItem, BatchJob, BatchStatus, BatchWorker, FolderWatcher, Preset, WatchWorker};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BatchWindowCommands{
+    None,
+    Start,
+    Close
+}
+
+pub struct BatchWindow{
+    pub is_open: bool,
+    pub source: String,
+    pub output_dir: String,
+    pub name_pattern: String,
+    pub shared_palette: bool,
+    /// Keep converting files of the input folder as they change instead of a one-off batch.
+    pub watch: bool,
+    pub debounce_ms: u64,
+    /// Preset the watch converts with instead of the current settings.
+    pub watch_preset: Option<PathBuf>,
+
+    job: Option<BatchWorker>,
+    watcher: Option<WatchWorker>,
+    watch_log: Vec<(String, BatchItem)>,
+    error: Option<String>,
+
+    last_command: BatchWindowCommands,
+}
+
+impl Default for BatchWindow {
+    fn default() -> Self {
+        Self{
+            is_open: false,
+            source: String::new(),
+            output_dir: String::new(),
+            name_pattern: String::new(),
+            shared_palette: false,
+            watch: false,
+            debounce_ms: 0,
+            watch_preset: None,
+            job: None,
+            watcher: None,
+            watch_log: vec![],
+            error: None,
+            last_command: BatchWindowCommands::None,
+        }
+    }
+}
+
+impl BatchWindow{
+    pub fn open_batch_window(&mut self, config: &Config){
+        self.is_open = true;
+        self.name_pattern = config.batch_name_pattern.clone();
+        self.shared_palette = config.batch_shared_palette;
+        self.debounce_ms = config.watch_debounce_ms;
+        self.watch_preset = config.watch_preset.clone();
+    }
+
+    pub fn close(&mut self){
+        self.is_open = false;
+        self.job = None;
+        self.watcher = None;
+        self.error = None;
+        self.clear_last_command();
+    }
+
+    pub fn clear_last_command(&mut self) {
+        self.last_command = BatchWindowCommands::None;
+    }
+
+    pub fn get_active_command(&self) -> BatchWindowCommands {
+        self.last_command
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.job.as_ref().map(|j| !j.is_done()).unwrap_or(false) || self.is_watching()
+    }
+
+    pub fn is_watching(&self) -> bool {
+        self.watcher.is_some()
+    }
+
+    pub fn stop_watching(&mut self) {
+        self.watcher = None;
+    }
+
+    /// Starts converting with the current pipeline and export settings.
+    pub fn start(&mut self, ctx: &Context, config: &Config) {
+        self.error = None;
+        self.job = None;
+        self.stop_watching();
+
+        if (self.output_dir.trim().is_empty()) {
+            self.error = Some("Choose an output folder".to_string());
+            return;
+        }
+        if (!is_supported_output(Path::new(&self.name_pattern))) {
+            self.error = Some(format!("{}: unsupported output format", self.name_pattern));
+            return;
+        }
+
+        if (self.watch) {
+            let mut settings = Config { batch_name_pattern: self.name_pattern.clone(), ..config.clone() };
+            if let Some(path) = &self.watch_preset {
+                match Preset::load(path) {
+                    Ok(preset) => settings.apply_preset(&preset),
+                    Err(e) => {
+                        self.error = Some(e);
+                        return;
+                    }
+                }
+            }
+            match FolderWatcher::new(
+                self.source.trim(),
+                Path::new(self.output_dir.trim()),
+                &settings.batch_name_pattern,
+                &settings.pipeline(),
+                &settings.export(),
+                Duration::from_millis(self.debounce_ms),
+            ) {
+                Ok(w) => {
+                    self.watch_log.clear();
+                    let ctx = ctx.clone();
+                    let interval = Duration::from_millis(DEFAULT_POLL_INTERVAL_MS);
+                    self.watcher = Some(WatchWorker::new(w, interval, move || ctx.request_repaint()));
+                }
+                Err(e) => self.error = Some(e),
+            }
+            return;
+        }
+
+        match collect_inputs(self.source.trim()) {
+            Ok(inputs) => {
+                let job = BatchJob::new(
+                    inputs,
+                    Path::new(self.output_dir.trim()),
+                    &self.name_pattern,
+                    &config.pipeline(),
+                    &config.export(),
+                    None,
+                    self.shared_palette,
+                );
+                let ctx = ctx.clone();
+                self.job = Some(BatchWorker::new(job, move || ctx.request_repaint()));
+            }
+            Err(e) => self.error = Some(e),
+        }
+    }
+
+    fn poll_watcher(&mut self) {
+        let Some(watcher) = &self.watcher else { return; };
+
+        for result in watcher.poll() {
+            match result {
+                Ok(items) => {
+                    let time = clock_time(SystemTime::now());
+                    self.watch_log.extend(items.into_iter().map(|i| (time.clone(), i)));
+                    self.error = None;
+                }
+                Err(e) => self.error = Some(e),
+            }
+        }
+    }
+
+    fn status_line(ui: &mut egui::Ui, prefix: &str, item: &BatchItem) {
+        let name = item.input.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+        match &item.status {
+            BatchStatus::Pending => { ui.weak(format!("{prefix}…  {name}")); }
+            BatchStatus::Sampled => { ui.label(format!("{prefix}~  {name}")); }
+            BatchStatus::Done => { ui.label(format!("{prefix}✔  {name}")); }
+            BatchStatus::Failed(e) => {
+                ui.colored_label(Color32::LIGHT_RED, format!("{prefix}✖  {name}: {e}"));
+            }
+        }
+    }
+
+    fn preset_name(&self) -> String {
+        match &self.watch_preset {
+            Some(path) => path.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default(),
+            None => "current settings".to_string(),
+        }
+    }
+
+    fn watch_list(&self, ui: &mut egui::Ui) {
+        if (self.is_watching()) {
+            ui.label(format!("Watching {} with {}...", self.source.trim(), self.preset_name()));
+        }
+        egui::ScrollArea::vertical().max_height(240.0).stick_to_bottom(true).show(ui, |ui| {
+            for (time, item) in &self.watch_log {
+                Self::status_line(ui, &format!("[{time}] "), item);
+            }
+        });
+    }
+
+    fn progress_list(&self, ui: &mut egui::Ui) {
+        let Some(job) = &self.job else { return; };
+
+        let (done, total) = job.progress();
+        ui.add(egui::ProgressBar::new(done as f32 / total.max(1) as f32).text(format!("{done} / {total}")));
+
+        egui::ScrollArea::vertical().max_height(240.0).show(ui, |ui| {
+            for item in &job.items {
+                Self::status_line(ui, "", item);
+            }
+        });
+
+        if (job.is_done()) {
+            let failed = job.failed().count();
+            ui.label(format!("{} of {} file(s) converted", total - failed, total));
+        }
+    }
+}
+
+impl UIWidget for BatchWindow {
+    fn update(&mut self, ctx: &Context) {
+        if (!self.is_open) {
+            return;
+        }
+
+        if let Some(job) = &mut self.job {
+            job.poll();
+        }
+        self.poll_watcher();
+
+        let running = self.is_running();
+
+        egui::Window::new("Batch Convert".to_string())
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.add_enabled_ui(!running, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.label("Input folder or glob:");
+                        ui.text_edit_singleline(&mut self.source);
+                        if ui.button("Browse...").clicked()
+                            && let Some(dir) = rfd::FileDialog::new().pick_folder() {
+                            self.source = dir.display().to_string();
+                        }
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("Output folder:          ");
+                        ui.text_edit_singleline(&mut self.output_dir);
+                        if ui.button("Browse...").clicked()
+                            && let Some(dir) = rfd::FileDialog::new().pick_folder() {
+                            self.output_dir = dir.display().to_string();
+                        }
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("File name:                ");
+                        ui.text_edit_singleline(&mut self.name_pattern);
+                    });
+                    ui.weak("{name} input name, {index} number, {ext} input extension; .png/.gif/.iff picks the format");
+                    ui.add_enabled(!self.watch, egui::Checkbox::new(&mut self.shared_palette, "One palette for all images"));
+                    ui.horizontal(|ui| {
+                        ui.checkbox(&mut self.watch, "Watch folder");
+                        ui.label("debounce ms:");
+                        ui.add_enabled(self.watch, egui::DragValue::new(&mut self.debounce_ms).range(0..=10_000));
+                    });
+                    ui.add_enabled_ui(self.watch, |ui| {
+                        ui.horizontal(|ui| {
+                            ui.label("Watch with:");
+                            ui.label(self.preset_name())
+                                .on_hover_text("A preset's settings replace the current ones for the watch; outputs take its format");
+                            if ui.button("Preset...").clicked()
+                                && let Some(path) = rfd::FileDialog::new().add_filter("Preset", &[PRESET_EXTENSION]).pick_file() {
+                                self.watch_preset = Some(path);
+                            }
+                            if (ui.add_enabled(self.watch_preset.is_some(), egui::Button::new("Current settings")).clicked()) {
+                                self.watch_preset = None;
+                            }
+                        });
+                    });
+                });
+
+                if let Some(e) = &self.error {
+                    ui.colored_label(Color32::LIGHT_RED, e);
+                }
+
+                ui.separator();
+                if (self.watch) {
+                    self.watch_list(ui);
+                } else {
+                    self.progress_list(ui);
+                }
+
+                ui.horizontal(|ui| {
+                    if (ui.add_enabled(!running, egui::Button::new("Start")).clicked()) {
+                        self.last_command = BatchWindowCommands::Start;
+                    }
+                    if (self.is_watching() && ui.button("Stop watching").clicked()) {
+                        self.stop_watching();
+                    }
+                    if (ui.button(if (running) { "Stop" } else { "Close" }).clicked()) {
+                        self.last_command = BatchWindowCommands::Close;
+                    }
+                });
+            });
+    }
+}
@@ -1,9 +1,9 @@
 ﻿use egui::Context;
 use crate::classes::c_dithered_image::DitheredImage;
 use crate::classes::c_palette_menu::PaletteMenu;
-use crate::classes::c_rgb16::Rgb16;
+use retroimager_core::{HardwarePalette, Rgb16};
 use crate::classes::t_widget::UIWidget;
-use crate::image_utils::{color32_to_rgb16, rgb16_to_color32};
+use crate::classes::c_color_editor::ColorEditor;
 
 #[derive(Default)]
 pub struct ColorReplaceWindow{
@@ -12,7 +12,9 @@ pub struct ColorReplaceWindow{
     pub start_color: Rgb16,
     pub end_color: Rgb16,
     pub palette_id: usize,
-    pub previewed: bool
+    pub previewed: bool,
+    pub hardware_palette: HardwarePalette,
+    editor: ColorEditor,
 }
 pub enum ColorReplaceCommand{
     None,
@@ -37,14 +39,7 @@ impl UIWidget for ColorReplaceWindow {
                 .show(ctx, |ui|{
                     ui.label("Replace selected palette color with:");
 
-                    // current selected color
-                    let mut new_c32 = rgb16_to_color32(self.end_color);
-
-                    // color picker
-                    ui.color_edit_button_srgba(&mut new_c32);
-
-
-                    self.end_color = color32_to_rgb16(new_c32);
+                    self.editor.show(ui, &mut self.end_color, self.start_color, &mut self.hardware_palette);
 
                     ui.separator();
 
@@ -74,8 +69,9 @@ impl ColorReplaceWindow {
     }
 
 
-    pub fn open_window(&mut self, palette_id: usize, dithered_image: &DitheredImage) {
+    pub fn open_window(&mut self, palette_id: usize, dithered_image: &DitheredImage, hardware_palette: HardwarePalette) {
         let data = dithered_image.get_palette_colors();
+        self.hardware_palette = hardware_palette;
         self.previewed = false;
         self.start_color = data[palette_id].clone();
         self.end_color = data[palette_id].clone();
@@ -0,0 +1,194 @@
+﻿use egui::color_picker::{color_picker_hsva_2d, Alpha};
+use egui::ecolor::Hsva;
+use egui::Ui;
+use retroimager_core::color_utils::{delta_e, hsl_to_rgb16, hsv_to_rgb16, linear_to_srgb, oklch_to_rgb16, rgb16_to_hsl, rgb16_to_hsv, rgb16_to_oklch, srgb_to_linear};
+use retroimager_core::palette_utils::{format_hex_color, parse_hex_color};
+use retroimager_core::{HardwarePalette, Rgb16};
+use crate::egui_utils::rgb16_to_color32;
+
+const SWATCH_SIZE: egui::Vec2 = egui::vec2(56.0, 28.0);
+
+/// Color editor at 16 bits per channel: a picker plus RGB, HSV, HSL, OKLCh and hex fields
+/// that all edit the same color, before/after swatches and the closest color of a
+/// hardware palette.
+#[derive(Default)]
+pub struct ColorEditor {
+    // field values (percent where shown as percent), kept while one group is edited so
+    // e.g. the hue of a gray doesn't jump back to 0
+    hsv: [f32; 3],
+    hsl: [f32; 3],
+    oklch: [f32; 3],
+    hex: String,
+    picker: Hsva,
+    /// Color the fields were last filled from.
+    synced: Option<Rgb16>,
+}
+
+fn unit(v: u16) -> f32 {
+    v as f32 / 65535.0
+}
+
+fn channel(v: f32) -> u16 {
+    (v.clamp(0.0, 1.0) * 65535.0).round() as u16
+}
+
+impl ColorEditor {
+    fn fill(&mut self, c: Rgb16) {
+        let (h, s, v) = rgb16_to_hsv(c);
+        self.hsv = [h, s * 100.0, v * 100.0];
+        let (h, s, l) = rgb16_to_hsl(c);
+        self.hsl = [h, s * 100.0, l * 100.0];
+        let (l, ch, h) = rgb16_to_oklch(c);
+        self.oklch = [l * 100.0, ch, h];
+        self.hex = format_hex_color(c);
+        self.picker = Hsva::from_rgb([srgb_to_linear(unit(c.r)), srgb_to_linear(unit(c.g)), srgb_to_linear(unit(c.b))]);
+        self.synced = Some(c);
+    }
+
+    /// Makes `c` the color after an edit of one field group; the other groups follow.
+    fn apply(&mut self, color: &mut Rgb16, c: Rgb16) {
+        let (hsv, hsl, oklch, hex, picker) = (self.hsv, self.hsl, self.oklch, self.hex.clone(), self.picker);
+        self.fill(c);
+        *color = c;
+        // a group keeps its values while they still give this color, so the edited one
+        // shows what was typed
+        if hsv_to_rgb16(hsv[0], hsv[1] / 100.0, hsv[2] / 100.0) == c {
+            self.hsv = hsv;
+        }
+        if hsl_to_rgb16(hsl[0], hsl[1] / 100.0, hsl[2] / 100.0) == c {
+            self.hsl = hsl;
+        }
+        if oklch_to_rgb16(oklch[0] / 100.0, oklch[1], oklch[2]) == c {
+            self.oklch = oklch;
+        }
+        if parse_hex_color(&hex) == Some(c) {
+            self.hex = hex;
+        }
+        if self.picker_color(picker) == c {
+            self.picker = picker;
+        }
+    }
+
+    fn picker_color(&self, hsva: Hsva) -> Rgb16 {
+        let [r, g, b] = hsva.to_rgb();
+        Rgb16 { r: channel(linear_to_srgb(r)), g: channel(linear_to_srgb(g)), b: channel(linear_to_srgb(b)) }
+    }
+
+    /// Shows the editor for `color`. `before` is the color the edit started from, clicking its
+    /// swatch goes back to it. `hardware` is the palette the nearest color is looked up in.
+    pub fn show(&mut self, ui: &mut Ui, color: &mut Rgb16, before: Rgb16, hardware: &mut HardwarePalette) {
+        if (self.synced != Some(*color)) {
+            self.fill(*color);
+        }
+
+        ui.horizontal(|ui| {
+            ui.vertical(|ui| {
+                ui.label("Before");
+                let (rect, resp) = ui.allocate_exact_size(SWATCH_SIZE, egui::Sense::click());
+                ui.painter().rect_filled(rect, 2.0, rgb16_to_color32(before));
+                if (resp.on_hover_text(format!("{}\nClick to go back", format_hex_color(before))).clicked()) {
+                    self.apply(color, before);
+                }
+            });
+            ui.vertical(|ui| {
+                ui.label("After");
+                let (rect, resp) = ui.allocate_exact_size(SWATCH_SIZE, egui::Sense::hover());
+                ui.painter().rect_filled(rect, 2.0, rgb16_to_color32(*color));
+                resp.on_hover_text(format!("{}\nΔE {:.2} to before", format_hex_color(*color), delta_e(before, *color)));
+            });
+        });
+
+        let mut picker = self.picker;
+        if (color_picker_hsva_2d(ui, &mut picker, Alpha::Opaque)) {
+            let c = self.picker_color(picker);
+            self.picker = picker;
+            if (c != *color) {
+                self.apply(color, c);
+            }
+        }
+
+        egui::Grid::new("color_editor_fields").num_columns(4).spacing([6.0, 4.0]).show(ui, |ui| {
+            let mut rgb = [color.r, color.g, color.b];
+            ui.label("RGB (16-bit)");
+            let mut changed = false;
+            for (v, prefix) in rgb.iter_mut().zip(["R ", "G ", "B "]) {
+                changed |= ui.add(egui::DragValue::new(v).range(0..=65535).speed(64.0).prefix(prefix)).changed();
+            }
+            if (changed) {
+                self.apply(color, Rgb16 { r: rgb[0], g: rgb[1], b: rgb[2] });
+            }
+            ui.end_row();
+
+            ui.label("HSV");
+            let mut hsv = self.hsv;
+            if (Self::triple(ui, &mut hsv, ["H ", "S ", "V "], [360.0, 100.0, 100.0], [" °", " %", " %"])) {
+                self.hsv = hsv;
+                self.apply(color, hsv_to_rgb16(hsv[0], hsv[1] / 100.0, hsv[2] / 100.0));
+            }
+            ui.end_row();
+
+            ui.label("HSL");
+            let mut hsl = self.hsl;
+            if (Self::triple(ui, &mut hsl, ["H ", "S ", "L "], [360.0, 100.0, 100.0], [" °", " %", " %"])) {
+                self.hsl = hsl;
+                self.apply(color, hsl_to_rgb16(hsl[0], hsl[1] / 100.0, hsl[2] / 100.0));
+            }
+            ui.end_row();
+
+            ui.label("OKLCh");
+            let mut oklch = self.oklch;
+            if (Self::triple(ui, &mut oklch, ["L ", "C ", "h "], [100.0, 0.37, 360.0], [" %", "", " °"])) {
+                self.oklch = oklch;
+                self.apply(color, oklch_to_rgb16(oklch[0] / 100.0, oklch[1], oklch[2]));
+            }
+            ui.end_row();
+
+            ui.label("Hex");
+            let resp = ui.add(egui::TextEdit::singleline(&mut self.hex).desired_width(120.0))
+                .on_hover_text("#RRGGBB, or #RRRRGGGGBBBB for 16 bits per channel");
+            if resp.changed()
+                && let Some(c) = parse_hex_color(&self.hex) {
+                self.apply(color, c);
+            }
+            if (resp.lost_focus()) {
+                self.hex = format_hex_color(*color);
+            }
+            ui.end_row();
+        });
+
+        ui.separator();
+        ui.horizontal(|ui| {
+            ui.label("Hardware palette:");
+            egui::ComboBox::from_id_salt("hardware_palette")
+                .selected_text(hardware.label())
+                .show_ui(ui, |ui| {
+                    for hp in HardwarePalette::ALL {
+                        ui.selectable_value(hardware, hp, hp.label());
+                    }
+                });
+        });
+        if let Some(nearest) = hardware.nearest(*color) {
+            ui.horizontal(|ui| {
+                ui.label("Nearest:");
+                let (rect, _) = ui.allocate_exact_size(egui::vec2(SWATCH_SIZE.y, SWATCH_SIZE.y / 2.0), egui::Sense::hover());
+                ui.painter().rect_filled(rect, 2.0, rgb16_to_color32(nearest));
+                ui.label(format!("{} (ΔE {:.2})", format_hex_color(nearest), delta_e(nearest, *color)));
+                if (ui.add_enabled(nearest != *color, egui::Button::new("Use")).clicked()) {
+                    self.apply(color, nearest);
+                }
+            });
+        }
+    }
+
+    /// Three drag values with the given upper bounds (the lower ones are 0); true on change.
+    fn triple(ui: &mut Ui, values: &mut [f32; 3], prefixes: [&str; 3], max: [f32; 3], suffixes: [&str; 3]) -> bool {
+        let mut changed = false;
+        for i in 0..3 {
+            let speed = max[i] / 400.0;
+            changed |= ui
+                .add(egui::DragValue::new(&mut values[i]).range(0.0..=max[i]).speed(speed).max_decimals(4).prefix(prefixes[i]).suffix(suffixes[i]))
+                .changed();
+        }
+        changed
+    }
+}
@@ -0,0 +1,32 @@
+﻿/// How the viewer shows the source next to the dithered result.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CompareMode {
+    /// One image, the dithered one or the source (toggled with T).
+    #[default]
+    Single,
+    /// Source left, dithered right, with the same zoom and pan.
+    SideBySide,
+    /// Source left of a draggable divider, dithered right of it.
+    Split,
+}
+
+impl CompareMode {
+    pub const ALL: [CompareMode; 3] = [
+        CompareMode::Single,
+        CompareMode::SideBySide,
+        CompareMode::Split,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            CompareMode::Single => "Single",
+            CompareMode::SideBySide => "Side by side",
+            CompareMode::Split => "Split",
+        }
+    }
+
+    pub fn next(&self) -> CompareMode {
+        let i = Self::ALL.iter().position(|m| m == self).unwrap_or(0);
+        Self::ALL[(i + 1) % Self::ALL.len()]
+    }
+}
@@ -1,14 +1,43 @@
-﻿use std::fs::File;
-use std::io::{BufReader, BufWriter};
-use std::path::PathBuf;
+﻿use std::path::PathBuf;
 use serde::{Deserialize, Serialize};
+use retroimager_core::batch_utils::DEFAULT_NAME_PATTERN;
+use retroimager_core::classes::c_folder_watcher::DEFAULT_DEBOUNCE_MS;
+use retroimager_core::{Adjustments, ColorMetric, DitherAlgorithm, ExportFormat, ExportSettings, HardwarePalette, PaletteSource, PipelineSettings, Preset, QuantizerKind, ResizeFilter, Rgb16};
 use crate::file_system_utils;
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 #[serde(default)]
 pub struct Config{
     pub colors_palette_size: u16,
     pub image_percent: f32,
+    /// Target size in pixels, 0 = from `image_percent`.
+    pub target_width: u32,
+    pub target_height: u32,
+    pub resize_filter: ResizeFilter,
+    pub adjustments: Adjustments,
+    pub palette_source: PaletteSource,
+    pub locked_colors: Vec<Rgb16>,
+    pub color_metric: ColorMetric,
+    pub quantizer: QuantizerKind,
+    pub dither_algorithm: DitherAlgorithm,
+    pub dither_strength: f32,
+    pub export_format: ExportFormat,
+    pub ilbm_compression: bool,
+    pub gif_loop_count: u16,
+    pub shared_palette: bool,
+    pub temporal_coherence: bool,
+    pub load_sequences: bool,
+    pub batch_name_pattern: String,
+    pub batch_shared_palette: bool,
+    pub watch_debounce_ms: u64,
+    /// Preset a folder watch converts with, None for the current settings.
+    pub watch_preset: Option<PathBuf>,
+    /// Re-dither while config sliders move, without pressing "Draw".
+    pub live_preview: bool,
+    /// Show a quick low resolution pass before the full preview.
+    pub low_res_preview: bool,
+    /// Palette the color editor shows the nearest displayable color of.
+    pub hardware_palette: HardwarePalette,
 }
 
 impl Config {
@@ -19,6 +48,76 @@ impl Config {
     pub fn set_size(&mut self, p0: f32) {
         self.image_percent = p0;
     }
+
+    pub fn pipeline(&self) -> PipelineSettings {
+        PipelineSettings {
+            colors: self.colors_palette_size,
+            scale: self.image_percent,
+            width: self.target_width,
+            height: self.target_height,
+            resize_filter: self.resize_filter,
+            adjustments: self.adjustments.clone(),
+            palette: self.palette_source.clone(),
+            locked_colors: self.locked_colors.clone(),
+            metric: self.color_metric,
+            quantizer: self.quantizer,
+            dither: self.dither_algorithm,
+            dither_strength: self.dither_strength,
+            shared_palette: self.shared_palette,
+            temporal_coherence: self.temporal_coherence,
+            load_sequences: self.load_sequences,
+        }
+    }
+
+    pub fn export(&self) -> ExportSettings {
+        ExportSettings {
+            format: self.export_format,
+            ilbm_compression: self.ilbm_compression,
+            gif_loop_count: self.gif_loop_count,
+        }
+    }
+}
+
+impl Config {
+    /// Takes over every pipeline and export setting of `preset`; GUI-only settings stay.
+    pub fn apply_preset(&mut self, preset: &Preset) {
+        self.apply_settings(&preset.pipeline, &preset.export);
+
+        // batch outputs follow the preset's format
+        let stem = self.batch_name_pattern.rsplit_once('.').map(|(s, _)| s).unwrap_or(&self.batch_name_pattern);
+        self.batch_name_pattern = format!("{stem}.{}", preset.export.format.extension());
+    }
+
+    pub fn apply_settings(&mut self, p: &PipelineSettings, e: &ExportSettings) {
+        self.colors_palette_size = p.colors;
+        self.image_percent = p.scale;
+        self.target_width = p.width;
+        self.target_height = p.height;
+        self.resize_filter = p.resize_filter;
+        self.adjustments = p.adjustments.clone();
+        self.palette_source = p.palette.clone();
+        self.locked_colors = p.locked_colors.clone();
+        self.color_metric = p.metric;
+        self.quantizer = p.quantizer;
+        self.dither_algorithm = p.dither;
+        self.dither_strength = p.dither_strength;
+        self.shared_palette = p.shared_palette;
+        self.temporal_coherence = p.temporal_coherence;
+        self.load_sequences = p.load_sequences;
+
+        self.export_format = e.format;
+        self.ilbm_compression = e.ilbm_compression;
+        self.gif_loop_count = e.gif_loop_count;
+    }
+
+    pub fn to_preset(&self, name: &str) -> Preset {
+        Preset {
+            name: name.to_string(),
+            pipeline: self.pipeline(),
+            export: self.export(),
+            ..Default::default()
+        }
+    }
 }
 
 impl Default for Config {
@@ -26,6 +125,29 @@ impl Default for Config {
         Self {
             colors_palette_size: 8,
             image_percent: 0.8,
+            target_width: 0,
+            target_height: 0,
+            resize_filter: ResizeFilter::Nearest,
+            adjustments: Adjustments::default(),
+            palette_source: PaletteSource::Extract,
+            locked_colors: vec![],
+            color_metric: ColorMetric::Rgb,
+            quantizer: QuantizerKind::MedianCut,
+            dither_algorithm: DitherAlgorithm::FloydSteinberg,
+            dither_strength: 1.0,
+            export_format: ExportFormat::Png,
+            ilbm_compression: true,
+            gif_loop_count: 0,
+            shared_palette: true,
+            temporal_coherence: true,
+            load_sequences: true,
+            batch_name_pattern: DEFAULT_NAME_PATTERN.to_string(),
+            batch_shared_palette: false,
+            watch_debounce_ms: DEFAULT_DEBOUNCE_MS,
+            watch_preset: None,
+            live_preview: true,
+            low_res_preview: true,
+            hardware_palette: HardwarePalette::None,
         }
     }
 }
@@ -41,41 +163,50 @@ impl Config {
     }
 
     pub fn full_file_path() -> PathBuf {
-        return file_system_utils::get_app_dir().join("config.json");
+        file_system_utils::get_config_path(&Self::file_name())
     }
 
-    pub fn read_file(&mut self){
-        if (file_system_utils::is_file_exist(Self::file_name().as_str())){
-            let file = match File::open(Self::full_file_path()){
-                Ok(file) => file,
-                Err(error) => panic!("There was a problem opening the file: {:?}", error),
-            };
-
-
+    /// Loads the config file, or keeps the defaults when there is none.
+    /// A file that can't be parsed is backed up and replaced by defaults.
+    /// Never fails hard: problems come back as a message for the user.
+    pub fn read_file(&mut self) -> Result<(), String> {
+        let path = Self::full_file_path();
 
-            let reader = BufReader::new(file);
-
-            let this: Config = match serde_json::from_reader(reader) {
-                Ok(config) => config,
-                Err(error) => panic!("There was a problem deserializing the file: {:?}", error),
-            };
+        if (!path.is_file()) {
+            return self.write_file();
+        }
 
-            *self = this;
-            Self::write_file(self);
-        }else{
-            Self::write_file(self);
+        let text = match std::fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) => return Err(format!("Can't read {}:\n{e}\nUsing default settings.", path.display())),
+        };
+
+        match serde_json::from_str::<Config>(&text) {
+            Ok(config) => {
+                *self = config;
+                // rewrite so fields added since the file was saved show up in it
+                self.write_file()
+            }
+            Err(e) => {
+                *self = Config::default();
+                let backup = match file_system_utils::backup_file(&path) {
+                    Ok(backup) => format!("The old file was moved to {}.", backup.display()),
+                    Err(be) => format!("Backing it up failed too: {be}"),
+                };
+                let write = self.write_file().err().map(|w| format!("\n{w}")).unwrap_or_default();
+                Err(format!("{} is damaged ({e}).\n{backup}\nUsing default settings.{write}", path.display()))
+            }
         }
     }
 
-    pub fn write_file(&self){
-        let file = File::create(Self::full_file_path()).unwrap();
-        let writer = BufWriter::new(file);
+    pub fn write_file(&self) -> Result<(), String> {
+        let path = Self::full_file_path();
+        let json = serde_json::to_string_pretty(self).map_err(|e| format!("Can't save settings: {e}"))?;
+
+        file_system_utils::write_atomic(&path, json.as_bytes())
+            .map_err(|e| format!("Can't save settings to {}:\n{e}", path.display()))?;
 
         println!("{}", "Config saved");
-        match serde_json::to_writer_pretty(writer, self){
-            Ok(_) => (),
-            Err(e) => panic!("{}", e)
-        }
+        Ok(())
     }
 }
-
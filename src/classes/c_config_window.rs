@@ -1,7 +1,18 @@
 ﻿use crate::classes::c_config::Config;
+use crate::classes::c_curve_editor::CurveEditor;
 use crate::classes::c_dithered_image::DitheredImage;
-use crate::classes::c_rgb16::Rgb16;
 use crate::classes::t_widget::UIWidget;
+use retroimager_core::classes::c_adjustments::{MAX_DENOISE_RADIUS, MAX_SHARPEN_RADIUS};
+use retroimager_core::classes::c_pipeline_settings::MAX_TARGET_SIZE;
+use retroimager_core::{Adjustments, ColorMetric, DitherAlgorithm, ExportFormat, HardwarePalette, Palette, PipelineSettings, QuantizerKind, ResizeFilter};
+use std::time::{Duration, Instant};
+
+/// Live preview starts once the settings stayed unchanged this long...
+const PREVIEW_DEBOUNCE_MS: u64 = 150;
+/// ...or, while they keep changing, at most this often.
+const PREVIEW_THROTTLE_MS: u64 = 400;
+/// Levels per channel when posterize is switched on.
+const POSTERIZE_DEFAULT_LEVELS: u8 = 4;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ConfigWindowCommands{
@@ -13,16 +24,21 @@ pub enum ConfigWindowCommands{
 
 pub struct ConfigWindow{
     pub is_open: bool,
-    colors_palette_size: u16,
+    config: Config,
     image_percent: f32,
 
     pub is_previewed: bool,
-    pub old_colors_palette_size: u16,
-    pub old_image_percent: f32,
+    pub old_config: Config,
+
+    pub palette_override : Vec<Palette>,
 
-    pub palette : Vec<Rgb16>,
-    pub palette_override : Vec<Rgb16>,
+    // live preview: settings seen last frame, settings last previewed and when
+    seen_pipeline: PipelineSettings,
+    previewed_pipeline: PipelineSettings,
+    edited_at: Option<Instant>,
+    previewed_at: Option<Instant>,
 
+    curve_editor: CurveEditor,
 
     last_command: ConfigWindowCommands,
 }
@@ -32,13 +48,16 @@ impl Default for ConfigWindow {
     fn default() -> Self {
         Self{
             is_open: false,
-            colors_palette_size: 0,
+            config: Config::default(),
             image_percent: 0.0,
             is_previewed: false,
-            old_colors_palette_size: 0,
-            old_image_percent: 0.0,
-            palette: vec![],
+            old_config: Config::default(),
             palette_override: vec![],
+            seen_pipeline: PipelineSettings::default(),
+            previewed_pipeline: PipelineSettings::default(),
+            edited_at: None,
+            previewed_at: None,
+            curve_editor: CurveEditor::default(),
             last_command: ConfigWindowCommands::None,
 
         }
@@ -49,13 +68,16 @@ impl ConfigWindow{
     pub fn new(config: &Config) -> Self{
         Self {
             is_open: false,
-            colors_palette_size: config.colors_palette_size,
+            config: config.clone(),
             image_percent: config.image_percent * 100.0,
             is_previewed: false,
-            old_colors_palette_size: 0,
-            old_image_percent: 0.0,
-            palette: vec![],
+            old_config: config.clone(),
             palette_override: vec![],
+            seen_pipeline: config.pipeline(),
+            previewed_pipeline: config.pipeline(),
+            edited_at: None,
+            previewed_at: None,
+            curve_editor: CurveEditor::default(),
             last_command: ConfigWindowCommands::None,
 
         }
@@ -71,20 +93,135 @@ impl ConfigWindow{
     }
     pub fn open_config_window(&mut self, config: &Config, dithered_image: &DitheredImage){
         self.is_open = true;
-        self.colors_palette_size = config.colors_palette_size;
+        self.config = config.clone();
         self.image_percent = config.image_percent * 100.0;
 
-        self.old_colors_palette_size = self.colors_palette_size;
-        self.old_image_percent = self.image_percent;
+        self.old_config = config.clone();
 
-        self.palette = dithered_image.get_pure_palette_colors().clone();
-        self.palette_override = dithered_image.get_palette_colors().clone();
+        self.palette_override = dithered_image.get_all_palette_colors().clone();
 
         self.is_previewed = false;
+        self.seen_pipeline = config.pipeline();
+        self.previewed_pipeline = config.pipeline();
+        self.edited_at = None;
+        self.previewed_at = None;
     }
 
-    pub fn get_data_cfg(&self) -> (u16, f32){
-        (self.colors_palette_size, self.image_percent/100.0)
+    fn request_preview(&mut self) {
+        self.is_previewed = true;
+        self.previewed_pipeline = self.get_data_cfg().pipeline();
+        self.previewed_at = Some(Instant::now());
+        self.last_command = ConfigWindowCommands::Reload;
+    }
+
+    /// Asks for a preview once the settings settle, or every `PREVIEW_THROTTLE_MS` while
+    /// they keep changing, so dragging a slider doesn't queue a job per frame.
+    fn live_preview(&mut self, ctx: &egui::Context) {
+        if (!self.config.live_preview || self.last_command != ConfigWindowCommands::None) {
+            return;
+        }
+
+        let current = self.get_data_cfg().pipeline();
+        if (current != self.seen_pipeline) {
+            self.seen_pipeline = current.clone();
+            self.edited_at = Some(Instant::now());
+        }
+        if (current == self.previewed_pipeline) {
+            return;
+        }
+
+        let settled = self.edited_at.map(|t| t.elapsed() >= Duration::from_millis(PREVIEW_DEBOUNCE_MS)).unwrap_or(true);
+        let throttle_over = self.previewed_at.map(|t| t.elapsed() >= Duration::from_millis(PREVIEW_THROTTLE_MS)).unwrap_or(true);
+        if (settled || throttle_over) {
+            self.request_preview();
+        } else {
+            ctx.request_repaint_after(Duration::from_millis(PREVIEW_DEBOUNCE_MS));
+        }
+    }
+
+    /// Takes over a hardware palette picked in the color editor, so saving doesn't undo it.
+    pub fn set_hardware_palette(&mut self, hardware_palette: HardwarePalette) {
+        self.config.hardware_palette = hardware_palette;
+        self.old_config.hardware_palette = hardware_palette;
+    }
+
+    /// Corrections applied to the source before the palette is built.
+    fn adjustments_ui(&mut self, ui: &mut egui::Ui) {
+        let a = &mut self.config.adjustments;
+        let header = if (a.is_identity()) { "Adjustments".to_string() } else { "Adjustments (active)".to_string() };
+        egui::CollapsingHeader::new(header).id_salt("adjustments").show(ui, |ui| {
+            egui::Grid::new("adjustments_grid").num_columns(2).show(ui, |ui| {
+                ui.label("Brightness:");
+                ui.add(egui::Slider::new(&mut a.brightness, -1.0..=1.0));
+                ui.end_row();
+                ui.label("Contrast:");
+                ui.add(egui::Slider::new(&mut a.contrast, -1.0..=1.0));
+                ui.end_row();
+                ui.label("Gamma:");
+                ui.add(egui::Slider::new(&mut a.gamma, 0.2..=5.0).logarithmic(true));
+                ui.end_row();
+                ui.label("Saturation:");
+                ui.add(egui::Slider::new(&mut a.saturation, 0.0..=2.0));
+                ui.end_row();
+                ui.label("Hue Shift:");
+                ui.add(egui::Slider::new(&mut a.hue_shift, -180.0..=180.0).suffix("°"));
+                ui.end_row();
+
+                ui.label("Input Levels:");
+                ui.horizontal(|ui| {
+                    ui.add(egui::DragValue::new(&mut a.levels_in_black).range(0.0..=1.0).speed(0.002).prefix("black "));
+                    ui.add(egui::DragValue::new(&mut a.levels_in_white).range(0.0..=1.0).speed(0.002).prefix("white "));
+                });
+                ui.end_row();
+                ui.label("Output Levels:");
+                ui.horizontal(|ui| {
+                    ui.add(egui::DragValue::new(&mut a.levels_out_black).range(0.0..=1.0).speed(0.002).prefix("black "));
+                    ui.add(egui::DragValue::new(&mut a.levels_out_white).range(0.0..=1.0).speed(0.002).prefix("white "));
+                });
+                ui.end_row();
+
+                ui.label("Curve:");
+                ui.vertical(|ui| {
+                    self.curve_editor.show(ui, &mut a.curve);
+                    if (ui.add_enabled(!a.curve.is_empty(), egui::Button::new("Reset curve")).clicked()) {
+                        a.curve.clear();
+                    }
+                });
+                ui.end_row();
+
+                ui.label("Sharpen:");
+                ui.horizontal(|ui| {
+                    ui.add(egui::Slider::new(&mut a.sharpen_amount, 0.0..=3.0).text("amount"));
+                    ui.add_enabled(a.sharpen_amount > 0.0, egui::Slider::new(&mut a.sharpen_radius, 0.3..=MAX_SHARPEN_RADIUS).text("radius"));
+                });
+                ui.end_row();
+                ui.label("Denoise (median):");
+                ui.add(egui::Slider::new(&mut a.denoise_radius, 0..=MAX_DENOISE_RADIUS).text("radius, 0 = off"));
+                ui.end_row();
+                ui.label("Posterize:");
+                ui.horizontal(|ui| {
+                    let mut on = a.posterize >= 2;
+                    if (ui.checkbox(&mut on, "").changed()) {
+                        a.posterize = if (on) { POSTERIZE_DEFAULT_LEVELS } else { 0 };
+                    }
+                    let mut levels = a.posterize.max(2);
+                    if (ui.add_enabled(on, egui::Slider::new(&mut levels, 2..=32).text("levels")).changed()) {
+                        a.posterize = levels;
+                    }
+                });
+                ui.end_row();
+            });
+
+            if (ui.add_enabled(!a.is_identity(), egui::Button::new("Reset adjustments")).clicked()) {
+                *a = Adjustments::default();
+            }
+        });
+    }
+
+    pub fn get_data_cfg(&self) -> Config {
+        let mut config = self.config.clone();
+        config.set_size(self.image_percent / 100.0);
+        config
     }
 
     pub fn get_active_command(&self) -> ConfigWindowCommands {
@@ -105,9 +242,107 @@ impl UIWidget for ConfigWindow {
                             ui.add(egui::Slider::new(&mut self.image_percent, 10.0..=100.0));
                         });
 
+                        ui.horizontal(|ui| {
+                            ui.label("Target Size (0 = %):");
+                            ui.add(egui::DragValue::new(&mut self.config.target_width).range(0..=MAX_TARGET_SIZE).prefix("w "));
+                            ui.add(egui::DragValue::new(&mut self.config.target_height).range(0..=MAX_TARGET_SIZE).prefix("h "));
+                        });
+
+                        ui.horizontal(|ui| {
+                            ui.label("Resize Filter:        ");
+                            egui::ComboBox::from_id_salt("resize_filter")
+                                .selected_text(self.config.resize_filter.label())
+                                .show_ui(ui, |ui| {
+                                    for f in ResizeFilter::ALL {
+                                        ui.selectable_value(&mut self.config.resize_filter, f, f.label());
+                                    }
+                                });
+                        });
+
                         ui.horizontal(|ui| {
                             ui.label("Colors Count:       ");
-                            ui.add(egui::Slider::new(&mut self.colors_palette_size, 2..=32));
+                            ui.add(egui::Slider::new(&mut self.config.colors_palette_size, 2..=32));
+                        });
+
+                        if (!self.config.locked_colors.is_empty()) {
+                            ui.horizontal(|ui| {
+                                ui.label(format!("Locked colors: {} (kept when the palette is rebuilt)", self.config.locked_colors.len()));
+                                if (ui.button("Unlock all").clicked()) {
+                                    self.config.locked_colors.clear();
+                                }
+                            });
+                        }
+
+                        ui.horizontal(|ui| {
+                            ui.label("Quantizer:            ");
+                            egui::ComboBox::from_id_salt("quantizer")
+                                .selected_text(self.config.quantizer.label())
+                                .show_ui(ui, |ui| {
+                                    for q in QuantizerKind::ALL {
+                                        ui.selectable_value(&mut self.config.quantizer, q, q.label());
+                                    }
+                                });
+                        });
+
+                        ui.horizontal(|ui| {
+                            ui.label("Dithering:             ");
+                            egui::ComboBox::from_id_salt("dither_algorithm")
+                                .selected_text(self.config.dither_algorithm.label())
+                                .show_ui(ui, |ui| {
+                                    for d in DitherAlgorithm::ALL {
+                                        ui.selectable_value(&mut self.config.dither_algorithm, d, d.label());
+                                    }
+                                });
+                        });
+
+                        ui.horizontal(|ui| {
+                            ui.label("Dither Strength:     ");
+                            ui.add(egui::Slider::new(&mut self.config.dither_strength, 0.0..=1.0));
+                        });
+
+                        ui.horizontal(|ui| {
+                            ui.label("Color Distance:      ");
+                            egui::ComboBox::from_id_salt("color_metric")
+                                .selected_text(self.config.color_metric.label())
+                                .show_ui(ui, |ui| {
+                                    for m in ColorMetric::ALL {
+                                        ui.selectable_value(&mut self.config.color_metric, m, m.label());
+                                    }
+                                });
+                        });
+
+                        ui.separator();
+                        self.adjustments_ui(ui);
+
+                        ui.separator();
+                        ui.label("Animation");
+                        ui.checkbox(&mut self.config.shared_palette, "Shared palette across frames");
+                        ui.checkbox(&mut self.config.temporal_coherence, "Temporal coherence (no flicker on static areas)");
+                        ui.checkbox(&mut self.config.load_sequences, "Load numbered PNG sequences");
+
+                        ui.separator();
+                        ui.label("Export");
+                        ui.horizontal(|ui| {
+                            ui.label("Format:");
+                            egui::ComboBox::from_id_salt("export_format")
+                                .selected_text(self.config.export_format.label())
+                                .show_ui(ui, |ui| {
+                                    for f in ExportFormat::ALL {
+                                        ui.selectable_value(&mut self.config.export_format, f, f.label());
+                                    }
+                                });
+                        });
+                        ui.checkbox(&mut self.config.ilbm_compression, "IFF ByteRun1 compression");
+
+                        ui.horizontal(|ui| {
+                            ui.label("GIF Loops (0 = forever):");
+                            ui.add(egui::DragValue::new(&mut self.config.gif_loop_count).range(0..=1000));
+                        });
+
+                        ui.separator();
+                        ui.horizontal(|ui| {
+                            ui.checkbox(&mut self.config.live_preview, "Live preview");
+                            ui.add_enabled(self.config.live_preview, egui::Checkbox::new(&mut self.config.low_res_preview, "Low-res pass first"));
                         });
 
                         ui.horizontal(|ui| {
@@ -118,12 +353,13 @@ impl UIWidget for ConfigWindow {
                                 self.last_command = ConfigWindowCommands::Cancel;
                             }
                             if (ui.button("Draw").clicked()) {
-                                self.is_previewed = true;
-                                self.last_command = ConfigWindowCommands::Reload;
+                                self.request_preview();
                             }
                         })
                     })
                 });
+
+            self.live_preview(ctx);
         }
     }
-}
\ No newline at end of file
+}
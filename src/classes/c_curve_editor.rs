@@ -0,0 +1,100 @@
+﻿use egui::{Color32, Pos2, Rect, Sense, Stroke, Ui};
+use retroimager_core::Adjustments;
+
+const CURVE_SIZE: f32 = 160.0;
+/// Screen distance in points within which a control point is grabbed.
+const GRAB_RADIUS: f32 = 7.0;
+/// Line segments the curve is drawn with.
+const CURVE_SEGMENTS: usize = 64;
+/// Smallest input distance between neighbouring points.
+const MIN_POINT_GAP: f32 = 0.01;
+
+/// Tone curve graph for [`Adjustments::curve`]: drag points to move them, click or drag
+/// empty space to add one, right-click a point to remove it.
+#[derive(Default)]
+pub struct CurveEditor {
+    dragged: Option<usize>,
+}
+
+impl CurveEditor {
+    /// Shows the graph for `points` (sorted by input). True when they changed.
+    pub fn show(&mut self, ui: &mut Ui, points: &mut Vec<[f32; 2]>) -> bool {
+        let (rect, response) = ui.allocate_exact_size(egui::vec2(CURVE_SIZE, CURVE_SIZE), Sense::click_and_drag());
+        let to_screen = |p: [f32; 2]| Pos2::new(rect.left() + p[0] * rect.width(), rect.bottom() - p[1] * rect.height());
+        let to_curve = |p: Pos2| [((p.x - rect.left()) / rect.width()).clamp(0.0, 1.0), ((rect.bottom() - p.y) / rect.height()).clamp(0.0, 1.0)];
+
+        let pointer = response.interact_pointer_pos().or(response.hover_pos());
+        let hovered = pointer.and_then(|p| {
+            points.iter()
+                .enumerate()
+                .map(|(i, &q)| (i, to_screen(q).distance(p)))
+                .filter(|&(_, d)| d <= GRAB_RADIUS)
+                .min_by(|a, b| a.1.total_cmp(&b.1))
+                .map(|(i, _)| i)
+        });
+
+        let mut changed = false;
+        if (response.drag_started() || response.clicked())
+            && let Some(pos) = pointer {
+            self.dragged = match hovered {
+                Some(i) => Some(i),
+                None => Some(Self::insert(points, to_curve(pos))),
+            };
+            changed |= hovered.is_none();
+        }
+        if response.dragged()
+            && let (Some(i), Some(pos)) = (self.dragged, pointer)
+            && i < points.len() {
+            let [x, y] = to_curve(pos);
+            let lo = if (i > 0) { points[i - 1][0] + MIN_POINT_GAP } else { 0.0 };
+            let hi = if (i + 1 < points.len()) { points[i + 1][0] - MIN_POINT_GAP } else { 1.0 };
+            let moved = [x.clamp(lo.min(hi), hi.max(lo)), y];
+            changed |= points[i] != moved;
+            points[i] = moved;
+        }
+        if (response.drag_stopped() || response.clicked()) {
+            self.dragged = None;
+        }
+        if response.secondary_clicked()
+            && let Some(i) = hovered.filter(|_| points.len() > 2) {
+            points.remove(i);
+            changed = true;
+        }
+
+        let painter = ui.painter_at(rect);
+        let visuals = ui.visuals();
+        painter.rect_filled(rect, 2.0, visuals.extreme_bg_color);
+        let grid = Stroke::new(1.0, visuals.widgets.noninteractive.bg_stroke.color);
+        for i in 1..4 {
+            let t = i as f32 / 4.0;
+            painter.line_segment([to_screen([t, 0.0]), to_screen([t, 1.0])], grid);
+            painter.line_segment([to_screen([0.0, t]), to_screen([1.0, t])], grid);
+        }
+        painter.line_segment([to_screen([0.0, 0.0]), to_screen([1.0, 1.0])], grid);
+
+        let samples = Adjustments::sample_curve(points, CURVE_SEGMENTS + 1);
+        let line: Vec<Pos2> = samples.iter().enumerate().map(|(i, &y)| to_screen([i as f32 / CURVE_SEGMENTS as f32, y])).collect();
+        painter.add(egui::Shape::line(line, Stroke::new(1.5, visuals.text_color())));
+
+        for (i, &p) in points.iter().enumerate() {
+            let active = Some(i) == self.dragged || Some(i) == hovered;
+            let fill = if (active) { visuals.selection.bg_fill } else { visuals.text_color() };
+            painter.circle(to_screen(p), 3.5, fill, Stroke::new(1.0, Color32::BLACK));
+        }
+        painter.rect_stroke(Rect::from_min_max(rect.min, rect.max), 2.0, grid, egui::StrokeKind::Inside);
+
+        response.on_hover_text("Drag points to shape the curve, click to add one, right-click to remove one");
+        changed
+    }
+
+    /// Adds `p` keeping the points sorted, starting from the identity line when there are
+    /// none yet. Returns its index.
+    fn insert(points: &mut Vec<[f32; 2]>, p: [f32; 2]) -> usize {
+        if (points.is_empty()) {
+            *points = vec![[0.0, 0.0], [1.0, 1.0]];
+        }
+        let i = points.iter().position(|q| q[0] > p[0]).unwrap_or(points.len());
+        points.insert(i, p);
+        i
+    }
+}
@@ -1,30 +1,114 @@
-﻿use crate::classes::c_config::Config;
-use crate::classes::c_rgb16::Rgb16;
+﻿use crate::classes::c_compare_mode::CompareMode;
+use crate::classes::c_config::Config;
+use crate::classes::c_eyedropper_pick::EyedropperPick;
+use crate::classes::c_image_state::ImageState;
+use crate::classes::c_viewport::Viewport;
 use crate::classes::t_widget::UIWidget;
-use crate::image_utils::{build_palette_median_cut_rgba16, dither_fs_palette_rgba16_to_rgba8, pack_rgb, resize_interleaved_nearest, rgb16_to_u8, rgb16_to_u8_exact, rgba8_to_rgba16, set_texture};
+use crate::egui_utils::set_texture;
+use retroimager_core::classes::c_project::project_dir;
+use retroimager_core::pipeline_utils::{apply_palette_overrides, frames_to_indexed, palette_usage};
+use retroimager_core::{Adjustments, ImageFrame, IndexedImage, JobSource, Palette, PaletteSource, PipelineWorker, PreviewPass, Project, ProjectSource, QuantizerKind, Rgb16, ViewerState};
 use eframe::egui;
-use std::path::PathBuf;
-use std::collections::HashMap;
+use image::RgbaImage;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+static EMPTY_PALETTE: Vec<Rgb16> = Vec::new();
+
+/// Pixel budget of the low resolution preview pass.
+const PREVIEW_PIXELS: usize = 160 * 120;
+
+const STATUS_BAR_HEIGHT: f32 = 22.0;
+/// Scroll distance (in points) of one zoom step.
+const SCROLL_PER_ZOOM_STEP: f32 = 40.0;
+/// Gap between the panes of the side by side view.
+const PANE_GAP: f32 = 4.0;
+/// Highlight mode: the other pixels become `c / HIGHLIGHT_DIM + HIGHLIGHT_FLOOR`.
+const HIGHLIGHT_DIM: u8 = 5;
+const HIGHLIGHT_FLOOR: u8 = 24;
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum DitheredImageCommand{
+    #[default]
+    None,
+    /// The job with this version is done and shown.
+    Loaded(u64),
+    Failed(String)
+}
 
+/// What to do with the result of the job that is running.
+struct PendingLoad {
+    path: Option<PathBuf>,
+    loaded_sequences: bool,
+    key: (QuantizerKind, u16, PaletteSource, Adjustments),
+    /// Edited palettes to show; None keeps the current ones, or resets them for new palettes.
+    overrides: Option<Vec<Palette>>,
+    /// Extracted colors of the palettes' entries; None keeps the current ones, or takes the new palettes.
+    extracted: Option<Vec<Palette>>,
+    current_frame: Option<usize>,
+    from_open_file: bool,
+    show_dithered: bool,
+}
 
 pub struct DitheredImage {
     tex: Option<egui::TextureHandle>,
     texDithered: Option<egui::TextureHandle>,
     texPure: Option<egui::TextureHandle>,
+    // low resolution pass of the running preview job, drawn until the full result is in
+    tex_preview: Option<egui::TextureHandle>,
+    // dithered frame with every pixel but the highlighted entry's dimmed
+    tex_highlight: Option<egui::TextureHandle>,
     w: usize,
     h: usize,
+    pure_w: usize,
+    pure_h: usize,
     is_loaded: bool,
     draw_dithered: bool,
-
-    image_bytes8: Vec<u8>,
-    image_bytes16: Vec<u16>,
-    image_bytes8_dithered_pure: Vec<u8>,
-    image_bytes8_dithered: Vec<u8>,
-
-    palette : Vec<Rgb16>,
-    palette_override : Vec<Rgb16>,
-
+    viewport: Viewport,
+    scroll_accum: f32,
+    compare: CompareMode,
+    // divider of the split view, 0..1 of the view width
+    split: f32,
+    // compare against the full resolution source instead of the scaled one
+    full_res_original: bool,
+
+    frames: Vec<ImageFrame>,
+    current_frame: usize,
+    is_playing: bool,
+    frame_time_ms: f64,
+
+    // one palette when it is shared by all frames, otherwise one per frame
+    palettes : Vec<Palette>,
+    palette_overrides : Vec<Palette>,
+    // the color every entry of `palettes` was extracted with, what a reset goes back to;
+    // `palettes` itself takes the edited colors when entries are added, deleted or moved
+    extracted : Vec<Palette>,
+    // what the current palettes were built from; a change rebuilds them
+    palette_key: (QuantizerKind, u16, PaletteSource, Adjustments),
+    // pixels per entry of every palette, counted when a result comes in
+    usage: Vec<Vec<usize>>,
+
+    // dim everything but the pixels of `highlight` (the hovered or edited entry)
+    highlight_mode: bool,
+    highlight: Option<usize>,
+    // (entry, frame) tex_highlight shows; None when it has to be rebuilt
+    highlighted: Option<(usize, usize)>,
+
+    // clicks pick colors instead of doing nothing
+    eyedropper: bool,
+    /// Set by an eyedropper click, taken by the app.
+    pub picked: Option<EyedropperPick>,
+
+    // decoded source frames, shared with the undo history
+    source: Arc<Vec<(RgbaImage, u32)>>,
     last_path_buff: Option<PathBuf>,
+    // sequence setting the frames were decoded with; reload re-reads the file when it changes
+    loaded_sequences: bool,
+
+    // decoding and dithering run here; the shown image stays until a job is done
+    worker: Option<PipelineWorker>,
+    pending: Option<PendingLoad>,
+    pub last_command: DitheredImageCommand,
 }
 
 impl Default for DitheredImage {
@@ -33,38 +117,92 @@ impl Default for DitheredImage {
             tex: None,
             texDithered: None,
             texPure: None,
+            tex_preview: None,
+            tex_highlight: None,
             w: 512,
             h: 512,
+            pure_w: 512,
+            pure_h: 512,
             is_loaded: false,
-            image_bytes8: vec![],
-            image_bytes16: vec![],
-            image_bytes8_dithered: vec![],
-            image_bytes8_dithered_pure: vec![],
+            frames: vec![],
+            current_frame: 0,
+            is_playing: false,
+            frame_time_ms: 0.0,
             draw_dithered: false,
-            palette: vec![],
-            palette_override: vec![],
+            viewport: Viewport::default(),
+            scroll_accum: 0.0,
+            compare: CompareMode::Single,
+            split: 0.5,
+            full_res_original: false,
+            palettes: vec![],
+            palette_overrides: vec![],
+            extracted: vec![],
+            palette_key: (QuantizerKind::default(), 0, PaletteSource::Extract, Adjustments::default()),
+            usage: vec![],
+            highlight_mode: false,
+            highlight: None,
+            highlighted: None,
+            eyedropper: false,
+            picked: None,
+            source: Arc::new(vec![]),
             last_path_buff: None,
+            loaded_sequences: true,
+            worker: None,
+            pending: None,
+            last_command: DitheredImageCommand::None,
         }
     }
 }
 impl UIWidget for DitheredImage{
     fn update(&mut self, ctx: &egui::Context) {
+        self.poll_worker(ctx);
+        self.tick_playback(ctx);
+        self.update_highlight(ctx);
+
         egui::CentralPanel::default().show(ctx, |ui| {
+            self.progress_bar(ui);
 
             if (self.is_loaded) {
-                let mut tex = self.tex.as_ref().unwrap();
+                if (self.frames.len() > 1) {
+                    self.playback_controls(ui);
+                }
 
-                if (self.draw_dithered) {
-                    tex = self.texDithered.as_ref().unwrap();
+                let full = ui.available_rect_before_wrap();
+                let view = egui::Rect::from_min_max(full.min, egui::pos2(full.max.x, (full.max.y - STATUS_BAR_HEIGHT).max(full.min.y)));
+                let response = ui.allocate_rect(view, egui::Sense::click_and_drag());
+                let panes = self.panes(view);
+
+                // zoom, pan and hit tests use the pane under the pointer; all panes have the same size
+                let pane = response.hover_pos()
+                    .and_then(|p| panes.iter().find(|r| r.contains(p)).copied())
+                    .unwrap_or(panes[0]);
+                self.view_input(ui, &response, pane);
+                if (self.eyedropper) {
+                    self.eyedropper_input(ui, &response, view, &panes, pane);
                 }
 
-                let rect = ui.available_rect_before_wrap();
-                ui.allocate_rect(rect, egui::Sense::hover());
+                match self.compare {
+                    CompareMode::Single => {
+                        let tex = if (self.draw_dithered) { self.dithered_tex() } else { self.original_tex() };
+                        self.paint_pane(ui, pane, tex, None);
+                    }
+                    CompareMode::SideBySide => {
+                        self.paint_pane(ui, panes[0], self.original_tex(), None);
+                        self.paint_pane(ui, panes[1], self.dithered_tex(), None);
+                    }
+                    CompareMode::Split => {
+                        let x = view.min.x + view.width() * self.split;
+                        self.paint_pane(ui, view, self.dithered_tex(), None);
+                        self.paint_pane(ui, view, self.original_tex(), Some(egui::Rect::from_min_max(view.min, egui::pos2(x, view.max.y))));
+                        self.split_divider(ui, view);
+                    }
+                }
 
-                // contain
-                let img_rect = Self::contain_rect(rect, self.w as f32, self.h as f32);
-                let uv = egui::Rect::from_min_max(egui::pos2(0.0, 0.0), egui::pos2(1.0, 1.0));
-                ui.painter().image(tex.id(), img_rect, uv, egui::Color32::WHITE);
+                let status = egui::Rect::from_min_max(egui::pos2(full.min.x, view.max.y), full.max);
+                let hovered = response.hover_pos().and_then(|p| self.viewport.pixel_at(p, pane, self.w, self.h));
+                ui.scope_builder(egui::UiBuilder::new().max_rect(status).layout(egui::Layout::left_to_right(egui::Align::Center)), |ui| {
+                    self.status_bar(ui, pane, hovered);
+                });
             }else{
                 ui.centered_and_justified(|ui|{
                     ui.label("Empty\nFile>Open...");
@@ -81,196 +219,719 @@ impl DitheredImage {
         img
     }
     pub fn get_bytes(&self) -> &Vec<u8> {
-        return &self.image_bytes8_dithered;
+        &self.frames[self.current_frame].dithered
     }
 
     pub fn size(&self) -> (usize, usize) {
         (self.w, self.h)
     }
 
-    pub fn upload_image(&mut self, path: &PathBuf, ctx: &egui::Context, config: &Config, from_open_file: bool) {
-        let img = match image::open(path) {
-            Ok(img) => img,
+    /// Loads `path` on the worker thread; the current image stays until it is done.
+    pub fn upload_image(&mut self, path: &Path, ctx: &egui::Context, config: &Config, from_open_file: bool) {
+        let pending = PendingLoad {
+            path: Some(path.to_path_buf()),
+            loaded_sequences: config.load_sequences,
+            key: Self::palette_key_of(config),
+            overrides: None,
+            extracted: None,
+            current_frame: None,
+            from_open_file,
+            show_dithered: true,
+        };
+        self.submit(ctx, JobSource::File { path: path.to_path_buf(), sequences: config.load_sequences }, config, None, pending, None);
+    }
+
+    fn submit(&mut self, ctx: &egui::Context, source: JobSource, config: &Config, palettes: Option<Vec<Palette>>, pending: PendingLoad, preview: Option<PreviewPass>) {
+        let worker = self.worker.get_or_insert_with(|| {
+            let ctx = ctx.clone();
+            PipelineWorker::new(move || ctx.request_repaint())
+        });
+        // locked entries stay where the palette menu shows them
+        worker.submit(source, config.pipeline(), palettes, self.palette_overrides.clone(), preview);
+        self.pending = Some(pending);
+    }
+
+    /// Takes over the worker's result once the newest job is done.
+    fn poll_worker(&mut self, ctx: &egui::Context) {
+        let Some(worker) = &mut self.worker else { return; };
+        let Some(result) = worker.poll() else { return; };
+        let version = worker.version();
+
+        let mut output = match result {
+            Ok(output) => output,
             Err(e) => {
-                eprintln!("Failed to open image: {e}");
+                self.tex_preview = None;
+                self.pending = None;
+                self.last_command = DitheredImageCommand::Failed(e);
                 return;
             }
         };
 
-        let rgba8_full = img.to_rgba8();
-        let (w0, h0) = rgba8_full.dimensions();
-
-        // 2) Pure texture
-        set_texture(
-            &mut self.texPure,
-            ctx,
-            "framebuffer_pure",
-            w0 as usize,
-            h0 as usize,
-            rgba8_full.as_raw(),
-        );
-
-        let scale = config.image_percent; // например 0.5
-        let (resized8, nw, nh) = resize_interleaved_nearest::<u8, 4>(
-            rgba8_full.as_raw(),
-            w0 as usize,
-            h0 as usize,
-            scale,
-        );
-
-        self.image_bytes8 = resized8;
-        self.w = nw;
-        self.h = nh;
-
-        rgba8_to_rgba16(&self.image_bytes8, &mut self.image_bytes16);
-
-        set_texture(
-            &mut self.tex,
-            ctx,
-            "framebuffer_scaled",
-            self.w,
-            self.h,
-            &self.image_bytes8,
-        );
-
-        self.last_path_buff = Some(path.clone());
+        if (output.is_preview) {
+            // kept palettes: show the user's edits in the preview too
+            let slot = self.current_palette_slot();
+            if let (Some(palette), Some(edited)) = (self.palettes.get(slot), self.palette_overrides.get(slot))
+                && output.image.palettes.first() == Some(palette) {
+                apply_palette_overrides(&mut output.image.frames, std::slice::from_ref(palette), std::slice::from_ref(edited));
+            }
+            if let Some(frame) = output.image.frames.first() {
+                set_texture(&mut self.tex_preview, ctx, "framebuffer_preview", output.image.w, output.image.h, &frame.dithered);
+            }
+            return;
+        }
+        self.tex_preview = None;
+
+        let Some(pending) = self.pending.take() else { return; };
+
+        let (w0, h0) = output.source[0].0.dimensions();
+        self.pure_w = w0 as usize;
+        self.pure_h = h0 as usize;
+        self.source = output.source;
+        self.frames = output.image.frames;
+        self.w = output.image.w;
+        self.h = output.image.h;
+        self.last_path_buff = pending.path;
+        self.loaded_sequences = pending.loaded_sequences;
+
+        self.palettes = output.image.palettes;
+        self.palette_key = pending.key;
+        self.usage = (0..self.palettes.len()).map(|slot| palette_usage(&self.frames, &self.palettes, slot)).collect();
+        if let Some(overrides) = pending.overrides {
+            self.palette_overrides = overrides;
+        } else if (output.new_palettes) {
+            self.palette_overrides = self.palettes.clone();
+        }
+        let extracted = pending.extracted.unwrap_or_else(|| std::mem::take(&mut self.extracted));
+        let same_layout = extracted.len() == self.palettes.len()
+            && extracted.iter().zip(&self.palettes).all(|(a, b)| a.len() == b.len());
+        self.extracted = if (output.new_palettes || !same_layout) { self.palettes.clone() } else { extracted };
+
+        if let Some(frame_id) = pending.current_frame {
+            self.current_frame = frame_id;
+        }
+        if (pending.from_open_file) {
+            self.viewport.fit();
+        }
+        if (pending.from_open_file || self.current_frame >= self.frames.len()) {
+            self.current_frame = 0;
+            self.is_playing = false;
+            self.frame_time_ms = 0.0;
+        }
+
         self.is_loaded = true;
+        self.draw_dithered = pending.show_dithered;
+
+        // also uploads the dithered texture
+        self.apply_palette_override_to_dithered(ctx);
+        self.upload_frame_textures(ctx);
 
-        self.dither(ctx, config, from_open_file);
+        self.last_command = DitheredImageCommand::Loaded(version);
     }
+
+    pub fn is_busy(&self) -> bool {
+        self.worker.as_ref().is_some_and(|w| w.is_busy())
+    }
+
+    /// Version of the newest job; [`DitheredImageCommand::Loaded`] reports it when it is done.
+    pub fn job_version(&self) -> u64 {
+        self.worker.as_ref().map(|w| w.version()).unwrap_or(0)
+    }
+
+    pub fn cancel(&mut self) {
+        if let Some(worker) = &mut self.worker {
+            worker.cancel();
+        }
+        self.pending = None;
+        self.tex_preview = None;
+    }
+
+    pub fn clear_command(&mut self) {
+        self.last_command = DitheredImageCommand::None;
+    }
+
+    fn progress_bar(&mut self, ui: &mut egui::Ui) {
+        let Some(progress) = self.worker.as_ref().and_then(|w| w.progress()) else { return; };
+        let (stage, fraction) = (progress.stage(), progress.fraction());
+
+        let mut cancel = false;
+        ui.horizontal(|ui| {
+            let width = (ui.available_width() - 80.0).max(80.0);
+            ui.add(egui::ProgressBar::new(fraction).desired_width(width).text(format!("{stage} {:.0}%", fraction * 100.0)));
+            cancel = ui.button("Cancel").clicked();
+        });
+        if (cancel) {
+            self.cancel();
+        }
+        // progress changes without input events
+        ui.ctx().request_repaint_after(std::time::Duration::from_millis(50));
+    }
+
     pub fn has_image(&self) -> bool {
         self.is_loaded
     }
 
-    pub fn contain_rect(outer: egui::Rect, img_w: f32, img_h: f32) -> egui::Rect {
-        let ow = outer.width().max(1.0);
-        let oh = outer.height().max(1.0);
-        let s = (ow / img_w.max(1.0)).min(oh / img_h.max(1.0));
-        let size = egui::vec2(img_w * s, img_h * s);
-        let min = outer.center() - size * 0.5;
-        egui::Rect::from_min_size(min, size)
+    /// One pane per shown image; side by side splits the view in two.
+    fn panes(&self, view: egui::Rect) -> Vec<egui::Rect> {
+        if (self.compare != CompareMode::SideBySide) {
+            return vec![view];
+        }
+        let half = (view.width() - PANE_GAP) * 0.5;
+        vec![
+            egui::Rect::from_min_size(view.min, egui::vec2(half, view.height())),
+            egui::Rect::from_min_size(egui::pos2(view.max.x - half, view.min.y), egui::vec2(half, view.height())),
+        ]
+    }
+
+    fn dithered_tex(&self) -> egui::TextureId {
+        if let Some(preview) = &self.tex_preview {
+            return preview.id();
+        }
+        match (&self.tex_highlight, self.highlighted) {
+            (Some(highlight), Some(_)) if (self.highlight_mode && self.highlight.is_some()) => highlight.id(),
+            _ => self.texDithered.as_ref().unwrap().id(),
+        }
     }
 
+    /// Entry to highlight in highlight mode, None shows the plain output.
+    pub fn set_highlight(&mut self, entry: Option<usize>) {
+        self.highlight = entry;
+    }
 
-    pub fn reload(&mut self, ctx: &egui::Context, config: &Config) {
-        if (self.has_image()){
-            if (!self.last_path_buff.is_none()){
-                let buff = self.last_path_buff.clone().unwrap();
-                self.upload_image(&buff, ctx, config, false);
+    /// Rebuilds the highlight texture when the entry, the frame or the colors changed.
+    fn update_highlight(&mut self, ctx: &egui::Context) {
+        let Some(entry) = self.highlight.filter(|_| self.highlight_mode) else { return; };
+        let key = (entry, self.current_frame);
+        if (self.highlighted == Some(key)) {
+            return;
+        }
+        let Some(frame) = self.frames.get(self.current_frame) else { return; };
+
+        let mut bytes = frame.dithered.clone();
+        for (p, &id) in bytes.chunks_exact_mut(4).zip(&frame.indices) {
+            if (id as usize != entry) {
+                for c in &mut p[..3] {
+                    *c = *c / HIGHLIGHT_DIM + HIGHLIGHT_FLOOR;
+                }
             }
         }
+        set_texture(&mut self.tex_highlight, ctx, "framebuffer_highlight", self.w, self.h, &bytes);
+        self.highlighted = Some(key);
     }
-    pub fn dither(&mut self, ctx: &egui::Context, config: &Config, from_open_file: bool) {
-        if !self.is_loaded { return; }
 
-        let k = (config.colors_palette_size as usize).max(2);
+    fn original_tex(&self) -> egui::TextureId {
+        match (&self.texPure, self.full_res_original) {
+            (Some(pure), true) => pure.id(),
+            _ => self.tex.as_ref().unwrap().id(),
+        }
+    }
 
-        if from_open_file || self.palette.len() != k {
-            self.palette = build_palette_median_cut_rgba16(&self.image_bytes16, self.w, self.h, k);
-            self.palette_override = self.palette.clone();
+    /// Draws `tex` over the image area of `pane`, cut to `clip` when given. Every texture
+    /// covers the same rect, so pixels and grid line up between panes and modes.
+    fn paint_pane(&self, ui: &egui::Ui, pane: egui::Rect, tex: egui::TextureId, clip: Option<egui::Rect>) {
+        let img_rect = self.viewport.image_rect(pane, self.w, self.h);
+        let uv = egui::Rect::from_min_max(egui::pos2(0.0, 0.0), egui::pos2(1.0, 1.0));
+        let painter = ui.painter_at(clip.unwrap_or(pane).intersect(pane));
+        painter.image(tex, img_rect, uv, egui::Color32::WHITE);
+        self.viewport.paint_grid(&ui.painter_at(pane), pane, self.w, self.h);
+    }
+
+    fn split_divider(&mut self, ui: &mut egui::Ui, view: egui::Rect) {
+        let x = view.min.x + view.width() * self.split;
+        let handle = egui::Rect::from_center_size(egui::pos2(x, view.center().y), egui::vec2(10.0, view.height()));
+        let response = ui.interact(handle, ui.id().with("split_divider"), egui::Sense::drag())
+            .on_hover_cursor(egui::CursorIcon::ResizeHorizontal);
+        if (response.dragged()) {
+            self.split = ((x + response.drag_delta().x - view.min.x) / view.width().max(1.0)).clamp(0.0, 1.0);
         }
 
-        self.image_bytes8_dithered.resize(self.w * self.h * 4, 0);
+        let painter = ui.painter_at(view);
+        let color = if (response.hovered() || response.dragged()) { egui::Color32::WHITE } else { egui::Color32::from_gray(200) };
+        painter.vline(x, view.y_range(), egui::Stroke::new(2.0, color));
+        painter.circle_filled(egui::pos2(x, view.center().y), 6.0, color);
+    }
+
+    /// Wheel zooms around the cursor, dragging pans, F/0 fit, 1 is 1:1, +/- zoom, G toggles the grid,
+    /// T switches between source and dithered, C goes through the compare modes, H toggles highlighting,
+    /// I the eyedropper.
+    fn view_input(&mut self, ui: &egui::Ui, response: &egui::Response, view: egui::Rect) {
+        let (w, h) = (self.w, self.h);
+
+        if (response.dragged()) {
+            self.viewport.pan(response.drag_delta(), view, w, h);
+        }
+        if (response.double_clicked()) {
+            self.viewport.fit();
+        }
+
+        let Some(pointer) = response.hover_pos() else {
+            self.scroll_accum = 0.0;
+            return;
+        };
+
+        self.scroll_accum += ui.input(|i| i.raw_scroll_delta.y);
+        let steps = (self.scroll_accum / SCROLL_PER_ZOOM_STEP) as i32;
+        if (steps != 0) {
+            self.scroll_accum -= steps as f32 * SCROLL_PER_ZOOM_STEP;
+            self.viewport.zoom_by(steps, pointer, view, w, h);
+        }
 
-        dither_fs_palette_rgba16_to_rgba8(
-            &self.image_bytes16,
-            &mut self.image_bytes8_dithered,
-            self.w,
-            self.h,
-            &self.palette,
-        );
+        // keys typed into a text field (hex color, batch paths) are not viewer shortcuts
+        if (ui.ctx().wants_keyboard_input() || ui.ctx().memory(|m| m.focused().is_some())) {
+            return;
+        }
 
-        self.image_bytes8_dithered_pure.clear();
-        self.image_bytes8_dithered_pure.extend_from_slice(&self.image_bytes8_dithered);
+        let (fit, actual, zoom_in, zoom_out, grid, toggle, compare, highlight, eyedropper) = ui.input(|i| (
+            i.key_pressed(egui::Key::F) || i.key_pressed(egui::Key::Num0),
+            i.key_pressed(egui::Key::Num1),
+            i.key_pressed(egui::Key::Plus) || i.key_pressed(egui::Key::Equals),
+            i.key_pressed(egui::Key::Minus),
+            i.key_pressed(egui::Key::G),
+            i.key_pressed(egui::Key::T),
+            i.key_pressed(egui::Key::C),
+            i.key_pressed(egui::Key::H),
+            i.key_pressed(egui::Key::I),
+        ));
+        if (toggle) { self.draw_dithered = !self.draw_dithered; }
+        if (highlight) { self.highlight_mode = !self.highlight_mode; }
+        if (eyedropper) { self.eyedropper = !self.eyedropper; }
+        if (compare) { self.compare = self.compare.next(); }
+        if (fit) { self.viewport.fit(); }
+        if (actual) { self.viewport.actual_size(); }
+        if (zoom_in) { self.viewport.zoom_by(1, view.center(), view, w, h); }
+        if (zoom_out) { self.viewport.zoom_by(-1, view.center(), view, w, h); }
+        if (grid) { self.viewport.show_grid = !self.viewport.show_grid; }
+    }
 
-        let img = egui::ColorImage::from_rgba_unmultiplied([self.w, self.h], &self.image_bytes8_dithered);
-        match &mut self.texDithered {
-            Some(t) => t.set(img, egui::TextureOptions::NEAREST),
-            None => self.texDithered = Some(ctx.load_texture("framebuffer_dithered", img, egui::TextureOptions::NEAREST)),
+    /// A click on the dithered image picks the pixel's palette entry, on the source its color.
+    fn eyedropper_input(&mut self, ui: &egui::Ui, response: &egui::Response, view: egui::Rect, panes: &[egui::Rect], pane: egui::Rect) {
+        if (response.hovered()) {
+            ui.ctx().set_cursor_icon(egui::CursorIcon::Crosshair);
         }
+        if (!response.clicked()) {
+            return;
+        }
+        let Some(pointer) = response.interact_pointer_pos() else { return; };
+        let Some((x, y)) = self.viewport.pixel_at(pointer, pane, self.w, self.h) else { return; };
 
-        self.draw_dithered = true;
+        let on_dithered = match self.compare {
+            CompareMode::Single => self.draw_dithered,
+            CompareMode::SideBySide => pane == panes[1],
+            CompareMode::Split => pointer.x >= view.min.x + view.width() * self.split,
+        };
+        self.picked = if (on_dithered) {
+            self.palette_index_at(x, y).map(EyedropperPick::Entry)
+        } else {
+            self.source_color_at(x, y).map(EyedropperPick::Color)
+        };
     }
 
-    pub fn set_override_colors(&mut self, p: Vec<Rgb16>, ctx: &egui::Context) {
-        for (i, &c) in p.iter().enumerate() {
-            self.replace_color(ctx, c, i);
+    /// Source color under image pixel `x`, `y`: 16-bit from the scaled source, or from the
+    /// full resolution one when that is what the viewer shows.
+    fn source_color_at(&self, x: usize, y: usize) -> Option<Rgb16> {
+        let frame = self.frames.get(self.current_frame)?;
+        if (self.full_res_original) {
+            let (px, py) = (x * self.pure_w / self.w.max(1), y * self.pure_h / self.h.max(1));
+            let i = (py * self.pure_w + px) * 4;
+            let p = frame.pure8.get(i..i + 3)?;
+            return Some(Rgb16 { r: p[0] as u16 * 257, g: p[1] as u16 * 257, b: p[2] as u16 * 257 });
         }
+        let i = (y * frame.source16.w + x) * 4;
+        let p = frame.source16.data.get(i..i + 3)?;
+        Some(Rgb16 { r: p[0], g: p[1], b: p[2] })
     }
 
+    fn status_bar(&mut self, ui: &mut egui::Ui, view: egui::Rect, hovered: Option<(usize, usize)>) {
+        let (w, h) = (self.w, self.h);
+
+        if (ui.small_button("Fit").clicked()) { self.viewport.fit(); }
+        if (ui.small_button("1:1").clicked()) { self.viewport.actual_size(); }
+        if (ui.small_button("-").clicked()) { self.viewport.zoom_by(-1, view.center(), view, w, h); }
+        ui.label(self.viewport.zoom_label(view, w, h));
+        if (ui.small_button("+").clicked()) { self.viewport.zoom_by(1, view.center(), view, w, h); }
+        ui.checkbox(&mut self.viewport.show_grid, "Grid");
+        ui.separator();
+
+        egui::ComboBox::from_id_salt("compare_mode")
+            .selected_text(self.compare.label())
+            .width(100.0)
+            .show_ui(ui, |ui| {
+                for m in CompareMode::ALL {
+                    ui.selectable_value(&mut self.compare, m, m.label());
+                }
+            });
+        if (self.compare == CompareMode::Single) {
+            let label = if (self.draw_dithered) { "Dithered" } else { "Original" };
+            if (ui.small_button(label).on_hover_text("T").clicked()) {
+                self.draw_dithered = !self.draw_dithered;
+            }
+        }
+        ui.checkbox(&mut self.full_res_original, "Full-res source");
+        ui.checkbox(&mut self.highlight_mode, "Highlight")
+            .on_hover_text("Dim every pixel except those of the palette entry under the mouse or being edited (H)");
+        ui.toggle_value(&mut self.eyedropper, "Eyedropper")
+            .on_hover_text("Click the dithered image to edit the palette entry of a pixel, the source to sample its color: \
+                            into the open color window, otherwise as a new palette entry (I)");
+        ui.separator();
+
+        ui.label(format!("{w}×{h}"));
+        let Some((x, y)) = hovered else { return; };
+        ui.separator();
+        ui.label(format!("x {x}  y {y}"));
+
+        let Some(frame) = self.frames.get(self.current_frame) else { return; };
+        let i = (y * w + x) * 4;
+        let (Some(src), Some(dst)) = (frame.bytes8.get(i..i + 4), frame.dithered.get(i..i + 4)) else { return; };
+
+        let show_source = self.compare != CompareMode::Single || !self.draw_dithered;
+        let show_dithered = self.compare != CompareMode::Single || self.draw_dithered;
+        if (show_source) {
+            Self::color_label(ui, if (show_dithered) { "source " } else { "" }, src);
+        }
+        if (show_dithered) {
+            Self::color_label(ui, "", dst);
+            if let Some(index) = self.palette_index_at(x, y) {
+                ui.label(format!("index {index}"));
+            }
+        }
+    }
 
-    pub fn replace_color(&mut self, ctx: &egui::Context, new_color: Rgb16, palette_id: usize) {
-        if palette_id >= self.palette.len() {
+    fn color_label(ui: &mut egui::Ui, prefix: &str, p: &[u8]) {
+        let (rect, _) = ui.allocate_exact_size(egui::vec2(12.0, 12.0), egui::Sense::hover());
+        ui.painter().rect_filled(rect, 1.0, egui::Color32::from_rgb(p[0], p[1], p[2]));
+        ui.label(format!("{prefix}#{:02X}{:02X}{:02X}", p[0], p[1], p[2]));
+    }
+
+    /// Palette entry of a pixel of the current dithered frame.
+    fn palette_index_at(&self, x: usize, y: usize) -> Option<usize> {
+        let frame = self.frames.get(self.current_frame)?;
+        frame.indices.get(y * self.w + x).map(|&id| id as usize)
+    }
+
+    /// Settings the palettes are built from. Locks are left out: locking an entry keeps the
+    /// current palette, the lock only applies when one of these changes.
+    fn palette_key_of(config: &Config) -> (QuantizerKind, u16, PaletteSource, Adjustments) {
+        (config.quantizer, config.colors_palette_size, config.palette_source.clone(), config.adjustments.clone())
+    }
+
+    /// Re-runs the pipeline with `config` on the frames already in memory, so a moved or
+    /// deleted source file doesn't matter. Only a changed sequence setting reads the file again.
+    /// The palettes (and edits to them) are kept unless a palette setting changed.
+    pub fn reload(&mut self, ctx: &egui::Context, config: &Config) {
+        self.reload_with(ctx, config, None);
+    }
+
+    /// [`Self::reload`] for settings being tried out: with `config.low_res_preview` a quick
+    /// low resolution pass of the current frame shows up first.
+    pub fn preview(&mut self, ctx: &egui::Context, config: &Config) {
+        let pass = PreviewPass { frame: self.current_frame, max_pixels: PREVIEW_PIXELS };
+        self.reload_with(ctx, config, if (config.low_res_preview) { Some(pass) } else { None });
+    }
+
+    fn reload_with(&mut self, ctx: &egui::Context, config: &Config, preview: Option<PreviewPass>) {
+        if (!self.has_image()) {
             return;
         }
-        if self.palette_override.len() != self.palette.len() {
-            self.palette_override = self.palette.clone();
+        if config.load_sequences != self.loaded_sequences
+            && let Some(path) = self.last_path_buff.clone().filter(|p| p.is_file()) {
+            self.upload_image(&path, ctx, config, false);
+            return;
         }
 
-        self.palette_override[palette_id] = new_color;
+        let key = Self::palette_key_of(config);
+        let slots = if (config.shared_palette || !matches!(config.palette_source, PaletteSource::Extract)) { 1 } else { self.frames.len() };
+        let keep = self.palette_key == key && self.palettes.len() == slots;
+
+        let pending = PendingLoad {
+            path: self.last_path_buff.clone(),
+            loaded_sequences: self.loaded_sequences,
+            key,
+            overrides: None,
+            extracted: None,
+            current_frame: None,
+            from_open_file: false,
+            show_dithered: true,
+        };
+        let palettes = if (keep) { Some(self.palettes.clone()) } else { None };
+        self.submit(ctx, JobSource::Frames(self.source.clone()), config, palettes, pending, preview);
+    }
+
+    /// Session snapshot. `embed` stores the source frames in the project, otherwise the
+    /// source path is stored, relative to `project_path`'s folder when possible.
+    pub fn to_project(&self, config: &Config, embed: bool, project_path: &Path) -> Result<Project, String> {
+        let source = match (&self.last_path_buff, embed) {
+            (Some(path), false) => ProjectSource::linked(path, &project_dir(project_path)),
+            _ => ProjectSource::embed(&self.source)?,
+        };
 
-        self.apply_palette_override_to_dithered(ctx);
+        Ok(Project {
+            source,
+            pipeline: config.pipeline(),
+            export: config.export(),
+            palettes: self.palettes.clone(),
+            overrides: self.palette_overrides.clone(),
+            viewer: ViewerState { current_frame: self.current_frame, show_dithered: self.draw_dithered },
+            ..Default::default()
+        })
     }
-    pub fn get_palette_colors(&self) -> &Vec<Rgb16> {
-        return &self.palette_override;
+
+    /// Restores a session saved by [`Self::to_project`]; `config` must already hold its settings.
+    pub fn load_project(&mut self, project: &Project, project_path: &Path, ctx: &egui::Context, config: &Config) -> Result<(), String> {
+        if (project.palettes.is_empty()) {
+            return Err("project has no palette".to_string());
+        }
+        let dir = project_dir(project_path);
+
+        let pending = PendingLoad {
+            path: project.source.path.as_ref().map(|p| dir.join(p)),
+            loaded_sequences: project.pipeline.load_sequences,
+            key: Self::palette_key_of(config),
+            overrides: Some(project.overrides.clone()),
+            extracted: Some(project.palettes.clone()),
+            current_frame: Some(project.viewer.current_frame),
+            from_open_file: false,
+            show_dithered: project.viewer.show_dithered,
+        };
+        let source = JobSource::Project { source: project.source.clone(), dir, sequences: project.pipeline.load_sequences };
+        self.submit(ctx, source, config, Some(project.palettes.clone()), pending, None);
+        Ok(())
     }
-    pub fn get_pure_palette_colors(&self) -> &Vec<Rgb16> {
-        return &self.palette;
+
+    pub fn state(&self) -> ImageState {
+        ImageState {
+            path: self.last_path_buff.clone(),
+            source: self.source.clone(),
+            loaded_sequences: self.loaded_sequences,
+            palettes: self.palettes.clone(),
+            overrides: self.palette_overrides.clone(),
+            extracted: self.extracted.clone(),
+            current_frame: self.current_frame,
+        }
     }
 
-    fn apply_palette_override_to_dithered(&mut self, ctx: &egui::Context) {
-        if self.image_bytes8_dithered_pure.len() != self.w * self.h * 4 {
+    /// Shows `state` dithered with `config`, keeping its palettes. An empty state unloads the image.
+    pub fn restore(&mut self, state: &ImageState, ctx: &egui::Context, config: &Config) {
+        if (!state.has_image()) {
+            self.cancel();
+            *self = DitheredImage { worker: self.worker.take(), ..Default::default() };
             return;
         }
-        if self.palette.is_empty() {
+
+        let pending = PendingLoad {
+            path: state.path.clone(),
+            loaded_sequences: state.loaded_sequences,
+            key: Self::palette_key_of(config),
+            overrides: Some(state.overrides.clone()),
+            extracted: Some(state.extracted.clone()),
+            current_frame: Some(state.current_frame),
+            from_open_file: false,
+            show_dithered: true,
+        };
+        self.submit(ctx, JobSource::Frames(state.source.clone()), config, Some(state.palettes.clone()), pending, None);
+    }
+
+    fn palette_slot(&self, frame_id: usize) -> usize {
+        if (self.palettes.len() <= 1) { 0 } else { frame_id.min(self.palettes.len() - 1) }
+    }
+
+    fn upload_frame_textures(&mut self, ctx: &egui::Context) {
+        let Some(frame) = self.frames.get(self.current_frame) else { return; };
+
+        set_texture(&mut self.texPure, ctx, "framebuffer_pure", self.pure_w, self.pure_h, &frame.pure8);
+        set_texture(&mut self.tex, ctx, "framebuffer_scaled", self.w, self.h, &frame.bytes8);
+        set_texture(&mut self.texDithered, ctx, "framebuffer_dithered", self.w, self.h, &frame.dithered);
+        self.highlighted = None;
+    }
+
+    pub fn set_frame(&mut self, ctx: &egui::Context, frame_id: usize) {
+        if (frame_id >= self.frames.len() || frame_id == self.current_frame) {
             return;
         }
-        if self.palette_override.len() != self.palette.len() {
-            self.palette_override = self.palette.clone();
+        self.current_frame = frame_id;
+        self.upload_frame_textures(ctx);
+    }
+
+    fn tick_playback(&mut self, ctx: &egui::Context) {
+        if (!self.is_playing || self.frames.len() < 2) {
+            return;
         }
 
-        let mut lut: HashMap<u32, usize> = HashMap::with_capacity(self.palette.len() * 2);
-        for (i, &c16) in self.palette.iter().enumerate() {
-            let (r, g, b) = rgb16_to_u8_exact(c16);
-            lut.insert(pack_rgb(r, g, b), i);
+        self.frame_time_ms += ctx.input(|i| i.stable_dt) as f64 * 1000.0;
+
+        let delay = self.frames[self.current_frame].delay_ms.max(10) as f64;
+        if (self.frame_time_ms >= delay) {
+            self.frame_time_ms = (self.frame_time_ms - delay).min(delay);
+            let next = (self.current_frame + 1) % self.frames.len();
+            self.set_frame(ctx, next);
         }
 
-        self.image_bytes8_dithered.resize(self.w * self.h * 4, 0);
+        ctx.request_repaint();
+    }
 
-        let src = &self.image_bytes8_dithered_pure;
-        let dst = &mut self.image_bytes8_dithered;
+    fn playback_controls(&mut self, ui: &mut egui::Ui) {
+        let ctx = ui.ctx().clone();
+        let last = self.frames.len() - 1;
+        let mut go_to: Option<usize> = None;
 
-        for p in (0..src.len()).step_by(4) {
-            let pr = src[p];
-            let pg = src[p + 1];
-            let pb = src[p + 2];
-            let pa = src[p + 3];
+        ui.horizontal(|ui| {
+            if ui.button("Prev").clicked() {
+                go_to = Some(if (self.current_frame == 0) { last } else { self.current_frame - 1 });
+            }
+            if ui.button(if (self.is_playing) { "Pause" } else { "Play" }).clicked() {
+                self.is_playing = !self.is_playing;
+                self.frame_time_ms = 0.0;
+            }
+            if ui.button("Next").clicked() {
+                go_to = Some((self.current_frame + 1) % self.frames.len());
+            }
 
-            if let Some(&id) = lut.get(&pack_rgb(pr, pg, pb)) {
-                let (nr, ng, nb) = rgb16_to_u8_exact(self.palette_override[id]);
-                dst[p] = nr;
-                dst[p + 1] = ng;
-                dst[p + 2] = nb;
-                dst[p + 3] = pa; 
-            } else {
-             
-                dst[p] = pr;
-                dst[p + 1] = pg;
-                dst[p + 2] = pb;
-                dst[p + 3] = pa;
+            let mut frame = self.current_frame;
+            if ui.add(egui::Slider::new(&mut frame, 0..=last).text(format!("of {}", last + 1))).changed() {
+                go_to = Some(frame);
             }
+            ui.label(format!("{} ms", self.frames[self.current_frame].delay_ms));
+        });
+
+        if let Some(frame_id) = go_to {
+            self.is_playing = false;
+            self.set_frame(&ctx, frame_id);
         }
+    }
 
-        let img = egui::ColorImage::from_rgba_unmultiplied([self.w, self.h], &self.image_bytes8_dithered);
-        match &mut self.texDithered {
-            Some(tex) => tex.set(img, egui::TextureOptions::NEAREST),
-            None => {
-                self.texDithered = Some(ctx.load_texture(
-                    "framebuffer_dithered",
-                    img,
-                    egui::TextureOptions::NEAREST,
-                ));
+    pub fn set_override_colors(&mut self, p: Vec<Palette>, ctx: &egui::Context) {
+        if self.palette_overrides.len() != self.palettes.len() {
+            self.palette_overrides = self.palettes.clone();
+        }
+        for (slot, colors) in p.iter().enumerate() {
+            let Some(target) = self.palette_overrides.get_mut(slot) else { break; };
+            for (i, &c) in colors.iter().enumerate() {
+                if i < target.len() {
+                    target[i] = c;
+                }
             }
         }
+        self.apply_palette_override_to_dithered(ctx);
     }
-}
\ No newline at end of file
+
+
+    pub fn replace_color(&mut self, ctx: &egui::Context, new_color: Rgb16, palette_id: usize) {
+        self.replace_color_in(ctx, self.current_palette_slot(), new_color, palette_id);
+    }
+
+    /// Like [`Self::replace_color`] for the palette `slot` instead of the current frame's one.
+    pub fn replace_color_in(&mut self, ctx: &egui::Context, slot: usize, new_color: Rgb16, palette_id: usize) {
+        if slot >= self.palettes.len() || palette_id >= self.palettes[slot].len() {
+            return;
+        }
+        if self.palette_overrides.len() != self.palettes.len() {
+            self.palette_overrides = self.palettes.clone();
+        }
+
+        self.palette_overrides[slot][palette_id] = new_color;
+
+        self.apply_palette_override_to_dithered(ctx);
+    }
+    /// Gives every entry of palette `slot` the color at its index in `colors` (same length),
+    /// pixels keep their entries.
+    pub fn recolor_palette_in(&mut self, ctx: &egui::Context, slot: usize, colors: &[Rgb16]) {
+        if slot >= self.palettes.len() || colors.len() != self.palettes[slot].len() {
+            return;
+        }
+        if self.palette_overrides.len() != self.palettes.len() {
+            self.palette_overrides = self.palettes.clone();
+        }
+
+        self.palette_overrides[slot] = Palette::new(colors.to_vec());
+
+        self.apply_palette_override_to_dithered(ctx);
+    }
+
+    /// Makes `colors` the palette of the current frame's slot and re-dithers against it, so
+    /// added, deleted or reordered entries reach the output and its index order.
+    pub fn set_palette(&mut self, ctx: &egui::Context, config: &Config, colors: Vec<Rgb16>) {
+        let slot = self.current_palette_slot();
+        if (!self.has_image() || colors.is_empty() || slot >= self.palettes.len()) {
+            return;
+        }
+        if self.palette_overrides.len() != self.palettes.len() {
+            self.palette_overrides = self.palettes.clone();
+        }
+
+        // an entry that is still there keeps its extracted color, a new one starts from its own
+        let mut taken = vec![false; self.palette_overrides[slot].len()];
+        let extracted_slot = colors.iter().map(|&color| {
+            let found = self.palette_overrides[slot].colors().iter().enumerate()
+                .position(|(i, &c)| !taken[i] && c == color);
+            match found {
+                Some(i) => {
+                    taken[i] = true;
+                    self.extracted.get(slot).and_then(|p| p.colors().get(i).copied()).unwrap_or(color)
+                }
+                None => color,
+            }
+        }).collect();
+
+        let mut palettes = self.palettes.clone();
+        let mut overrides = self.palette_overrides.clone();
+        let mut extracted = self.extracted.clone();
+        extracted.resize_with(palettes.len(), Default::default);
+        palettes[slot] = Palette::new(colors.clone());
+        overrides[slot] = Palette::new(colors);
+        extracted[slot] = Palette::new(extracted_slot);
+
+        let pending = PendingLoad {
+            path: self.last_path_buff.clone(),
+            loaded_sequences: self.loaded_sequences,
+            key: Self::palette_key_of(config),
+            overrides: Some(overrides),
+            extracted: Some(extracted),
+            current_frame: Some(self.current_frame),
+            from_open_file: false,
+            show_dithered: true,
+        };
+        self.submit(ctx, JobSource::Frames(self.source.clone()), config, Some(palettes), pending, None);
+    }
+
+    /// Pixels per entry of the current frame's palette, over all frames that use it.
+    pub fn palette_usage(&self) -> &[usize] {
+        self.usage.get(self.current_palette_slot()).map(|u| u.as_slice()).unwrap_or(&[])
+    }
+
+    pub fn get_palette_colors(&self) -> &Vec<Rgb16> {
+        self.palette_overrides.get(self.palette_slot(self.current_frame)).map(|p| p.colors()).unwrap_or(&EMPTY_PALETTE)
+    }
+    /// The current palette's entries as extracted, before any edit.
+    pub fn get_pure_palette_colors(&self) -> &Vec<Rgb16> {
+        self.extracted.get(self.palette_slot(self.current_frame)).map(|p| p.colors()).unwrap_or(&EMPTY_PALETTE)
+    }
+    pub fn get_all_palette_colors(&self) -> &Vec<Palette> {
+        &self.palette_overrides
+    }
+
+    pub fn current_palette_slot(&self) -> usize {
+        self.palette_slot(self.current_frame)
+    }
+
+    pub fn current_frame(&self) -> usize {
+        self.current_frame
+    }
+
+    /// All frames as palette indices (in the order of the palette bar) plus their delays in ms.
+    pub fn to_indexed_frames(&self) -> (Vec<IndexedImage>, Vec<u32>) {
+        frames_to_indexed(&self.frames, &self.palette_overrides)
+    }
+
+    fn apply_palette_override_to_dithered(&mut self, ctx: &egui::Context) {
+        if self.palettes.is_empty() {
+            return;
+        }
+        if self.palette_overrides.len() != self.palettes.len() {
+            self.palette_overrides = self.palettes.clone();
+        }
+
+        apply_palette_overrides(&mut self.frames, &self.palettes, &self.palette_overrides);
+
+        let Some(frame) = self.frames.get(self.current_frame) else { return; };
+        set_texture(&mut self.texDithered, ctx, "framebuffer_dithered", self.w, self.h, &frame.dithered);
+        self.highlighted = None;
+    }
+}
@@ -0,0 +1,61 @@
+﻿use crate::classes::c_config::Config;
+use crate::classes::c_image_state::ImageState;
+use retroimager_core::image_utils::u16_to_u8;
+use retroimager_core::Rgb16;
+
+/// One undoable user action. Each variant keeps both sides so it can be undone and redone.
+#[derive(Clone)]
+pub enum EditCommand {
+    /// A palette entry got a new color.
+    ReplaceColor { slot: usize, index: usize, from: Rgb16, to: Rgb16 },
+    /// Every entry of a palette got a new color, e.g. by a hue shift.
+    RecolorPalette { slot: usize, label: String, from: Vec<Rgb16>, to: Vec<Rgb16> },
+    /// Settings changed (config window, preset, project); the image state
+    /// holds the palettes made with each side.
+    ChangeSettings { label: String, from: Box<Config>, to: Box<Config>, image_from: ImageState, image_to: ImageState },
+    /// Another image was opened.
+    LoadImage { from: ImageState, to: ImageState },
+}
+
+impl EditCommand {
+    pub fn label(&self) -> String {
+        match self {
+            EditCommand::ReplaceColor { index, to, .. } => {
+                format!("Color {} → #{:02X}{:02X}{:02X}", index + 1, u16_to_u8(to.r), u16_to_u8(to.g), u16_to_u8(to.b))
+            }
+            EditCommand::RecolorPalette { label, .. } => label.clone(),
+            EditCommand::ChangeSettings { label, .. } => label.clone(),
+            EditCommand::LoadImage { to, .. } => {
+                let name = to.path.as_ref().and_then(|p| p.file_name()).map(|n| n.to_string_lossy().to_string());
+                format!("Open {}", name.unwrap_or_else(|| "image".to_string()))
+            }
+        }
+    }
+
+    /// Short description of what changed between two configs, for the history list.
+    pub fn settings_label(from: &Config, to: &Config) -> String {
+        let mut parts = vec![];
+        if (from.colors_palette_size != to.colors_palette_size) {
+            parts.push(format!("{} colors", to.colors_palette_size));
+        }
+        if (from.image_percent != to.image_percent || from.target_width != to.target_width || from.target_height != to.target_height) {
+            parts.push("size".to_string());
+        }
+        if (from.quantizer != to.quantizer) {
+            parts.push(to.quantizer.label().to_string());
+        }
+        if (from.dither_algorithm != to.dither_algorithm) {
+            parts.push(to.dither_algorithm.label().to_string());
+        }
+        if (from.adjustments != to.adjustments) {
+            parts.push("adjustments".to_string());
+        }
+        if (from.locked_colors != to.locked_colors) {
+            parts.push(format!("{} locked", to.locked_colors.len()));
+        }
+        if (parts.is_empty()) {
+            return "Settings".to_string();
+        }
+        format!("Settings: {}", parts.join(", "))
+    }
+}
@@ -0,0 +1,10 @@
+﻿use retroimager_core::Rgb16;
+
+/// What a click with the eyedropper picked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EyedropperPick {
+    /// Palette entry of a pixel of the dithered image.
+    Entry(usize),
+    /// Color of a pixel of the source image.
+    Color(Rgb16),
+}
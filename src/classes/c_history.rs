@@ -0,0 +1,58 @@
+﻿use crate::classes::c_edit_command::EditCommand;
+
+/// Entries beyond this are dropped from the start of the history.
+pub const HISTORY_LIMIT: usize = 100;
+
+/// Undo/redo stack. `entries[..position]` are applied, the rest can be redone.
+#[derive(Default)]
+pub struct History {
+    entries: Vec<EditCommand>,
+    position: usize,
+}
+
+impl History {
+    /// Records an action that was just applied; anything that could be redone is dropped.
+    pub fn push(&mut self, command: EditCommand) {
+        self.entries.truncate(self.position);
+        self.entries.push(command);
+        if (self.entries.len() > HISTORY_LIMIT) {
+            self.entries.remove(0);
+        }
+        self.position = self.entries.len();
+    }
+
+    pub fn can_undo(&self) -> bool {
+        self.position > 0
+    }
+
+    pub fn can_redo(&self) -> bool {
+        self.position < self.entries.len()
+    }
+
+    /// The action to revert, moving the position back by one.
+    pub fn undo(&mut self) -> Option<EditCommand> {
+        if (!self.can_undo()) {
+            return None;
+        }
+        self.position -= 1;
+        Some(self.entries[self.position].clone())
+    }
+
+    /// The action to apply again, moving the position forward by one.
+    pub fn redo(&mut self) -> Option<EditCommand> {
+        if (!self.can_redo()) {
+            return None;
+        }
+        self.position += 1;
+        Some(self.entries[self.position - 1].clone())
+    }
+
+    pub fn entries(&self) -> &Vec<EditCommand> {
+        &self.entries
+    }
+
+    /// Number of applied entries.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+}
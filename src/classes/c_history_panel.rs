@@ -0,0 +1,72 @@
+﻿use egui::Context;
+use crate::classes::c_history::History;
+use crate::classes::t_widget::UIWidget;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HistoryPanelCommand{
+    #[default]
+    None,
+    /// Undo or redo until this many entries are applied.
+    JumpTo(usize)
+}
+
+#[derive(Default)]
+pub struct HistoryPanel{
+    pub is_open: bool,
+    labels: Vec<String>,
+    position: usize,
+    is_enabled: bool,
+    pub last_command: HistoryPanelCommand,
+}
+
+impl HistoryPanel {
+    pub fn toggle(&mut self) {
+        self.is_open = !self.is_open;
+    }
+
+    pub fn clear_command(&mut self) {
+        self.last_command = HistoryPanelCommand::None;
+    }
+
+    pub fn update_history(&mut self, ctx: &Context, history: &History, is_enabled: bool) {
+        if (!self.is_open) {
+            return;
+        }
+        self.labels = history.entries().iter().map(|e| e.label()).collect();
+        self.position = history.position();
+        self.is_enabled = is_enabled;
+
+        self.update(ctx);
+    }
+}
+
+impl UIWidget for HistoryPanel {
+    fn update(&mut self, ctx: &Context) {
+        let mut is_open = self.is_open;
+
+        egui::Window::new("History")
+            .open(&mut is_open)
+            .resizable(true)
+            .default_width(220.0)
+            .show(ctx, |ui| {
+                ui.add_enabled_ui(self.is_enabled, |ui| {
+                    egui::ScrollArea::vertical().max_height(320.0).show(ui, |ui| {
+                        if (ui.selectable_label(self.position == 0, "Original").clicked()) {
+                            self.last_command = HistoryPanelCommand::JumpTo(0);
+                        }
+                        for (i, label) in self.labels.iter().enumerate() {
+                            let applied = i < self.position;
+                            let text = if (applied) { egui::RichText::new(label) } else { egui::RichText::new(label).weak() };
+                            if (ui.selectable_label(i + 1 == self.position, text).clicked()) {
+                                self.last_command = HistoryPanelCommand::JumpTo(i + 1);
+                            }
+                        }
+                    });
+                    ui.separator();
+                    ui.weak("Ctrl+Z undo, Ctrl+Shift+Z redo");
+                });
+            });
+
+        self.is_open = is_open;
+    }
+}
@@ -0,0 +1,23 @@
+﻿use std::path::PathBuf;
+use std::sync::Arc;
+use image::RgbaImage;
+use retroimager_core::Palette;
+
+/// Everything the image view shows apart from the settings: the decoded source frames and
+/// the palettes they were dithered with. Restoring it doesn't run the quantizer again.
+#[derive(Clone, Default)]
+pub struct ImageState {
+    pub path: Option<PathBuf>,
+    pub source: Arc<Vec<(RgbaImage, u32)>>,
+    pub loaded_sequences: bool,
+    pub palettes: Vec<Palette>,
+    pub overrides: Vec<Palette>,
+    pub extracted: Vec<Palette>,
+    pub current_frame: usize,
+}
+
+impl ImageState {
+    pub fn has_image(&self) -> bool {
+        !self.source.is_empty()
+    }
+}
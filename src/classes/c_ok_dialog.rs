@@ -9,6 +9,7 @@ pub struct OkDialog {
     ok_text: String,
     cancel_text: String,
     header_text: String,
+    message_text: String,
 }
 #[derive(Copy, Clone)]
 pub enum Action {
@@ -24,6 +25,7 @@ impl Default for OkDialog {
             ok_text: "Ok".to_string(),
             cancel_text: "Cancel".to_string(),
             header_text: "Header".to_string(),
+            message_text: String::new(),
         }
     }
 }
@@ -37,11 +39,15 @@ impl UIWidget for OkDialog {
                 .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
                 .show(ctx, |ui| {
                     // ui.label(self.header_text.clone());
+                    if (!self.message_text.is_empty()) {
+                        ui.label(self.message_text.clone());
+                        ui.separator();
+                    }
                     ui.horizontal(|ui| {
                         if ui.button(self.ok_text.clone()).clicked() {
                             self.last_action = Action::Ok;
                         }
-                        if ui.button(self.cancel_text.clone()).clicked() {
+                        if (!self.cancel_text.is_empty() && ui.button(self.cancel_text.clone()).clicked()) {
                             self.last_action = Action::Cancel;
                         }
                     });
@@ -67,5 +73,12 @@ impl OkDialog {
         self.ok_text = ok.to_string();
         self.cancel_text = cancel.to_string();
         self.header_text = header.to_string();
+        self.message_text = String::new();
+    }
+
+    /// Message with a single "OK" button; any button just closes it.
+    pub fn show_message(&mut self, header: &str, message: &str) {
+        self.open_dialog("OK", "", header);
+        self.message_text = message.to_string();
     }
 }
\ No newline at end of file
@@ -1,23 +1,65 @@
 ﻿use eframe::epaint::StrokeKind;
 use egui::{Color32, Image};
 use crate::classes::c_dithered_image::DitheredImage;
-use crate::classes::c_rgb16::Rgb16;
+use retroimager_core::{PaletteSort, Rgb16};
 use crate::classes::t_widget::UIWidget;
-use crate::image_utils::u16_to_u8;
+use retroimager_core::image_utils::u16_to_u8;
+use retroimager_core::palette_utils::MAX_PALETTE_COLORS;
+
+/// Smallest palette that entries can still be deleted from.
+const MIN_PALETTE_SIZE: usize = 2;
 
-#[derive(Default)]
 pub struct PaletteMenu{
     last_palette: Vec<Rgb16>,
+    // per entry: kept by re-quantization
+    locked: Vec<bool>,
+    // per entry: pixels that use it
+    usage: Vec<usize>,
     is_enabled: bool,
+    // color picked in the "+" popup
+    new_color: Color32,
+    merge_delta_e: f32,
+    // "Shift" menu: hue in degrees, saturation and brightness in percent
+    shift: [f32; 3],
     pub selected: Option<usize>,
+    /// Entry under the mouse this frame.
+    pub hovered: Option<usize>,
     pub last_command: PaletteMenuCommand
 }
 
+impl Default for PaletteMenu {
+    fn default() -> Self {
+        Self {
+            last_palette: vec![],
+            locked: vec![],
+            usage: vec![],
+            is_enabled: false,
+            new_color: Color32::from_gray(128),
+            merge_delta_e: 5.0,
+            shift: [0.0; 3],
+            selected: None,
+            hovered: None,
+            last_command: PaletteMenuCommand::None,
+        }
+    }
+}
+
 #[derive(Default)]
 pub enum PaletteMenuCommand{
     #[default]
     None,
-    OpenPaletteWindow
+    OpenPaletteWindow,
+    ToggleLock(usize),
+    AddColor(Rgb16),
+    DeleteColor(usize),
+    /// Entry `from` dragged onto the slot of entry `to`.
+    MoveColor { from: usize, to: usize },
+    Sort(PaletteSort),
+    /// Merge entries closer than this ΔE.
+    Merge(f32),
+    /// Recolor every entry: hue rotated by `hue` degrees, saturation and brightness
+    /// scaled by `1 + saturation` and `1 + brightness` (-1..=1).
+    Shift { hue: f32, saturation: f32, brightness: f32 }
 }
 
 impl PaletteMenu {
@@ -27,9 +69,11 @@ impl PaletteMenu {
         self.last_command = PaletteMenuCommand::None;
     }
 
-    pub fn update_palette(&mut self, ctx: &egui::Context, dithered_image: &DitheredImage, is_enabled: bool) {
+    pub fn update_palette(&mut self, ctx: &egui::Context, dithered_image: &DitheredImage, locked_colors: &[Rgb16], is_enabled: bool) {
 
         self.last_palette = dithered_image.get_palette_colors().clone();
+        self.locked = self.last_palette.iter().map(|c| locked_colors.contains(c)).collect();
+        self.usage = dithered_image.palette_usage().to_vec();
         self.is_enabled = is_enabled;
 
         self.update(ctx);
@@ -38,6 +82,9 @@ impl PaletteMenu {
 
 impl UIWidget for PaletteMenu {
     fn update(&mut self, ctx: &egui::Context) {
+        self.hovered = None;
+        let total: usize = self.usage.iter().sum();
+
         egui::TopBottomPanel::bottom("PaletteMenu").show(ctx, |ui| {
             let swatch = egui::vec2(18.0, 18.0); // rect
             let rounding = egui::Rounding::same(1.0 as u8);
@@ -47,7 +94,12 @@ impl UIWidget for PaletteMenu {
 
                 for (i, &c) in self.last_palette.iter().enumerate() {
 
-                    let (rect, resp) = ui.allocate_exact_size(swatch, egui::Sense::click());
+                    let sense = if (self.is_enabled) { egui::Sense::click_and_drag() } else { egui::Sense::click() };
+                    let (rect, resp) = ui.allocate_exact_size(swatch, sense);
+                    let is_locked = self.locked.get(i).copied().unwrap_or(false);
+                    if (resp.hovered()) {
+                        self.hovered = Some(i);
+                    }
 
 
                     let mut strokeColor = egui::Color32::from_gray(80);
@@ -66,6 +118,29 @@ impl UIWidget for PaletteMenu {
                             
                             self.last_command = PaletteMenuCommand::OpenPaletteWindow;
                         }
+                        resp.context_menu(|ui| {
+                            let label = if (is_locked) { "Unlock color" } else { "Lock color" };
+                            if (ui.button(label).clicked()) {
+                                self.last_command = PaletteMenuCommand::ToggleLock(i);
+                                ui.close();
+                            }
+                            if (ui.add_enabled(self.last_palette.len() > MIN_PALETTE_SIZE, egui::Button::new("Delete color")).clicked()) {
+                                self.last_command = PaletteMenuCommand::DeleteColor(i);
+                                ui.close();
+                            }
+                        });
+
+                        resp.dnd_set_drag_payload(i);
+                        if let Some(from) = resp.dnd_hover_payload::<usize>()
+                            && *from != i {
+                            // where the dragged entry lands: before this one when coming from the right
+                            let x = if (*from > i) { rect.left() - 3.0 } else { rect.right() + 3.0 };
+                            ui.painter().vline(x, rect.y_range(), egui::Stroke::new(2.0, Color32::from_gray(230)));
+                        }
+                        if let Some(from) = resp.dnd_release_payload::<usize>()
+                            && *from != i {
+                            self.last_command = PaletteMenuCommand::MoveColor { from: *from, to: i };
+                        }
                         if (self.selected.is_some()) {
                             if (i == self.selected.unwrap()) {
                                 strokeColor = egui::Color32::from_rgb(230, 230, 230);
@@ -83,6 +158,63 @@ impl UIWidget for PaletteMenu {
                         egui::Stroke::new(width, strokeColor),
                         StrokeKind::Inside
                     );
+
+                    if (is_locked) {
+                        // small corner tab: this entry survives re-quantization
+                        let tab = egui::Rect::from_min_size(rect.right_top() - egui::vec2(7.0, 0.0), egui::vec2(7.0, 7.0));
+                        ui.painter().rect_filled(tab, 0.0, Color32::from_gray(20));
+                        ui.painter().rect_stroke(tab, 0.0, egui::Stroke::new(1.0, Color32::from_gray(230)), StrokeKind::Inside);
+                    }
+                    let (r, g, b) = (u16_to_u8(c.r), u16_to_u8(c.g), u16_to_u8(c.b));
+                    let used = self.usage.get(i).copied().unwrap_or(0);
+                    let share = if (total > 0) { used as f64 * 100.0 / total as f64 } else { 0.0 };
+                    let hint = if (is_locked) { "Locked: kept when the palette is rebuilt. Right-click to unlock" } else { "Click to change, drag to move, right-click to lock or delete" };
+                    resp.on_hover_text(format!("{} · #{r:02X}{g:02X}{b:02X}\n{used} px ({share:.2}%)\n{hint}", i + 1));
+                }
+
+                if (self.is_enabled && !self.last_palette.is_empty()) {
+                    ui.separator();
+                    ui.add_enabled_ui(self.last_palette.len() < MAX_PALETTE_COLORS, |ui| ui.menu_button("+", |ui| {
+                        egui::color_picker::color_picker_color32(ui, &mut self.new_color, egui::color_picker::Alpha::Opaque);
+                        if (ui.button("Add color").clicked()) {
+                            let [r, g, b, _] = self.new_color.to_array();
+                            self.last_command = PaletteMenuCommand::AddColor(Rgb16 { r: r as u16 * 257, g: g as u16 * 257, b: b as u16 * 257 });
+                            ui.close();
+                        }
+                    }));
+                    ui.menu_button("Sort", |ui| {
+                        for sort in PaletteSort::ALL {
+                            if (ui.button(sort.label()).clicked()) {
+                                self.last_command = PaletteMenuCommand::Sort(sort);
+                                ui.close();
+                            }
+                        }
+                    });
+                    ui.menu_button("Merge", |ui| {
+                        ui.add(egui::Slider::new(&mut self.merge_delta_e, 1.0..=20.0).text("ΔE"));
+                        ui.label("Entries closer than this join the more used one");
+                        if (ui.button("Merge similar colors").clicked()) {
+                            self.last_command = PaletteMenuCommand::Merge(self.merge_delta_e);
+                            ui.close();
+                        }
+                    });
+                    ui.menu_button("Shift", |ui| {
+                        ui.add(egui::Slider::new(&mut self.shift[0], -180.0..=180.0).text("Hue °"));
+                        ui.add(egui::Slider::new(&mut self.shift[1], -100.0..=100.0).text("Saturation %"));
+                        ui.add(egui::Slider::new(&mut self.shift[2], -100.0..=100.0).text("Brightness %"));
+                        ui.label("Recolors every entry, pixels keep their entry");
+                        ui.horizontal(|ui| {
+                            if (ui.add_enabled(self.shift != [0.0; 3], egui::Button::new("Shift all colors")).clicked()) {
+                                let [hue, saturation, brightness] = self.shift;
+                                self.last_command = PaletteMenuCommand::Shift { hue, saturation: saturation / 100.0, brightness: brightness / 100.0 };
+                                self.shift = [0.0; 3];
+                                ui.close();
+                            }
+                            if (ui.button("Reset").clicked()) {
+                                self.shift = [0.0; 3];
+                            }
+                        });
+                    });
                 }
             });
         });
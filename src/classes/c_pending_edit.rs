@@ -0,0 +1,9 @@
+﻿use crate::classes::c_config::Config;
+use crate::classes::c_image_state::ImageState;
+
+/// History entry waiting for its background job; it is recorded once the result is shown,
+/// since only then the new image state is known. Holds the state before the change.
+pub enum PendingEdit {
+    LoadImage { from: ImageState },
+    Settings { label: Option<String>, config: Box<Config>, image: ImageState },
+}
@@ -1,6 +0,0 @@
-﻿#[derive(Clone, Copy, Debug, Default)]
-pub struct Rgb16 {
-    pub r: u16,
-    pub g: u16,
-    pub b: u16,
-}
\ No newline at end of file
@@ -1,6 +1,9 @@
 ﻿use eframe::egui;
+use crate::classes::c_config::Config;
 use crate::classes::c_dithered_image::DitheredImage;
+use crate::classes::c_history::History;
 use crate::classes::t_widget::UIWidget;
+use retroimager_core::PaletteSource;
 
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -9,7 +12,20 @@ pub enum TopPanelCommands{
     OpenFile,
     SaveFile,
     Exit,
-    OpenConfig
+    OpenConfig,
+    OpenBatch,
+    LoadPreset,
+    SavePreset,
+    OpenProject,
+    SaveProject,
+    SaveProjectEmbedded,
+    Undo,
+    Redo,
+    ToggleHistory,
+    ImportPalette,
+    PaletteFromImage,
+    ExportPalette,
+    UseExtractedPalette
 }
 
 #[derive(Default)]
@@ -17,6 +33,9 @@ pub struct TopMenu{
     command: TopPanelCommands,
     is_image_loaded: bool,
     is_enabled: bool,
+    can_undo: bool,
+    can_redo: bool,
+    has_fixed_palette: bool,
 }
 
 impl TopMenu {
@@ -36,8 +55,11 @@ impl Default for TopPanelCommands {
 }
 
 impl TopMenu {
-    pub(crate) fn update_menu(&mut self, ctx: &egui::Context, dithered_image: &DitheredImage, enabled:bool) {
+    pub(crate) fn update_menu(&mut self, ctx: &egui::Context, dithered_image: &DitheredImage, history: &History, config: &Config, enabled:bool) {
         self.is_image_loaded = dithered_image.has_image();
+        self.can_undo = history.can_undo();
+        self.can_redo = history.can_redo();
+        self.has_fixed_palette = !matches!(config.palette_source, PaletteSource::Extract);
         self.is_enabled = enabled;
 
         self.update(ctx);
@@ -61,12 +83,43 @@ impl UIWidget for TopMenu {
                             if (ui.button("Save File...").clicked()) { self.command = TopPanelCommands::SaveFile }
                         });
 
+                        ui.separator();
+                        if (ui.button("Open Project...").clicked()) { self.command = TopPanelCommands::OpenProject }
+
+                        ui.add_enabled_ui(self.is_image_loaded && self.is_enabled, |ui| {
+                            if (ui.button("Save Project...").clicked()) { self.command = TopPanelCommands::SaveProject }
+                            if (ui.button("Save Project (embed image)...").clicked()) { self.command = TopPanelCommands::SaveProjectEmbedded }
+                        });
+                        ui.separator();
+
+                        if (ui.button("Batch Convert...").clicked()) { self.command = TopPanelCommands::OpenBatch }
+
                         if (ui.button("Exit").clicked()) { self.command = TopPanelCommands::Exit }
                     });
 
+                    ui.menu_button("     Edit     ", |ui| {
+                        if (ui.add_enabled(self.can_undo, egui::Button::new("Undo").shortcut_text("Ctrl+Z")).clicked()) { self.command = TopPanelCommands::Undo }
+                        if (ui.add_enabled(self.can_redo, egui::Button::new("Redo").shortcut_text("Ctrl+Shift+Z")).clicked()) { self.command = TopPanelCommands::Redo }
+                        ui.separator();
+                        if (ui.button("History").clicked()) { self.command = TopPanelCommands::ToggleHistory }
+                    });
+
                     if (ui.button("   Config   ").clicked()){
                         self.command = TopPanelCommands::OpenConfig
                     }
+
+                    ui.menu_button("   Palette   ", |ui| {
+                        if (ui.button("Import Palette...").clicked()) { self.command = TopPanelCommands::ImportPalette }
+                        if (ui.button("Load Palette from Image...").clicked()) { self.command = TopPanelCommands::PaletteFromImage }
+                        if (ui.add_enabled(self.is_image_loaded, egui::Button::new("Export Palette...")).clicked()) { self.command = TopPanelCommands::ExportPalette }
+                        ui.separator();
+                        if (ui.add_enabled(self.has_fixed_palette, egui::Button::new("Use Extracted Palette")).clicked()) { self.command = TopPanelCommands::UseExtractedPalette }
+                    });
+
+                    ui.menu_button("   Presets   ", |ui| {
+                        if (ui.button("Load Preset...").clicked()) { self.command = TopPanelCommands::LoadPreset }
+                        if (ui.button("Save Preset...").clicked()) { self.command = TopPanelCommands::SavePreset }
+                    });
                 });
             });
     }
@@ -0,0 +1,120 @@
+﻿use eframe::egui;
+
+/// Zoom steps in screen pixels per image pixel: integers when magnifying, 1/n when shrinking.
+pub const ZOOM_STEPS: [f32; 17] = [
+    1.0 / 8.0, 1.0 / 6.0, 1.0 / 4.0, 1.0 / 3.0, 1.0 / 2.0,
+    1.0, 2.0, 3.0, 4.0, 6.0, 8.0, 12.0, 16.0, 24.0, 32.0, 48.0, 64.0,
+];
+
+/// The pixel grid shows up from this zoom on.
+pub const GRID_MIN_ZOOM: f32 = 8.0;
+
+/// Zoom and pan of the image view. Without a zoom the image is fitted into the view.
+#[derive(Debug, Clone, Default)]
+pub struct Viewport {
+    /// Screen pixels per image pixel; None = fit.
+    pub zoom: Option<f32>,
+    /// Image center relative to the view center, in screen pixels.
+    pub offset: egui::Vec2,
+    pub show_grid: bool,
+}
+
+impl Viewport {
+    pub fn fit(&mut self) {
+        self.zoom = None;
+        self.offset = egui::Vec2::ZERO;
+    }
+
+    pub fn actual_size(&mut self) {
+        self.zoom = Some(1.0);
+        self.offset = egui::Vec2::ZERO;
+    }
+
+    /// Screen pixels per image pixel for an image of `w`×`h` in `view`.
+    pub fn scale(&self, view: egui::Rect, w: usize, h: usize) -> f32 {
+        match self.zoom {
+            Some(z) => z,
+            None => (view.width() / w.max(1) as f32).min(view.height() / h.max(1) as f32),
+        }
+    }
+
+    pub fn image_rect(&self, view: egui::Rect, w: usize, h: usize) -> egui::Rect {
+        let s = self.scale(view, w, h);
+        let size = egui::vec2(w as f32 * s, h as f32 * s);
+        let min = view.center() + self.offset - size * 0.5;
+        // whole screen pixels keep every image pixel the same size
+        let min = if (s >= 1.0) { min.round() } else { min };
+        egui::Rect::from_min_size(min, size)
+    }
+
+    /// Goes `steps` zoom steps in (positive) or out, keeping the image point under `anchor` in place.
+    pub fn zoom_by(&mut self, steps: i32, anchor: egui::Pos2, view: egui::Rect, w: usize, h: usize) {
+        let current = self.scale(view, w, h);
+        let index = if (steps > 0) {
+            ZOOM_STEPS.iter().position(|&z| z > current * 1.001).unwrap_or(ZOOM_STEPS.len() - 1)
+        } else {
+            ZOOM_STEPS.iter().rposition(|&z| z < current * 0.999).unwrap_or(0)
+        };
+        let index = (index as i32 + steps - steps.signum()).clamp(0, ZOOM_STEPS.len() as i32 - 1) as usize;
+        let zoom = ZOOM_STEPS[index];
+
+        // the anchor's distance to the image center scales with the zoom
+        let center = view.center() + self.offset;
+        let new_center = anchor + (center - anchor) * (zoom / current);
+        self.offset = new_center - view.center();
+        self.zoom = Some(zoom);
+    }
+
+    /// Moves the image by `delta`; a fitted image keeps its current zoom from then on.
+    pub fn pan(&mut self, delta: egui::Vec2, view: egui::Rect, w: usize, h: usize) {
+        if (self.zoom.is_none()) {
+            self.zoom = Some(self.scale(view, w, h));
+        }
+        self.offset += delta;
+    }
+
+    /// Image pixel under `pos`, if it is on the image.
+    pub fn pixel_at(&self, pos: egui::Pos2, view: egui::Rect, w: usize, h: usize) -> Option<(usize, usize)> {
+        let rect = self.image_rect(view, w, h);
+        if (!rect.contains(pos)) {
+            return None;
+        }
+        let s = self.scale(view, w, h);
+        let x = ((pos.x - rect.min.x) / s) as usize;
+        let y = ((pos.y - rect.min.y) / s) as usize;
+        if (x < w && y < h) { Some((x, y)) } else { None }
+    }
+
+    /// Lines between image pixels, only over the visible part of the image.
+    pub fn paint_grid(&self, painter: &egui::Painter, view: egui::Rect, w: usize, h: usize) {
+        let s = self.scale(view, w, h);
+        if (!self.show_grid || s < GRID_MIN_ZOOM) {
+            return;
+        }
+        let rect = self.image_rect(view, w, h);
+        let visible = rect.intersect(view);
+        if (!visible.is_positive()) {
+            return;
+        }
+
+        let stroke = egui::Stroke::new(1.0, egui::Color32::from_black_alpha(90));
+        let x0 = ((visible.min.x - rect.min.x) / s).floor() as usize;
+        let x1 = (((visible.max.x - rect.min.x) / s).ceil() as usize).min(w);
+        for x in x0..=x1 {
+            let sx = rect.min.x + x as f32 * s;
+            painter.vline(sx, visible.y_range(), stroke);
+        }
+        let y0 = ((visible.min.y - rect.min.y) / s).floor() as usize;
+        let y1 = (((visible.max.y - rect.min.y) / s).ceil() as usize).min(h);
+        for y in y0..=y1 {
+            let sy = rect.min.y + y as f32 * s;
+            painter.hline(visible.x_range(), sy, stroke);
+        }
+    }
+
+    pub fn zoom_label(&self, view: egui::Rect, w: usize, h: usize) -> String {
+        let s = self.scale(view, w, h);
+        let percent = format!("{:.0}%", s * 100.0);
+        if (self.zoom.is_none()) { format!("Fit ({percent})") } else { percent }
+    }
+}
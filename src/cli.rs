@@ -0,0 +1,369 @@
+﻿use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use crate::classes::c_config::Config;
+use retroimager_core::batch_utils::{check_output_is_not_input, collect_inputs};
+use retroimager_core::classes::c_folder_watcher::{clock_time, DEFAULT_POLL_INTERVAL_MS};
+use retroimager_core::classes::c_project::{project_dir, PROJECT_EXTENSION};
+use retroimager_core::export_utils::{is_supported_output, write_by_extension};
+use retroimager_core::palette_utils::read_palette_file;
+use retroimager_core::pipeline_utils::{palette_from_image, process_file};
+use retroimager_core::{BatchJob, BatchStatus, ColorMetric, DitherAlgorithm, ExportSettings, FolderWatcher, Palette, PaletteSource, Preset, ProcessedImage, Project, QuantizerKind, ResizeFilter};
+
+pub const EXIT_OK: i32 = 0;
+/// Bad arguments.
+pub const EXIT_USAGE: i32 = 2;
+/// Input image or palette file can't be read.
+pub const EXIT_INPUT: i32 = 3;
+/// Output can't be written.
+pub const EXIT_OUTPUT: i32 = 4;
+
+const USAGE: &str = "\
+Usage:
+  DITHERING                                 start the GUI
+  DITHERING convert <input> <output> [options]
+  DITHERING batch <folder|glob> <output folder> [options]
+  DITHERING watch <folder|glob> <output folder> [options]
+  DITHERING help
+
+Output format is picked by extension: .png (indexed), .gif, .iff/.ilbm/.lbm
+A .dsproj input renders the saved project with its own settings and palette.
+Batch input is a folder of images or a wildcard like \"sprites/*.png\".
+
+Options:
+      --preset <FILE>      load all settings from a preset; later options override it
+  -c, --colors <N>         palette size, 2..=256 (default 8)
+  -s, --scale <F>          scale factor before dithering, e.g. 0.5 (default 1.0)
+      --width <N>          target width in pixels (height follows unless given)
+      --height <N>         target height in pixels (width follows unless given)
+      --filter <NAME>      nearest | bilinear | catmull-rom | lanczos3 (default nearest)
+  -q, --quantizer <NAME>   median-cut | kmeans (default median-cut)
+  -d, --dither <NAME>      fs | atkinson | bayer8 | none (default fs)
+      --strength <F>       dither strength 0..1 (default 1)
+      --metric <NAME>      rgb | redmean | luma color distance (default rgb)
+  -p, --palette <FILE>     fixed palette (.hex .gpl .pal .act .ase .txt or
+                           PNG strip), skips the quantizer
+      --palette-from <IMG> fixed palette from a reference image: its exact colors
+                           when it has at most 256, else quantized to --colors
+      --per-frame-palette  separate palette for every frame of an animation
+      --no-temporal        disable temporal coherence for animations
+      --no-sequence        don't load name_0001.png as a numbered sequence
+      --loops <N>          GIF loop count, 0 = forever (default 0)
+      --no-rle             write uncompressed IFF BODY
+
+Batch options:
+  -n, --name <PATTERN>     output file name, {name} {index} {ext} (default {name}.png)
+      --shared-all         build one palette from all images of the batch
+
+Watch options (plus --name):
+      --debounce <MS>      wait until a file is unchanged this long (default 500)
+      --interval <MS>      folder poll interval (default 250)
+
+Exit codes: 0 ok, 2 bad arguments, 3 input error, 4 output error";
+
+pub struct CliError {
+    pub code: i32,
+    pub message: String,
+}
+
+impl CliError {
+    fn usage(message: String) -> Self {
+        Self { code: EXIT_USAGE, message }
+    }
+}
+
+struct ConvertArgs {
+    input: PathBuf,
+    output: PathBuf,
+    palette_file: Option<PathBuf>,
+    palette_image: Option<PathBuf>,
+    config: Config,
+}
+
+struct Options {
+    positional: Vec<String>,
+    palette_file: Option<PathBuf>,
+    palette_image: Option<PathBuf>,
+    config: Config,
+    interval_ms: u64,
+}
+
+/// Entry point for `args` without the program name. Returns the process exit code.
+pub fn run(args: &[String]) -> i32 {
+    let result = match args.first().map(|s| s.as_str()) {
+        Some("convert") => parse_convert(&args[1..]).and_then(|a| convert(&a)),
+        Some("batch") => parse_options(&args[1..], "batch").and_then(|o| batch(&o)),
+        Some("watch") => parse_options(&args[1..], "watch").and_then(|o| watch(&o)),
+        Some("help") | Some("-h") | Some("--help") => {
+            println!("{USAGE}");
+            Ok(())
+        }
+        Some(other) => Err(CliError::usage(format!("unknown command '{other}'"))),
+        None => Err(CliError::usage("missing command".to_string())),
+    };
+
+    match result {
+        Ok(()) => EXIT_OK,
+        Err(e) => {
+            eprintln!("error: {}", e.message);
+            if (e.code == EXIT_USAGE) {
+                eprintln!();
+                eprintln!("{USAGE}");
+            }
+            e.code
+        }
+    }
+}
+
+fn value<'a>(args: &'a [String], i: &mut usize, flag: &str) -> Result<&'a str, CliError> {
+    *i += 1;
+    args.get(*i)
+        .map(|s| s.as_str())
+        .ok_or_else(|| CliError::usage(format!("{flag} needs a value")))
+}
+
+fn number<T: std::str::FromStr>(s: &str, flag: &str) -> Result<T, CliError> {
+    s.parse::<T>()
+        .map_err(|_| CliError::usage(format!("{flag}: '{s}' is not a valid number")))
+}
+
+/// Options shared by all commands plus the ones only `command` understands.
+fn parse_options(args: &[String], command: &str) -> Result<Options, CliError> {
+    let batch = command == "batch";
+    let watch = command == "watch";
+    let mut positional: Vec<String> = vec![];
+    let mut palette_file = None;
+    let mut palette_image = None;
+    let mut interval_ms = DEFAULT_POLL_INTERVAL_MS;
+
+    let mut config = Config::default();
+    config.set_size(1.0);
+
+    let mut i = 0;
+    while i < args.len() {
+        let arg = args[i].as_str();
+        match arg {
+            "--preset" => {
+                let path = PathBuf::from(value(args, &mut i, arg)?);
+                let preset = Preset::load(&path).map_err(|message| CliError { code: EXIT_INPUT, message })?;
+                config.apply_preset(&preset);
+            }
+            "-c" | "--colors" => {
+                let n: u16 = number(value(args, &mut i, arg)?, arg)?;
+                if !(2..=256).contains(&n) {
+                    return Err(CliError::usage(format!("{arg}: palette size must be 2..=256")));
+                }
+                config.set_colors_count(n);
+            }
+            "-s" | "--scale" => {
+                let f: f32 = number(value(args, &mut i, arg)?, arg)?;
+                if !(f > 0.0 && f.is_finite()) {
+                    return Err(CliError::usage(format!("{arg}: scale must be > 0")));
+                }
+                config.set_size(f);
+            }
+            "--width" => config.target_width = number(value(args, &mut i, arg)?, arg)?,
+            "--height" => config.target_height = number(value(args, &mut i, arg)?, arg)?,
+            "--filter" => {
+                let name = value(args, &mut i, arg)?;
+                config.resize_filter = ResizeFilter::from_cli_name(name)
+                    .ok_or_else(|| CliError::usage(format!("{arg}: unknown resize filter '{name}'")))?;
+            }
+            "--strength" => {
+                let f: f32 = number(value(args, &mut i, arg)?, arg)?;
+                if !(0.0..=1.0).contains(&f) {
+                    return Err(CliError::usage(format!("{arg}: strength must be 0..1")));
+                }
+                config.dither_strength = f;
+            }
+            "--metric" => {
+                let name = value(args, &mut i, arg)?;
+                config.color_metric = ColorMetric::from_cli_name(name)
+                    .ok_or_else(|| CliError::usage(format!("{arg}: unknown color metric '{name}'")))?;
+            }
+            "-q" | "--quantizer" => {
+                let name = value(args, &mut i, arg)?;
+                config.quantizer = QuantizerKind::from_cli_name(name)
+                    .ok_or_else(|| CliError::usage(format!("{arg}: unknown quantizer '{name}'")))?;
+            }
+            "-d" | "--dither" => {
+                let name = value(args, &mut i, arg)?;
+                config.dither_algorithm = DitherAlgorithm::from_cli_name(name)
+                    .ok_or_else(|| CliError::usage(format!("{arg}: unknown dither algorithm '{name}'")))?;
+            }
+            "-p" | "--palette" => palette_file = Some(PathBuf::from(value(args, &mut i, arg)?)),
+            "--palette-from" => palette_image = Some(PathBuf::from(value(args, &mut i, arg)?)),
+            "--per-frame-palette" => config.shared_palette = false,
+            "--no-temporal" => config.temporal_coherence = false,
+            "--no-sequence" => config.load_sequences = false,
+            "--loops" => config.gif_loop_count = number(value(args, &mut i, arg)?, arg)?,
+            "--no-rle" => config.ilbm_compression = false,
+            "-n" | "--name" if batch || watch => config.batch_name_pattern = value(args, &mut i, arg)?.to_string(),
+            "--shared-all" if batch => config.batch_shared_palette = true,
+            "--debounce" if watch => config.watch_debounce_ms = number(value(args, &mut i, arg)?, arg)?,
+            "--interval" if watch => interval_ms = number::<u64>(value(args, &mut i, arg)?, arg)?.max(10),
+            _ if arg.starts_with('-') && arg.len() > 1 => {
+                return Err(CliError::usage(format!("unknown option '{arg}'")));
+            }
+            _ => positional.push(arg.to_string()),
+        }
+        i += 1;
+    }
+
+    if (palette_file.is_some() && palette_image.is_some()) {
+        return Err(CliError::usage("use either --palette or --palette-from".to_string()));
+    }
+
+    Ok(Options { positional, palette_file, palette_image, config, interval_ms })
+}
+
+fn parse_convert(args: &[String]) -> Result<ConvertArgs, CliError> {
+    let Options { mut positional, palette_file, palette_image, config, .. } = parse_options(args, "convert")?;
+
+    if positional.len() != 2 {
+        return Err(CliError::usage("convert needs <input> and <output>".to_string()));
+    }
+    let output = PathBuf::from(positional.pop().unwrap());
+    let input = PathBuf::from(positional.pop().unwrap());
+
+    if !is_supported_output(&output) {
+        return Err(CliError::usage(format!("{}: unsupported output format", output.display())));
+    }
+
+    Ok(ConvertArgs { input, output, palette_file, palette_image, config })
+}
+
+fn read_fixed_palette(palette_file: &Option<PathBuf>, palette_image: &Option<PathBuf>, config: &Config) -> Result<Option<Palette>, CliError> {
+    let input_error = |message: String| CliError { code: EXIT_INPUT, message };
+    match (palette_file, palette_image) {
+        (Some(p), _) => read_palette_file(p).map(Some).map_err(input_error),
+        (None, Some(p)) => palette_from_image(p, &config.pipeline())
+            .map(Some)
+            .map_err(|e| input_error(format!("{}: {e}", p.display()))),
+        (None, None) => Ok(None),
+    }
+}
+
+fn convert(args: &ConvertArgs) -> Result<(), CliError> {
+    let input_error = |message: String| CliError { code: EXIT_INPUT, message };
+    check_output_is_not_input(&args.input, &args.output).map_err(input_error)?;
+
+    let is_project = args.input.extension().is_some_and(|e| e.eq_ignore_ascii_case(PROJECT_EXTENSION));
+    let (processed, export) = if is_project {
+        render_project(&args.input).map_err(input_error)?
+    } else {
+        let fixed = read_fixed_palette(&args.palette_file, &args.palette_image, &args.config)?;
+        let processed = process_file(&args.input, &args.config.pipeline(), fixed)
+            .map_err(|e| input_error(format!("{}: {e}", args.input.display())))?;
+        (processed, args.config.export())
+    };
+
+    let (indexed, delays) = processed.to_indexed();
+    write_by_extension(&args.output, &indexed, &delays, 0, &export).map_err(|e| CliError {
+        code: EXIT_OUTPUT,
+        message: format!("{}: {e}", args.output.display()),
+    })?;
+
+    println!(
+        "{} -> {} ({}x{}, {} frame(s), {} colors)",
+        args.input.display(),
+        args.output.display(),
+        processed.w,
+        processed.h,
+        processed.frames.len(),
+        processed.palettes[0].len()
+    );
+    Ok(())
+}
+
+/// A saved session with its own settings and edited palettes; command line options don't apply.
+fn render_project(path: &Path) -> Result<(ProcessedImage, ExportSettings), String> {
+    let project = Project::load(path)?;
+    let processed = project.source
+        .decode(&project_dir(path), project.pipeline.load_sequences)
+        .and_then(|decoded| project.render(decoded))
+        .map_err(|e| format!("{}: {e}", path.display()))?;
+    Ok((processed, project.export))
+}
+
+/// `<folder|glob> <output folder>` of `batch` and `watch`, with the name pattern checked.
+fn folders<'a>(options: &'a Options, command: &str) -> Result<(&'a str, &'a str), CliError> {
+    let [source, output_dir] = options.positional.as_slice() else {
+        return Err(CliError::usage(format!("{command} needs <folder|glob> and <output folder>")));
+    };
+
+    let pattern = &options.config.batch_name_pattern;
+    if !is_supported_output(Path::new(pattern)) {
+        return Err(CliError::usage(format!("{pattern}: unsupported output format")));
+    }
+    Ok((source, output_dir))
+}
+
+fn batch(options: &Options) -> Result<(), CliError> {
+    let (source, output_dir) = folders(options, "batch")?;
+    let config = &options.config;
+
+    let fixed = read_fixed_palette(&options.palette_file, &options.palette_image, config)?;
+    let inputs = collect_inputs(source).map_err(|message| CliError { code: EXIT_INPUT, message })?;
+
+    let mut job = BatchJob::new(
+        inputs,
+        Path::new(output_dir),
+        &config.batch_name_pattern,
+        &config.pipeline(),
+        &config.export(),
+        fixed,
+        config.batch_shared_palette,
+    );
+
+    job.run(|item| match &item.status {
+        BatchStatus::Done => println!("ok      {} -> {}", item.input.display(), item.output.display()),
+        BatchStatus::Failed(e) => println!("failed  {}: {e}", item.input.display()),
+        _ => {}
+    });
+
+    let failed = job.failed().count();
+    println!("{} of {} file(s) converted", job.items.len() - failed, job.items.len());
+
+    if failed > 0 {
+        return Err(CliError { code: EXIT_INPUT, message: format!("{failed} file(s) failed") });
+    }
+    Ok(())
+}
+
+/// Converts files of `source` as they change, until the process is killed.
+fn watch(options: &Options) -> Result<(), CliError> {
+    let (source, output_dir) = folders(options, "watch")?;
+    let config = &options.config;
+
+    let mut pipeline = config.pipeline();
+    if let Some(colors) = read_fixed_palette(&options.palette_file, &options.palette_image, config)? {
+        pipeline.palette = PaletteSource::Fixed { colors };
+    }
+
+    let mut watcher = FolderWatcher::new(
+        source,
+        Path::new(output_dir),
+        &config.batch_name_pattern,
+        &pipeline,
+        &config.export(),
+        Duration::from_millis(config.watch_debounce_ms),
+    )
+    .map_err(|message| CliError { code: EXIT_INPUT, message })?;
+
+    println!("watching {source} -> {output_dir} (Ctrl+C to stop)");
+    loop {
+        match watcher.poll() {
+            Ok(items) => {
+                for item in items {
+                    let time = clock_time(SystemTime::now());
+                    match &item.status {
+                        BatchStatus::Failed(e) => println!("[{time}] failed  {}: {e}", item.input.display()),
+                        _ => println!("[{time}] ok      {} -> {}", item.input.display(), item.output.display()),
+                    }
+                }
+            }
+            Err(e) => eprintln!("[{}] {e}", clock_time(SystemTime::now())),
+        }
+        std::thread::sleep(Duration::from_millis(options.interval_ms));
+    }
+}
@@ -0,0 +1,21 @@
+﻿use retroimager_core::Rgb16;
+
+pub fn rgb16_to_color32(c: Rgb16) -> egui::Color32 {
+    egui::Color32::from_rgb((c.r >> 8) as u8, (c.g >> 8) as u8, (c.b >> 8) as u8)
+}
+
+
+pub fn set_texture(
+    tex: &mut Option<egui::TextureHandle>,
+    ctx: &egui::Context,
+    name: &str,
+    w: usize,
+    h: usize,
+    rgba8: &[u8],
+) {
+    let img = egui::ColorImage::from_rgba_unmultiplied([w, h], rgba8);
+    match tex {
+        Some(t) => t.set(img, egui::TextureOptions::NEAREST),
+        None => *tex = Some(ctx.load_texture(name, img, egui::TextureOptions::NEAREST)),
+    }
+}
@@ -1,11 +1,67 @@
 ﻿use std::env;
-use std::path::PathBuf;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
 
+/// Folder name inside the platform config directory.
+pub const APP_DIR_NAME: &str = "dithering-slave";
 
 pub fn get_app_dir() -> PathBuf{
-    return env::current_dir().unwrap();
+    env::current_dir().unwrap_or_else(|_| PathBuf::from("."))
 }
 
 pub fn is_file_exist(fileName: &str) -> bool {
     return get_app_dir().join(fileName).is_file();
 }
+
+/// Where `file_name` lives: the working directory when the file already exists there
+/// (portable setups), otherwise the platform config directory ($XDG_CONFIG_HOME on Linux).
+pub fn get_config_path(file_name: &str) -> PathBuf {
+    if (is_file_exist(file_name)) {
+        return get_app_dir().join(file_name);
+    }
+    match dirs::config_dir() {
+        Some(dir) => dir.join(APP_DIR_NAME).join(file_name),
+        None => get_app_dir().join(file_name),
+    }
+}
+
+/// Writes into a temporary file next to `path` and renames it over `path`,
+/// so a crash mid-write never leaves a half written file behind.
+pub fn write_atomic(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+
+    let result = (|| {
+        let mut file = fs::File::create(&tmp)?;
+        file.write_all(bytes)?;
+        file.sync_all()?;
+        fs::rename(&tmp, path)
+    })();
+
+    if (result.is_err()) {
+        let _ = fs::remove_file(&tmp);
+    }
+    result
+}
+
+/// Moves a broken file aside as `<name>.bak-<unix time>` and returns the new path.
+pub fn backup_file(path: &Path) -> io::Result<PathBuf> {
+    let stamp = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".bak-{stamp}"));
+    let backup = PathBuf::from(name);
+
+    fs::rename(path, &backup)?;
+    Ok(backup)
+}
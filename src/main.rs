@@ -1,6 +1,7 @@
 mod classes;
-mod image_utils;
+mod egui_utils;
 mod file_system_utils;
+mod cli;
 
 use std::sync::Arc;
 use crate::classes::c_app::App;
@@ -22,6 +23,12 @@ fn load_icon() -> Arc<egui::IconData> {
 
 
 fn main() {
+    // any argument -> headless command line mode
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if !args.is_empty() {
+        std::process::exit(cli::run(&args));
+    }
+
     let native_options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default()
             .with_icon(load_icon()),